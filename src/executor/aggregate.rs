@@ -0,0 +1,206 @@
+use super::super::sql_analyzer::types::*;
+use super::expr::arithmetic;
+use super::types::QueryExecutionError;
+use rust_decimal::Decimal;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+impl Expr {
+    /// the operands of the expression, an aggregate call is not looked into
+    fn operands(&self) -> Vec<&Expr> {
+        match self {
+            Expr::Add(left, right)
+            | Expr::Sub(left, right)
+            | Expr::Mul(left, right)
+            | Expr::Div(left, right) => vec![left, right],
+            Expr::Extract(_, expr) => vec![expr],
+            _ => vec![],
+        }
+    }
+
+    /// whether the expression has an aggregate call
+    pub fn has_aggregate(&self) -> bool {
+        matches!(self, Expr::Aggregate(_))
+            || self.operands().iter().any(|expr| expr.has_aggregate())
+    }
+
+    /// add the aggregate calls of the expression to `aggregates`, each call is added once
+    pub fn collect_aggregates(&self, aggregates: &mut Vec<Aggregate>) {
+        match self {
+            Expr::Aggregate(aggregate) if !aggregates.contains(aggregate) => {
+                aggregates.push(aggregate.clone())
+            }
+            _ => {
+                for expr in self.operands() {
+                    expr.collect_aggregates(aggregates);
+                }
+            }
+        }
+    }
+
+    /// the columns read outside of the aggregate calls
+    pub fn bare_columns(&self) -> Vec<&str> {
+        match self {
+            Expr::Column(name) => vec![name],
+            _ => self
+                .operands()
+                .into_iter()
+                .flat_map(|expr| expr.bare_columns())
+                .collect(),
+        }
+    }
+
+    /// the expression with every aggregate call replaced by its value in `values`
+    pub fn replace_aggregates(&self, aggregates: &[Aggregate], values: &[SqlValue]) -> Expr {
+        let replace = |expr: &Expr| Box::new(expr.replace_aggregates(aggregates, values));
+        match self {
+            Expr::Aggregate(aggregate) => match aggregates.iter().position(|a| a == aggregate) {
+                Some(position) => Expr::Value(values[position].clone()),
+                None => self.clone(),
+            },
+            Expr::Add(left, right) => Expr::Add(replace(left), replace(right)),
+            Expr::Sub(left, right) => Expr::Sub(replace(left), replace(right)),
+            Expr::Mul(left, right) => Expr::Mul(replace(left), replace(right)),
+            Expr::Div(left, right) => Expr::Div(replace(left), replace(right)),
+            Expr::Extract(field, expr) => Expr::Extract(*field, replace(expr)),
+            _ => self.clone(),
+        }
+    }
+}
+
+impl WhereConstraint {
+    /// add the aggregate calls compared by the condition to `aggregates`
+    pub fn collect_aggregates(&self, aggregates: &mut Vec<Aggregate>) {
+        match self {
+            WhereConstraint::And(left, right) | WhereConstraint::Or(left, right) => {
+                left.collect_aggregates(aggregates);
+                right.collect_aggregates(aggregates);
+            }
+            WhereConstraint::Not(wc) => wc.collect_aggregates(aggregates),
+            WhereConstraint::Compare(left, _, right) => {
+                left.collect_aggregates(aggregates);
+                right.collect_aggregates(aggregates);
+            }
+            WhereConstraint::Constrait(_, _, _) | WhereConstraint::IsNull(_) => {}
+        }
+    }
+
+    /// the columns read by the condition outside of the aggregate calls
+    pub fn bare_columns(&self) -> Vec<&str> {
+        match self {
+            WhereConstraint::And(left, right) | WhereConstraint::Or(left, right) => {
+                let mut columns = left.bare_columns();
+                columns.extend(right.bare_columns());
+                columns
+            }
+            WhereConstraint::Not(wc) => wc.bare_columns(),
+            WhereConstraint::Compare(left, _, right) => {
+                let mut columns = left.bare_columns();
+                columns.extend(right.bare_columns());
+                columns
+            }
+            WhereConstraint::Constrait(name, _, _) | WhereConstraint::IsNull(name) => vec![name],
+        }
+    }
+
+    /// the condition with every aggregate call replaced by its value in `values`
+    pub fn replace_aggregates(&self, aggregates: &[Aggregate], values: &[SqlValue]) -> Self {
+        let replace = |wc: &WhereConstraint| Box::new(wc.replace_aggregates(aggregates, values));
+        match self {
+            WhereConstraint::And(left, right) => {
+                WhereConstraint::And(replace(left), replace(right))
+            }
+            WhereConstraint::Or(left, right) => WhereConstraint::Or(replace(left), replace(right)),
+            WhereConstraint::Not(wc) => WhereConstraint::Not(replace(wc)),
+            WhereConstraint::Compare(left, cmp_opt, right) => WhereConstraint::Compare(
+                left.replace_aggregates(aggregates, values),
+                cmp_opt.clone(),
+                right.replace_aggregates(aggregates, values),
+            ),
+            _ => self.clone(),
+        }
+    }
+}
+
+/// The running value of an aggregate over the rows seen so far
+pub struct Accumulator {
+    aggregate: Aggregate,
+    /// the values seen by a DISTINCT aggregate
+    seen: HashSet<SqlValue>,
+    /// number of values aggregated, or of rows for COUNT(*)
+    count: i64,
+    /// the sum for SUM and AVG, the smallest or the largest value for MIN and MAX
+    value: SqlValue,
+}
+
+impl Accumulator {
+    pub fn new(aggregate: &Aggregate) -> Self {
+        Self {
+            aggregate: aggregate.clone(),
+            seen: HashSet::new(),
+            count: 0,
+            value: SqlValue::Null,
+        }
+    }
+
+    /// aggregate a row of a table with the columns `names`
+    pub fn update(&mut self, row: &RowValue, names: &[String]) -> Result<(), QueryExecutionError> {
+        let value = match &self.aggregate.arg {
+            Some(arg) => arg.eval(row, names)?,
+            None => {
+                self.count += 1;
+                return Ok(());
+            }
+        };
+        // NULL is left out of every aggregate
+        if matches!(value, SqlValue::Null)
+            || (self.aggregate.distinct && !self.seen.insert(value.clone()))
+        {
+            return Ok(());
+        }
+        let mismatch = || QueryExecutionError::TypeDoesNotMatch(format!("{}", self.aggregate));
+        let kept = match (self.aggregate.function, &self.value) {
+            (AggregateFunction::Count, _) => SqlValue::Null,
+            (AggregateFunction::Sum | AggregateFunction::Avg, _) if value.as_f64().is_none() => {
+                return Err(mismatch())
+            }
+            (_, SqlValue::Null) => value,
+            (AggregateFunction::Sum | AggregateFunction::Avg, sum) => {
+                arithmetic(sum.clone(), value, true)?
+            }
+            (function, kept) => {
+                let replaced = match kept.compare(&value).ok_or_else(mismatch)? {
+                    Ordering::Greater => function == AggregateFunction::Min,
+                    Ordering::Less => function == AggregateFunction::Max,
+                    Ordering::Equal => false,
+                };
+                if replaced {
+                    value
+                } else {
+                    kept.clone()
+                }
+            }
+        };
+        self.value = kept;
+        self.count += 1;
+        Ok(())
+    }
+
+    /// the value of the aggregate, NULL when no value was aggregated except for COUNT
+    pub fn finish(&self) -> Result<SqlValue, QueryExecutionError> {
+        match (self.aggregate.function, &self.value) {
+            (AggregateFunction::Count, _) => Ok(SqlValue::BigInt(self.count)),
+            (AggregateFunction::Avg, SqlValue::Null) => Ok(SqlValue::Null),
+            (AggregateFunction::Avg, SqlValue::Double(sum)) => {
+                Ok(SqlValue::Double(sum / self.count as f64))
+            }
+            // the average of integers and decimals is an exact decimal
+            (AggregateFunction::Avg, sum) => sum
+                .as_decimal()
+                .and_then(|sum| sum.checked_div(Decimal::from(self.count)))
+                .map(SqlValue::Decimal)
+                .ok_or_else(|| QueryExecutionError::ValueOutOfRange(self.aggregate.to_string())),
+            (_, value) => Ok(value.clone()),
+        }
+    }
+}
@@ -1,160 +1,842 @@
 use super::super::sql_analyzer::types::*;
-use super::super::storage::StoreUtil;
+use super::super::storage::{
+    compare_values, duplicate_key_index, is_locked, is_write_conflict, row_too_large, LockMode,
+    RowId, StorageEngine,
+};
+use super::aggregate::Accumulator;
+use super::sort::{sort_memory, ExternalSort};
 use super::types::*;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::Display;
+use std::io;
+use std::ops::Bound;
 use tabled::settings::style::{HorizontalLine, VerticalLine};
 use tabled::{builder::Builder, settings::Style};
 
-fn compare_sqlvalue(sqlvalue1: &SqlValue, sqlvalue2: &SqlValue, cmp_opt: &CmpOpt) -> bool {
-    //Determine whether the relationship between two values meets the input criteria
-    match sqlvalue1 {
-        SqlValue::Int(value1) => {
-            match sqlvalue2 {
-                //Compare int
-                SqlValue::Int(value2) => match cmp_opt {
-                    CmpOpt::Eq => value1 == value2,
-                    CmpOpt::Ge => value1 >= value2,
-                    CmpOpt::Gt => value1 > value2,
-                    CmpOpt::Le => value1 <= value2,
-                    CmpOpt::Lt => value1 < value2,
-                    CmpOpt::Ne => value1 != value2,
-                },
-                _ => {
-                    return false;
+/// whether the relationship between two values meets the criteria,
+/// None (unknown) if either of them is NULL
+fn compare_sqlvalue(sqlvalue1: &SqlValue, sqlvalue2: &SqlValue, cmp_opt: &CmpOpt) -> Option<bool> {
+    if *sqlvalue1 == SqlValue::Null || *sqlvalue2 == SqlValue::Null {
+        return None;
+    }
+    let temporal_type = |value: &SqlValue| match value {
+        SqlValue::Date(_) => Some(SqlType::Date),
+        SqlValue::Time(_) => Some(SqlType::Time),
+        SqlValue::Timestamp(_) => Some(SqlType::Timestamp),
+        _ => None,
+    };
+    // a date or a time may be compared with its text
+    let ordering = sqlvalue1
+        .compare(sqlvalue2)
+        .or_else(|| match (sqlvalue1, sqlvalue2) {
+            (SqlValue::String(_), other) => {
+                sqlvalue1.coerce(&temporal_type(other)?)?.compare(other)
+            }
+            (other, SqlValue::String(_)) => {
+                other.compare(&sqlvalue2.coerce(&temporal_type(other)?)?)
+            }
+            _ => None,
+        });
+    let ordering = match ordering {
+        Some(ordering) => ordering,
+        None => return Some(false),
+    };
+    Some(match cmp_opt {
+        CmpOpt::Eq => ordering.is_eq(),
+        CmpOpt::Ge => ordering.is_ge(),
+        CmpOpt::Gt => ordering.is_gt(),
+        CmpOpt::Le => ordering.is_le(),
+        CmpOpt::Lt => ordering.is_lt(),
+        CmpOpt::Ne => ordering.is_ne(),
+    })
+}
+
+/// whether the record meets the conditions in three-valued logic,
+/// None (unknown) if it depends on a NULL
+fn compare_condition(
+    wc: &WhereConstraint,
+    record: &RowValue,
+    record_names: &[String],
+) -> Result<Option<bool>, QueryExecutionError> {
+    //Based on the input conditions, judge whether the record meets the conditions
+    let value = |name: &String| {
+        // Find the index of 'name' in 'record_name'
+        record_names
+            .iter()
+            .position(|r_name| r_name == name)
+            .map(|index| record.values.get(index).unwrap_or(&SqlValue::Null))
+    };
+    Ok(match wc {
+        WhereConstraint::Constrait(name, cmp_opt, sql_value) => match value(name) {
+            None => Some(false),
+            // Compare the SqlValue of 'record' with 'sql_value' based on 'cmp_opt'
+            Some(value) => compare_sqlvalue(value, sql_value, cmp_opt),
+        },
+        WhereConstraint::Compare(left, cmp_opt, right) => compare_sqlvalue(
+            &left.eval(record, record_names)?,
+            &right.eval(record, record_names)?,
+            cmp_opt,
+        ),
+        WhereConstraint::IsNull(name) => {
+            Some(value(name).is_some_and(|value| *value == SqlValue::Null))
+        }
+        WhereConstraint::Not(wc_box) => {
+            compare_condition(wc_box, record, record_names)?.map(|result| !result)
+        }
+        // false wins over unknown
+        WhereConstraint::And(left_wc, right_wc) => {
+            match (
+                compare_condition(left_wc, record, record_names)?,
+                compare_condition(right_wc, record, record_names)?,
+            ) {
+                (Some(false), _) | (_, Some(false)) => Some(false),
+                (Some(true), Some(true)) => Some(true),
+                _ => None,
+            }
+        }
+        // true wins over unknown
+        WhereConstraint::Or(left_wc, right_wc) => {
+            match (
+                compare_condition(left_wc, record, record_names)?,
+                compare_condition(right_wc, record, record_names)?,
+            ) {
+                (Some(true), _) | (_, Some(true)) => Some(true),
+                (Some(false), Some(false)) => Some(false),
+                _ => None,
+            }
+        }
+    })
+}
+
+/// the expression with its value if it is the same for every row,
+/// so NOW() has the same value in all the rows of a statement
+fn fold_constant(expr: Expr) -> Result<Expr, QueryExecutionError> {
+    match expr {
+        Expr::Value(_) => Ok(expr),
+        _ if expr.is_constant() => Ok(Expr::Value(expr.eval(&RowValue::default(), &[])?)),
+        _ => Ok(expr),
+    }
+}
+
+/// the value compared with a column of the table, a string compared with
+/// a CHAR column is padded like the values of the column
+fn compared_value(columns: &[Column], name: &str, value: SqlValue) -> SqlValue {
+    match columns.iter().find(|column| column.name == name) {
+        Some(column) if matches!(column.type_info, SqlType::Char(_)) => {
+            value.coerce(&column.type_info).unwrap_or(value)
+        }
+        _ => value,
+    }
+}
+
+/// the conditions with their constant expressions computed,
+/// so a column compared with one of them can be looked up in an index
+fn fold_constants(
+    wc: &WhereConstraint,
+    columns: &[Column],
+) -> Result<WhereConstraint, QueryExecutionError> {
+    let folded = match wc {
+        WhereConstraint::Constrait(name, cmp_opt, value) => WhereConstraint::Constrait(
+            name.clone(),
+            cmp_opt.clone(),
+            compared_value(columns, name, value.clone()),
+        ),
+        WhereConstraint::Compare(left, cmp_opt, right) => {
+            match (fold_constant(left.clone())?, fold_constant(right.clone())?) {
+                (Expr::Column(name), Expr::Value(value)) => {
+                    let value = compared_value(columns, &name, value);
+                    WhereConstraint::Constrait(name, cmp_opt.clone(), value)
+                }
+                // the column is put on the left side
+                (Expr::Value(value), Expr::Column(name)) => {
+                    let cmp_opt = match cmp_opt {
+                        CmpOpt::Lt => CmpOpt::Gt,
+                        CmpOpt::Le => CmpOpt::Ge,
+                        CmpOpt::Gt => CmpOpt::Lt,
+                        CmpOpt::Ge => CmpOpt::Le,
+                        other => other.clone(),
+                    };
+                    let value = compared_value(columns, &name, value);
+                    WhereConstraint::Constrait(name, cmp_opt, value)
                 }
+                (left, right) => WhereConstraint::Compare(left, cmp_opt.clone(), right),
             }
         }
-        SqlValue::String(value1) => {
-            match sqlvalue2 {
-                //Compare String
-                SqlValue::String(value2) => match cmp_opt {
-                    CmpOpt::Eq => value1 == value2,
-                    CmpOpt::Ge => value1 >= value2,
-                    CmpOpt::Gt => value1 > value2,
-                    CmpOpt::Le => value1 <= value2,
-                    CmpOpt::Lt => value1 < value2,
-                    CmpOpt::Ne => value1 != value2,
-                },
-                _ => {
-                    return false;
+        WhereConstraint::Not(wc_box) => {
+            WhereConstraint::Not(Box::new(fold_constants(wc_box, columns)?))
+        }
+        WhereConstraint::And(left_wc, right_wc) => WhereConstraint::And(
+            Box::new(fold_constants(left_wc, columns)?),
+            Box::new(fold_constants(right_wc, columns)?),
+        ),
+        WhereConstraint::Or(left_wc, right_wc) => WhereConstraint::Or(
+            Box::new(fold_constants(left_wc, columns)?),
+            Box::new(fold_constants(right_wc, columns)?),
+        ),
+        other => other.clone(),
+    };
+    Ok(folded)
+}
+
+fn compare_name(names_insert: &[String], columns: &[Column]) -> Option<String> {
+    //Determine if the input column name exists in the table
+    names_insert
+        .iter()
+        .find(|name_insert| !columns.iter().any(|column| &column.name == *name_insert))
+        .cloned()
+}
+
+fn get_newrol(
+    names_insert: Vec<String>,
+    columns: &[Column],
+    value: RowValue,
+) -> Result<RowValue, QueryExecutionError> {
+    //Arrange and complete input values according to column names, ensuring that they are in the same order as the data in the table
+    if let Some(name_insert) = compare_name(&names_insert, columns) {
+        return Err(QueryExecutionError::ColumnDoesNotExist(name_insert));
+    }
+    let mut row_values: Vec<SqlValue> = Vec::new();
+    for column in columns {
+        if let Some(index) = names_insert
+            .iter()
+            .position(|name_insert| name_insert == &column.name)
+        {
+            row_values.push(value.values[index].clone());
+        } else {
+            // a column left out takes its default, or NULL
+            row_values.push(match &column.constraints.default {
+                Some(default) => default.eval(&RowValue::default(), &[])?,
+                None => SqlValue::Null,
+            });
+        }
+    }
+    Ok(RowValue { values: row_values })
+}
+
+/// the values of the row converted to the types of their columns
+fn coerce_row(columns: &[Column], row: RowValue) -> Result<RowValue, QueryExecutionError> {
+    let values = row
+        .values
+        .into_iter()
+        .enumerate()
+        .map(|(position, value)| match columns.get(position) {
+            Some(column) => value.coerce(&column.type_info).ok_or_else(|| {
+                match (&column.type_info, value.as_f64()) {
+                    // a number with too many digits for the decimal column
+                    (SqlType::Decimal(_, _), Some(_)) => {
+                        QueryExecutionError::ValueOutOfRange(value.to_string())
+                    }
+                    (SqlType::Varchar(length) | SqlType::Char(length), _)
+                        if matches!(value, SqlValue::String(_)) =>
+                    {
+                        QueryExecutionError::ValueTooLong(column.name.clone(), *length)
+                    }
+                    _ => QueryExecutionError::TypeDoesNotMatch(value.to_string()),
                 }
+            }),
+            None => Ok(value),
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(RowValue { values })
+}
+
+/// map the io error of loading a table into execution error
+fn open_error(err: io::Error, table_name: String) -> QueryExecutionError {
+    match err.kind() {
+        io::ErrorKind::NotFound => QueryExecutionError::TableNotFound(table_name),
+        _ => QueryExecutionError::TableOpenfail(table_name),
+    }
+}
+
+/// map the io error of writing a table into execution error
+pub(crate) fn save_error(err: io::Error, table_name: String) -> QueryExecutionError {
+    if is_write_conflict(&err) {
+        QueryExecutionError::WriteConflict(table_name)
+    } else if let Some(index) = duplicate_key_index(&err) {
+        QueryExecutionError::UniqueViolation(index)
+    } else if let Some((size, limit)) = row_too_large(&err) {
+        QueryExecutionError::RowTooLarge(table_name, size, limit)
+    } else {
+        QueryExecutionError::TableSavefail(table_name)
+    }
+}
+
+/// map the io error of locking a table into execution error
+pub(crate) fn lock_error(err: io::Error, table_name: String) -> QueryExecutionError {
+    if is_locked(&err) {
+        QueryExecutionError::Locked(table_name)
+    } else {
+        QueryExecutionError::TableOpenfail(table_name)
+    }
+}
+
+/// map the io error of an index operation into execution error
+pub(crate) fn index_error(err: io::Error, index_name: String) -> QueryExecutionError {
+    if let Some(index) = duplicate_key_index(&err) {
+        return QueryExecutionError::UniqueViolation(index);
+    }
+    match err.kind() {
+        io::ErrorKind::AlreadyExists => QueryExecutionError::IndexAlreadyExists(index_name),
+        io::ErrorKind::NotFound => QueryExecutionError::IndexNotFound(index_name),
+        _ => QueryExecutionError::IndexFail(index_name, err.to_string()),
+    }
+}
+
+/// ids of the rows an index of the table gives for the conditions,
+/// None if no index fits them
+fn index_candidates<S: StorageEngine + ?Sized>(
+    storage: &S,
+    table_name: &str,
+    columns: &[Column],
+    wc: &WhereConstraint,
+) -> Result<Option<Vec<RowId>>, QueryExecutionError> {
+    match wc {
+        WhereConstraint::Constrait(name, cmp_opt, value) => {
+            // the index keeps the values with the type of the column
+            let typed = columns
+                .iter()
+                .find(|column| column.name == *name)
+                .and_then(|column| value.coerce(&column.type_info));
+            let value = typed.as_ref().unwrap_or(value);
+            let (lower, upper) = match cmp_opt {
+                CmpOpt::Eq => (Bound::Included(value), Bound::Included(value)),
+                CmpOpt::Lt => (Bound::Unbounded, Bound::Excluded(value)),
+                CmpOpt::Le => (Bound::Unbounded, Bound::Included(value)),
+                CmpOpt::Gt => (Bound::Excluded(value), Bound::Unbounded),
+                CmpOpt::Ge => (Bound::Included(value), Bound::Unbounded),
+                CmpOpt::Ne => return Ok(None),
+            };
+            // a hash index of the column finds equal keys with the fewest reads,
+            // a B+tree index whose first column it is finds any of them
+            let indexes = storage.indexes(table_name);
+            let hash = indexes.iter().find(|index| {
+                index.kind == IndexKind::Hash
+                    && *cmp_opt == CmpOpt::Eq
+                    && typed.is_some()
+                    && index.columns == [name.clone()]
+            });
+            let btree = indexes.iter().find(|index| {
+                index.kind == IndexKind::BTree && index.columns.first() == Some(name)
+            });
+            let index = match hash.or(btree) {
+                Some(index) => index,
+                None => return Ok(None),
+            };
+            let mut ids = storage
+                .index_lookup(&index.name, lower, upper)
+                .map_err(|err| index_error(err, index.name.clone()))?;
+            // keep the order of a scan
+            ids.sort();
+            ids.dedup();
+            Ok(Some(ids))
+        }
+        // both sides have to be met, so the rows of either side are enough
+        WhereConstraint::And(left_wc, right_wc) => {
+            match index_candidates(storage, table_name, columns, left_wc)? {
+                Some(ids) => Ok(Some(ids)),
+                None => index_candidates(storage, table_name, columns, right_wc),
             }
         }
+        // NULL is kept in the indexes like any other value
+        WhereConstraint::IsNull(name) => index_candidates(
+            storage,
+            table_name,
+            columns,
+            &WhereConstraint::Constrait(name.clone(), CmpOpt::Eq, SqlValue::Null),
+        ),
+        WhereConstraint::Compare(_, _, _) | WhereConstraint::Not(_) | WhereConstraint::Or(_, _) => {
+            Ok(None)
+        }
+    }
+}
+
+/// Rows meeting the conditions, filtered one by one while they are read
+type MatchingRows<'a> =
+    Box<dyn Iterator<Item = Result<(RowId, RowValue), QueryExecutionError>> + 'a>;
+
+/// the rows meeting the conditions, read through an index when one fits them
+/// and by scanning the whole table otherwise
+fn filter_rows<'a, S: StorageEngine + ?Sized>(
+    storage: &'a S,
+    table_name: &str,
+    wc: Option<&WhereConstraint>,
+) -> Result<(ColumnInfo, MatchingRows<'a>), QueryExecutionError> {
+    //check the result of loading
+    let scan = storage
+        .scan(table_name)
+        .map_err(|err| open_error(err, table_name.to_string()))?;
+    let names: Vec<String> = scan
+        .columns
+        .iter()
+        .map(|column| column.name.clone())
+        .collect();
+    let wc = wc.map(|wc| fold_constants(wc, &scan.columns)).transpose()?;
+    let candidates = match &wc {
+        Some(wc) => index_candidates(storage, table_name, &scan.columns, wc)?,
+        None => None,
+    };
+    let rows: Box<dyn Iterator<Item = Result<(RowId, RowValue), io::Error>>> = match candidates {
+        Some(ids) => Box::new(
+            storage
+                .fetch_rows(table_name, &ids)
+                .map_err(|err| open_error(err, table_name.to_string()))?
+                .into_iter()
+                .map(Ok),
+        ),
+        None => scan.rows,
+    };
+    let table_name = table_name.to_string();
+    let matched = rows.filter_map(move |row| {
+        let (id, row) = match row {
+            Ok(row) => row,
+            Err(_) => return Some(Err(QueryExecutionError::TableOpenfail(table_name.clone()))),
+        };
+        // the index only narrowed the rows down by one of the conditions
+        let matched_row = match &wc {
+            Some(wc) => compare_condition(wc, &row, &names).map(|matched| matched == Some(true)),
+            None => Ok(true),
+        };
+        match matched_row {
+            Ok(true) => Some(Ok((id, row))),
+            Ok(false) => None,
+            Err(err) => Some(Err(err)),
+        }
+    });
+    Ok((scan.columns, Box::new(matched)))
+}
+
+/// all the rows meeting the conditions
+fn matching_rows<S: StorageEngine + ?Sized>(
+    storage: &S,
+    table_name: &str,
+    wc: Option<&WhereConstraint>,
+) -> Result<(ColumnInfo, Vec<(RowId, RowValue)>), QueryExecutionError> {
+    let (columns, rows) = filter_rows(storage, table_name, wc)?;
+    Ok((columns, rows.collect::<Result<_, _>>()?))
+}
+
+/// hash aggregation of the rows by the values of the columns at `positions`,
+/// every group has its first row and the accumulators of `aggregates`.
+/// The groups are in the order their first row is read,
+/// without columns all the rows make a single group, even when there is no row
+fn group_rows(
+    rows: MatchingRows<'_>,
+    positions: &[usize],
+    aggregates: &[Aggregate],
+    names: &[String],
+) -> Result<Vec<(RowValue, Vec<Accumulator>)>, QueryExecutionError> {
+    let accumulators = || aggregates.iter().map(Accumulator::new).collect::<Vec<_>>();
+    let mut groups = Vec::new();
+    let mut group_index: HashMap<Vec<SqlValue>, usize> = HashMap::new();
+    if positions.is_empty() {
+        groups.push((RowValue::default(), accumulators()));
+        group_index.insert(Vec::new(), 0);
+    }
+    for row in rows {
+        let (_, row) = row?;
+        // NULL values of the columns make a group of their own
+        let key: Vec<SqlValue> = positions
+            .iter()
+            .map(|position| row.values.get(*position).cloned().unwrap_or(SqlValue::Null))
+            .collect();
+        let index = *group_index.entry(key).or_insert_with(|| {
+            groups.push((row.clone(), accumulators()));
+            groups.len() - 1
+        });
+        for accumulator in groups[index].1.iter_mut() {
+            accumulator.update(&row, names)?;
+        }
+    }
+    Ok(groups)
+}
+
+/// the rows of LIMIT and OFFSET, no row is read past the limit.
+/// An error is never skipped, so it still ends the query
+fn limit_rows<T, E>(
+    rows: impl Iterator<Item = Result<T, E>>,
+    limit: Limit,
+) -> impl Iterator<Item = Result<T, E>> {
+    let mut skipped = 0;
+    rows.filter(move |row| {
+        let skip = row.is_ok() && skipped < limit.offset;
+        if skip {
+            skipped += 1;
+        }
+        !skip
+    })
+    .take(usize::try_from(limit.count).unwrap_or(usize::MAX))
+}
+
+/// name of the index kept on the primary key of a table
+fn primary_key_index(table_name: &str) -> String {
+    format!("{table_name}_pkey")
+}
+
+/// name of the index kept on a unique column of a table
+fn unique_index(table_name: &str, column: &str) -> String {
+    format!("{table_name}_{column}_key")
+}
+
+/// whether the new rows would give two rows of the table the same values in the columns at
+/// `positions`, each new row comes with the id of the row it replaces.
+/// Keys with a NULL value are never equal to another key
+fn duplicate_key<S: StorageEngine + ?Sized>(
+    storage: &S,
+    table_name: &str,
+    columns: &[Column],
+    positions: &[usize],
+    rows: &[(Option<RowId>, &RowValue)],
+) -> Result<bool, QueryExecutionError> {
+    let value = |row: &RowValue, position: usize| {
+        row.values.get(position).cloned().unwrap_or(SqlValue::Null)
+    };
+    let key = |row: &RowValue| -> Vec<SqlValue> {
+        positions
+            .iter()
+            .map(|position| value(row, *position))
+            .collect()
+    };
+    let mut keys = HashSet::new();
+    for (_, row) in rows {
+        let key = key(row);
+        if key.contains(&SqlValue::Null) {
+            continue;
+        }
+        if !keys.insert(key) {
+            return Ok(true);
+        }
+    }
+    let replaced: HashSet<RowId> = rows.iter().filter_map(|(id, _)| *id).collect();
+    let others = match rows {
+        [] => return Ok(false),
+        _ if keys.is_empty() => return Ok(false),
+        // a single row is looked up by its key, through an index of the key if there is one
+        [(_, row)] => {
+            let wc = positions
+                .iter()
+                .map(|position| {
+                    WhereConstraint::Constrait(
+                        columns[*position].name.clone(),
+                        CmpOpt::Eq,
+                        value(row, *position),
+                    )
+                })
+                .reduce(|left, right| WhereConstraint::And(Box::new(left), Box::new(right)))
+                .unwrap();
+            matching_rows(storage, table_name, Some(&wc))?.1
+        }
+        _ => matching_rows(storage, table_name, None)?.1,
+    };
+    Ok(others
+        .iter()
+        .any(|(id, row)| !replaced.contains(id) && keys.contains(&key(row))))
+}
+
+/// name of the index kept on a column referring to another table
+fn foreign_key_index(table_name: &str, column: &str) -> String {
+    format!("{table_name}_{column}_fkey")
+}
+
+/// the other tables with a column referring to the table, with their columns
+fn referencing_tables<S: StorageEngine + ?Sized>(
+    storage: &S,
+    table_name: &str,
+) -> Result<Vec<(String, ColumnInfo)>, QueryExecutionError> {
+    let names = storage
+        .list_tables()
+        .map_err(|_| QueryExecutionError::TableOpenfail(table_name.to_string()))?;
+    let mut tables = Vec::new();
+    for name in names {
+        let columns = storage
+            .scan(&name)
+            .map_err(|err| open_error(err, name.clone()))?
+            .columns;
+        let refers = columns.iter().any(|column| {
+            column
+                .constraints
+                .references
+                .as_ref()
+                .is_some_and(|references| references.table == table_name)
+        });
+        if refers {
+            tables.push((name, columns));
+        }
+    }
+    Ok(tables)
+}
+
+/// the rows of the table whose column has one of the values
+fn rows_with_values<S: StorageEngine + ?Sized>(
+    storage: &S,
+    table_name: &str,
+    column: &str,
+    values: &HashSet<SqlValue>,
+) -> Result<Vec<(RowId, RowValue)>, QueryExecutionError> {
+    match values.iter().collect::<Vec<_>>()[..] {
+        [] => Ok(Vec::new()),
+        // a single value is looked up through an index of the column if there is one
+        [value] => {
+            let wc = WhereConstraint::Constrait(column.to_string(), CmpOpt::Eq, value.clone());
+            Ok(matching_rows(storage, table_name, Some(&wc))?.1)
+        }
         _ => {
-            return false;
+            let (columns, rows) = matching_rows(storage, table_name, None)?;
+            let position = columns.iter().position(|other| other.name == column);
+            Ok(rows
+                .into_iter()
+                .filter(|(_, row)| {
+                    position
+                        .and_then(|position| row.values.get(position))
+                        .is_some_and(|value| values.contains(value))
+                })
+                .collect())
         }
     }
 }
 
-fn compare_condition(wc: WhereConstraint, record: &RowValue, record_names: &Vec<String>) -> bool {
-    //Based on the input conditions, judge whether the record meets the conditions
-    match wc {
-        WhereConstraint::Constrait(name, cmp_opt, sql_value) => {
-            // Handle Constrait case
-            if !record_names.contains(&name) {
-                return false;
-            } else {
-                // Find the index of 'name' in 'record_name'
-                let index = record_names
-                    .iter()
-                    .position(|r_name| r_name == &name)
-                    .unwrap();
-                // Retrieve the SqlValue from 'record' using the index
-                let record_value = &record.values[index];
+/// the values of the column at `position` in the rows, without NULL
+fn column_values<'a>(
+    rows: impl Iterator<Item = &'a RowValue>,
+    position: usize,
+) -> HashSet<SqlValue> {
+    rows.filter_map(|row| row.values.get(position))
+        .filter(|value| **value != SqlValue::Null)
+        .cloned()
+        .collect()
+}
 
-                // Compare 'record_value' with 'sql_value' based on 'cmp_opt'
-                compare_sqlvalue(&record_value, &sql_value, &cmp_opt)
+/// fail if the new rows break a constraint of the columns of the table,
+/// each new row comes with the id of the row it replaces.
+/// The keys are only checked again when `changed` gives one of their columns
+fn check_constraints<S: StorageEngine + ?Sized>(
+    storage: &S,
+    table_name: &str,
+    columns: &[Column],
+    rows: &[(Option<RowId>, &RowValue)],
+    changed: impl Fn(&Column) -> bool,
+) -> Result<(), QueryExecutionError> {
+    let names: Vec<String> = columns.iter().map(|column| column.name.clone()).collect();
+    for (_, row) in rows {
+        for (position, column) in columns.iter().enumerate() {
+            let constraints = &column.constraints;
+            let missing = matches!(row.values.get(position), None | Some(SqlValue::Null));
+            if missing && (constraints.not_null || constraints.primary_key) {
+                return Err(QueryExecutionError::NotNullViolation(column.name.clone()));
             }
-        }
-        WhereConstraint::Not(wc_box) => {
-            // Handle Constrait case
-            // let mut result: Vec<SelectCriteria> = Vec::new();
-            let new_wc = *wc_box.clone();
-            if !compare_condition(new_wc, &record, &record_names) {
-                return true;
-            } else {
-                return false;
+            // a check which is unknown is met
+            if let Some(check) = &constraints.check {
+                if compare_condition(check, row, &names)? == Some(false) {
+                    return Err(QueryExecutionError::CheckViolation(column.name.clone()));
+                }
             }
         }
-        WhereConstraint::And(left_wc, right_wc) => {
-            let new_left_wc: WhereConstraint = *left_wc.clone();
-            let new_right_wc: WhereConstraint = *right_wc.clone();
-            if compare_condition(new_left_wc, &record, &record_names)
-                && compare_condition(new_right_wc, &record, &record_names)
+    }
+    let primary_key: Vec<usize> = columns
+        .iter()
+        .enumerate()
+        .filter(|(_, column)| column.constraints.primary_key)
+        .map(|(position, _)| position)
+        .collect();
+    if primary_key
+        .iter()
+        .any(|position| changed(&columns[*position]))
+        && duplicate_key(storage, table_name, columns, &primary_key, rows)?
+    {
+        return Err(QueryExecutionError::PrimaryKeyViolation(
+            table_name.to_string(),
+        ));
+    }
+    for (position, column) in columns.iter().enumerate() {
+        if column.constraints.unique
+            && changed(column)
+            && duplicate_key(storage, table_name, columns, &[position], rows)?
+        {
+            return Err(QueryExecutionError::UniqueViolation(unique_index(
+                table_name,
+                &column.name,
+            )));
+        }
+    }
+    for (position, column) in columns.iter().enumerate() {
+        let references = match &column.constraints.references {
+            Some(references) if changed(column) => references,
+            _ => continue,
+        };
+        let mut values = column_values(rows.iter().map(|(_, row)| *row), position);
+        if references.table == table_name {
+            // a row may refer to a row added with it
+            if let Some(referenced) = columns
+                .iter()
+                .position(|other| other.name == references.column)
             {
-                return true;
-            } else {
-                return false;
+                let added = column_values(rows.iter().map(|(_, row)| *row), referenced);
+                values.retain(|value| !added.contains(value));
             }
         }
-        WhereConstraint::Or(left_wc, right_wc) => {
-            let new_left_wc: WhereConstraint = *left_wc.clone();
-            let new_right_wc: WhereConstraint = *right_wc.clone();
-            if compare_condition(new_left_wc, &record, &record_names)
-                || compare_condition(new_right_wc, &record, &record_names)
+        for value in values {
+            let wc = WhereConstraint::Constrait(references.column.clone(), CmpOpt::Eq, value);
+            if matching_rows(storage, &references.table, Some(&wc))?
+                .1
+                .is_empty()
             {
-                return true;
-            } else {
-                return false;
+                return Err(QueryExecutionError::ForeignKeyViolation(
+                    column.name.clone(),
+                ));
             }
         }
     }
+    Ok(())
 }
-fn compare_name(names_insert: &Vec<String>, columns: &Vec<Column>) -> Option<String> {
-    //Determine if the input column name exists in the table
-    let mut names_columns: Vec<String> = Vec::new();
-    for column in columns {
-        names_columns.push(column.name.clone());
-    }
-    for name_insert in names_insert {
-        if !names_columns.contains(name_insert) {
-            return Some(name_insert.clone());
+
+/// fail if the changed rows take away values other tables refer to
+fn check_referenced<S: StorageEngine + ?Sized>(
+    storage: &S,
+    table_name: &str,
+    columns: &[Column],
+    rows_old: &[(RowId, RowValue)],
+    rows_new: &[(RowId, RowValue)],
+) -> Result<(), QueryExecutionError> {
+    for (child, child_columns) in referencing_tables(storage, table_name)? {
+        for column in &child_columns {
+            let position = match &column.constraints.references {
+                Some(references) if references.table == table_name => columns
+                    .iter()
+                    .position(|other| other.name == references.column),
+                _ => None,
+            };
+            if let Some(position) = position {
+                let kept = column_values(rows_new.iter().map(|(_, row)| row), position);
+                let mut lost = column_values(rows_old.iter().map(|(_, row)| row), position);
+                lost.retain(|value| !kept.contains(value));
+                if !rows_with_values(storage, &child, &column.name, &lost)?.is_empty() {
+                    return Err(QueryExecutionError::RowReferenced(
+                        table_name.to_string(),
+                        child,
+                    ));
+                }
+            }
         }
     }
-    None
+    Ok(())
 }
 
-fn get_newrol(
-    names_insert: Vec<String>,
-    columns: &Vec<Column>,
-    value: RowValue,
-) -> Result<RowValue, QueryExecutionError> {
-    //Arrange and complete input values according to column names, ensuring that they are in the same order as the data in the table
-    match compare_name(&names_insert, columns) {
-        None => {
-            // Create an iterator over the names_table
-            let mut row_values: Vec<SqlValue> = Vec::new();
-            // let name_columns: Vec<String> = columns.iter().map(|column: &Column| column.name.clone()).collect();
-            for column in columns {
-                if let Some(index) = names_insert
-                    .iter()
-                    .position(|name_insert| name_insert == &column.name)
-                {
-                    // row_value.push(value.clone().values[index]);
-                    let values: &Vec<SqlValue> = &value.values;
-                    match &values[index] {
-                        SqlValue::String(row_value) => {
-                            row_values.push(SqlValue::String(row_value.clone()));
+/// The changes of a delete, with the ON DELETE actions of the rows referencing the deleted rows
+#[derive(Default)]
+struct DeletePlan {
+    /// rows to remove by table
+    removed: HashMap<String, BTreeMap<RowId, RowValue>>,
+    /// rows whose referencing columns lose their value by table
+    cleared: HashMap<String, BTreeMap<RowId, RowValue>>,
+    /// rows which keep the delete from happening unless they are removed too
+    restricted: Vec<(String, String, RowId)>,
+}
+
+impl DeletePlan {
+    /// add the rows of the table to remove, and the rows referencing them
+    fn remove<S: StorageEngine + ?Sized>(
+        &mut self,
+        storage: &S,
+        table_name: &str,
+        columns: &[Column],
+        rows: Vec<(RowId, RowValue)>,
+    ) -> Result<(), QueryExecutionError> {
+        let removed = self.removed.entry(table_name.to_string()).or_default();
+        let rows: Vec<(RowId, RowValue)> = rows
+            .into_iter()
+            .filter(|(id, _)| !removed.contains_key(id))
+            .collect();
+        removed.extend(rows.iter().cloned());
+        if rows.is_empty() {
+            return Ok(());
+        }
+        // only a primary key or a unique column is referred to
+        let has_key = columns
+            .iter()
+            .any(|column| column.constraints.primary_key || column.constraints.unique);
+        if !has_key {
+            return Ok(());
+        }
+        for (child, child_columns) in referencing_tables(storage, table_name)? {
+            for (child_position, column) in child_columns.iter().enumerate() {
+                let (references, position) = match &column.constraints.references {
+                    Some(references) if references.table == table_name => {
+                        match columns
+                            .iter()
+                            .position(|other| other.name == references.column)
+                        {
+                            Some(position) => (references, position),
+                            None => continue,
                         }
-                        SqlValue::Int(row_value) => {
-                            row_values.push(SqlValue::Int(*row_value));
+                    }
+                    _ => continue,
+                };
+                let values = column_values(rows.iter().map(|(_, row)| row), position);
+                let referencing = rows_with_values(storage, &child, &column.name, &values)?;
+                match references.on_delete {
+                    OnDelete::Restrict => self.restricted.extend(
+                        referencing
+                            .into_iter()
+                            .map(|(id, _)| (table_name.to_string(), child.clone(), id)),
+                    ),
+                    OnDelete::Cascade => {
+                        self.remove(storage, &child, &child_columns, referencing)?
+                    }
+                    OnDelete::SetNull => {
+                        if column.constraints.not_null || column.constraints.primary_key {
+                            return Err(QueryExecutionError::NotNullViolation(column.name.clone()));
                         }
-                        SqlValue::Unknown => {
-                            row_values.push(SqlValue::Unknown);
+                        let cleared = self.cleared.entry(child.clone()).or_default();
+                        for (id, row) in referencing {
+                            let row = cleared.entry(id).or_insert(row);
+                            row.values[child_position] = SqlValue::Null;
                         }
                     }
-                } else {
-                    match column.type_info {
-                        SqlType::String => row_values.push(SqlValue::String("NULL".to_string())),
-                        SqlType::Int => row_values.push(SqlValue::Int(0)),
-                        SqlType::Unknown => row_values.push(SqlValue::Unknown),
-                    }
                 }
             }
-            let rowvalues: RowValue = RowValue { values: row_values };
-            Ok(rowvalues)
         }
-        Some(name_insert) => return Err(QueryExecutionError::ColumnDoesNotExist(name_insert)),
+        Ok(())
+    }
+
+    /// check the restrictions and write the changes
+    fn apply<S: StorageEngine + ?Sized>(self, storage: &mut S) -> Result<(), QueryExecutionError> {
+        let removed = |table: &str, id: &RowId| {
+            self.removed
+                .get(table)
+                .is_some_and(|rows| rows.contains_key(id))
+        };
+        if let Some((table, child, _)) = self
+            .restricted
+            .iter()
+            .find(|(_, child, id)| !removed(child, id))
+        {
+            return Err(QueryExecutionError::RowReferenced(
+                table.clone(),
+                child.clone(),
+            ));
+        }
+        // the ids stay the same until rows are removed
+        for (table, rows) in &self.cleared {
+            let rows: Vec<(RowId, RowValue)> = rows
+                .iter()
+                .filter(|(id, _)| !removed(table, id))
+                .map(|(id, row)| (*id, row.clone()))
+                .collect();
+            if !rows.is_empty() {
+                storage
+                    .replace_rows(table, rows)
+                    .map_err(|err| save_error(err, table.clone()))?;
+            }
+        }
+        for (table, rows) in &self.removed {
+            let ids: Vec<RowId> = rows.keys().copied().collect();
+            if !ids.is_empty() {
+                storage
+                    .remove_rows(table, &ids)
+                    .map_err(|err| save_error(err, table.clone()))?;
+            }
+        }
+        Ok(())
     }
 }
 
@@ -170,37 +852,162 @@ impl SqlTable {
 
 impl Executable for CreateStatement {
     //Create a new table
-    fn check_and_execute(
+    fn check_and_execute<S: StorageEngine + ?Sized>(
         self,
-        storage_util: StoreUtil,
+        storage: &mut S,
     ) -> Result<ExecuteResponse, QueryExecutionError> {
-        let name = self.table.clone();
-        let columns_infos = self.columns;
-        let table = SqlTable::new(columns_infos);
-        match storage_util.save(name.clone(), &table) {
-            Ok(()) => Ok(ExecuteResponse::Message(format!(
-                "save {} successful",
-                name
-            ))),
-            Err(_) => Err(QueryExecutionError::TableSavefail(name)),
+        let name = self.table;
+        let mut columns = self.columns;
+        let key_columns = columns
+            .iter()
+            .filter(|column| column.constraints.primary_key)
+            .count();
+        if key_columns > 1 || (key_columns == 1 && !self.primary_key.is_empty()) {
+            return Err(QueryExecutionError::MultiplePrimaryKeys(name));
+        }
+        if let Some(column) = compare_name(&self.primary_key, &columns) {
+            return Err(QueryExecutionError::ColumnDoesNotExist(column));
+        }
+        for column in columns.iter_mut() {
+            if self.primary_key.contains(&column.name) {
+                column.constraints.primary_key = true;
+            }
         }
+        for column in columns.iter_mut() {
+            if let Some(default) = &column.constraints.default {
+                // a default like NOW() is computed for every row, it may not refer to a column
+                let value = default
+                    .eval(&RowValue::default(), &[])?
+                    .coerce(&column.type_info)
+                    .ok_or_else(|| QueryExecutionError::TypeDoesNotMatch(default.to_string()))?;
+                if let Expr::Value(_) = default {
+                    column.constraints.default = Some(Expr::Value(value));
+                }
+            }
+        }
+        for (name, references) in self.foreign_keys {
+            match columns.iter_mut().find(|column| column.name == name) {
+                Some(column) => column.constraints.references = Some(references),
+                None => return Err(QueryExecutionError::ColumnDoesNotExist(name)),
+            }
+        }
+        let key_columns = columns
+            .iter()
+            .filter(|column| column.constraints.primary_key)
+            .count();
+        for column in &columns {
+            let references = match &column.constraints.references {
+                Some(references) => references,
+                None => continue,
+            };
+            // a table may refer to itself
+            let referenced_columns = if references.table == name {
+                columns.clone()
+            } else {
+                storage
+                    .scan(&references.table)
+                    .map_err(|err| open_error(err, references.table.clone()))?
+                    .columns
+            };
+            let referenced = referenced_columns
+                .iter()
+                .find(|other| other.name == references.column)
+                .ok_or_else(|| {
+                    QueryExecutionError::ColumnDoesNotExist(references.column.clone())
+                })?;
+            let referenced_key_columns = referenced_columns
+                .iter()
+                .filter(|other| other.constraints.primary_key)
+                .count();
+            let is_key = referenced.constraints.unique
+                || (referenced.constraints.primary_key && referenced_key_columns == 1);
+            if !is_key {
+                return Err(QueryExecutionError::InvalidForeignKey(column.name.clone()));
+            }
+            if referenced.type_info != column.type_info {
+                return Err(QueryExecutionError::TypeDoesNotMatch(column.name.clone()));
+            }
+        }
+        // the keys are looked up on every insert, so they get an index when the storage has them
+        let mut indexes = Vec::new();
+        let key: Vec<String> = columns
+            .iter()
+            .filter(|column| column.constraints.primary_key)
+            .map(|column| column.name.clone())
+            .collect();
+        if !key.is_empty() {
+            indexes.push(IndexInfo {
+                name: primary_key_index(&name),
+                table: name.clone(),
+                columns: key,
+                unique: true,
+                kind: IndexKind::BTree,
+            });
+        }
+        for column in columns.iter().filter(|column| column.constraints.unique) {
+            // NULL may repeat, so the executor checks the uniqueness instead of the index
+            indexes.push(IndexInfo {
+                name: unique_index(&name, &column.name),
+                table: name.clone(),
+                columns: vec![column.name.clone()],
+                unique: false,
+                kind: IndexKind::BTree,
+            });
+        }
+        // the referencing rows are looked up on every delete of the table they refer to
+        for column in columns.iter().filter(|column| {
+            column.constraints.references.is_some()
+                && !column.constraints.unique
+                && !(column.constraints.primary_key && key_columns == 1)
+        }) {
+            indexes.push(IndexInfo {
+                name: foreign_key_index(&name, &column.name),
+                table: name.clone(),
+                columns: vec![column.name.clone()],
+                unique: false,
+                kind: IndexKind::BTree,
+            });
+        }
+        let table = SqlTable::new(columns);
+        if let Err(err) = storage.save(&name, &table) {
+            return Err(save_error(err, name));
+        }
+        for index in indexes {
+            match storage.create_index(&index) {
+                Err(err) if err.kind() != io::ErrorKind::Unsupported => {
+                    return Err(index_error(err, index.name))
+                }
+                _ => (),
+            }
+        }
+        Ok(ExecuteResponse::Message(format!(
+            "save {} successful",
+            name
+        )))
     }
 }
 
 impl Executable for DropStatement {
     //delete a table
-    fn check_and_execute(
+    fn check_and_execute<S: StorageEngine + ?Sized>(
         self,
-        storage_util: StoreUtil,
+        storage: &mut S,
     ) -> Result<ExecuteResponse, QueryExecutionError> {
-        let name = self.table.clone();
-        match storage_util.delete(&name) {
+        let name = self.table;
+        if let Some((child, _)) = referencing_tables(storage, &name)?
+            .into_iter()
+            .find(|(child, _)| *child != name)
+        {
+            return Err(QueryExecutionError::TableReferenced(name, child));
+        }
+        match storage.delete(&name) {
             Ok(()) => Ok(ExecuteResponse::Message(format!(
                 "delete {} successful",
                 name
             ))),
             Err(err) => match err.kind() {
-                std::io::ErrorKind::NotFound => Err(QueryExecutionError::TableNotFound(name)),
+                io::ErrorKind::NotFound => Err(QueryExecutionError::TableNotFound(name)),
+                _ if is_write_conflict(&err) => Err(QueryExecutionError::WriteConflict(name)),
                 _ => Err(QueryExecutionError::TableDeletefail(name)),
             },
         }
@@ -209,264 +1016,493 @@ impl Executable for DropStatement {
 
 impl Executable for InsertStatement {
     // insert a record
-    fn check_and_execute(
+    fn check_and_execute<S: StorageEngine + ?Sized>(
         self,
-        storage_util: StoreUtil,
+        storage: &mut S,
     ) -> Result<ExecuteResponse, QueryExecutionError> {
-        let name = self.table.clone();
-        match storage_util.load(name.clone()) {
-            Ok(table) => {
-                let columns: Vec<Column> = table.columns;
-                let mut rows: Vec<RowValue> = table.rows;
-                // let name_columns: Vec<String> = columns.iter().map(|column: &Column| column.name.clone()).collect();
-                match self.columns {
-                    Some(name_insert) => match get_newrol(name_insert, &columns, self.values) {
-                        Ok(rowvalue) => {
-                            rows.push(rowvalue);
-                            let new_table = SqlTable { columns, rows };
-                            match storage_util.save(name.clone(), &new_table) {
-                                Ok(()) => Ok(ExecuteResponse::Message(format!(
-                                    "save {} successful",
-                                    name
-                                ))),
-                                Err(_) => Err(QueryExecutionError::TableSavefail(name)),
-                            }
-                        }
-                        Err(err) => Err(err),
-                    },
-                    None => {
-                        let rowvalue = self.values;
-                        rows.push(rowvalue);
-                        let new_table = SqlTable { columns, rows };
-                        match storage_util.save(name.clone(), &new_table) {
-                            Ok(()) => Ok(ExecuteResponse::Message(format!(
-                                "save {} successful",
-                                name
-                            ))),
-                            Err(_) => Err(QueryExecutionError::TableSavefail(name)),
-                        }
-                    }
-                }
-            }
-            Err(err) => match err.kind() {
-                std::io::ErrorKind::NotFound => Err(QueryExecutionError::TableNotFound(name)),
-                _ => Err(QueryExecutionError::TableOpenfail(name)),
-            },
+        let name = self.table;
+        let columns = storage
+            .scan(&name)
+            .map_err(|err| open_error(err, name.clone()))?
+            .columns;
+        let values = self
+            .values
+            .iter()
+            .map(|expr| expr.eval(&RowValue::default(), &[]))
+            .collect::<Result<_, _>>()?;
+        let values = RowValue { values };
+        // every column given, or every column of the table, takes exactly one value
+        let width = self.columns.as_ref().map_or(columns.len(), Vec::len);
+        if values.values.len() != width {
+            return Err(QueryExecutionError::ValueCountMismatch(
+                values.values.len(),
+                width,
+            ));
+        }
+        let rowvalue = match self.columns {
+            Some(name_insert) => get_newrol(name_insert, &columns, values)?,
+            None => values,
+        };
+        let rowvalue = coerce_row(&columns, rowvalue)?;
+        check_constraints(storage, &name, &columns, &[(None, &rowvalue)], |_| true)?;
+        match storage.append(&name, rowvalue) {
+            Ok(_) => Ok(ExecuteResponse::Message(format!(
+                "save {} successful",
+                name
+            ))),
+            Err(err) => Err(save_error(err, name)),
         }
     }
 }
 
 impl Executable for DeleteStatement {
     // delete a record
-    fn check_and_execute(
+    fn check_and_execute<S: StorageEngine + ?Sized>(
         self,
-        storage_util: StoreUtil,
+        storage: &mut S,
     ) -> Result<ExecuteResponse, QueryExecutionError> {
-        let table_name = self.table.clone();
+        let table_name = self.table;
         let wc = match self.constraints {
             Some(wc_tmp) => wc_tmp,
             None => {
                 return Err(QueryExecutionError::NoConditionsObtained());
             }
         };
-        match storage_util.load(table_name.clone()) {
-            //check the result of loading
-            Ok(table) => {
-                let rows_old: Vec<RowValue> = table.rows;
-                let columns_old = table.columns;
-                let mut name_old: Vec<String> = Vec::new();
-                for column_old in &columns_old {
-                    name_old.push(column_old.name.clone());
-                }
-                let mut rows_new: Vec<RowValue> = Vec::new();
-                for row_old in rows_old {
-                    if compare_condition(wc.clone(), &row_old, &name_old) {
-                        continue;
-                    } else {
-                        rows_new.push(row_old.clone());
-                    }
-                }
-                let table_new = SqlTable {
-                    columns: columns_old,
-                    rows: rows_new,
-                };
-                match storage_util.save(table_name.clone(), &table_new) {
-                    Ok(_) => Ok(ExecuteResponse::Message("delete final".to_string())),
-                    Err(_) => Err(QueryExecutionError::TableSavefail(table_name)),
-                }
-            }
-            Err(err) => match err.kind() {
-                std::io::ErrorKind::NotFound => Err(QueryExecutionError::TableNotFound(table_name)),
-                _ => Err(QueryExecutionError::TableOpenfail(table_name)),
-            },
+        let (columns, rows_old) = matching_rows(storage, &table_name, Some(&wc))?;
+        let mut plan = DeletePlan::default();
+        plan.remove(storage, &table_name, &columns, rows_old)?;
+        plan.apply(storage)?;
+        Ok(ExecuteResponse::Message("delete final".to_string()))
+    }
+}
+
+/// where the value of an ORDER BY key comes from
+enum OrderKey {
+    /// the result column at the position
+    Result(usize),
+    /// an expression of the row of the table
+    Row(Expr),
+}
+
+/// a key names a result column or gives its position, counted from 1,
+/// otherwise it is computed from the row of the table
+fn order_key(item: &OrderItem, columns_return: &[Column]) -> Result<OrderKey, QueryExecutionError> {
+    match &item.expr {
+        Expr::Column(name) => match columns_return
+            .iter()
+            .position(|column| column.name == *name)
+        {
+            Some(position) => Ok(OrderKey::Result(position)),
+            None => Ok(OrderKey::Row(item.expr.clone())),
+        },
+        Expr::Value(position @ (SqlValue::Int(_) | SqlValue::BigInt(_))) => position
+            .as_i64()
+            .and_then(|position| usize::try_from(position).ok())
+            .filter(|position| (1..=columns_return.len()).contains(position))
+            .map(|position| OrderKey::Result(position - 1))
+            .ok_or_else(|| QueryExecutionError::ColumnDoesNotExist(position.to_string())),
+        expr => Ok(OrderKey::Row(fold_constant(expr.clone())?)),
+    }
+}
+
+/// order of two rows by the values of their ORDER BY keys
+fn compare_order(a: &[SqlValue], b: &[SqlValue], order_by: &[OrderItem]) -> Ordering {
+    for ((a, b), item) in a.iter().zip(b).zip(order_by) {
+        // NULL is larger than any value unless NULLS FIRST or LAST says otherwise
+        let nulls_first = item.nulls_first.unwrap_or(item.descending);
+        let ordering = match (a, b) {
+            (SqlValue::Null, SqlValue::Null) => Ordering::Equal,
+            (SqlValue::Null, _) if nulls_first => Ordering::Less,
+            (SqlValue::Null, _) => Ordering::Greater,
+            (_, SqlValue::Null) if nulls_first => Ordering::Greater,
+            (_, SqlValue::Null) => Ordering::Less,
+            _ if item.descending => compare_values(b, a),
+            _ => compare_values(a, b),
+        };
+        if ordering.is_ne() {
+            return ordering;
         }
     }
+    Ordering::Equal
 }
 
 impl Executable for SelectStatement {
     // select recodes in table
-    fn check_and_execute(
+    fn check_and_execute<S: StorageEngine + ?Sized>(
         self,
-        storage_util: StoreUtil,
+        storage: &mut S,
     ) -> Result<ExecuteResponse, QueryExecutionError> {
-        let table_name = self.table.clone();
-        match storage_util.load(table_name.clone()) {
-            //check the result of loading
-            Ok(table) => {
-                let columns: Vec<Column> = table.columns;
-                let columns_clone = columns.clone();
-                let names_columns = columns_clone
-                    .iter()
-                    .map(|column: &Column| column.name.clone())
-                    .collect();
-                let rows_mapping: Vec<RowValue> = {
-                    match self.constraints {
-                        None => table.rows.clone(),
-                        Some(constraints) => {
-                        let mut rows: Vec<RowValue> = Vec::new();
-                        for row in table.rows {
-                            if compare_condition(constraints.clone(), &row, &names_columns) {
-                                rows.push(row.clone());
-                            }
-                        }
-                        rows
-                        }
+        let table_name = self.table;
+        // a single COUNT(*) of all the rows gives their number unless LIMIT drops its row
+        let count_rows = self.group_by.is_empty()
+            && self.having.is_none()
+            && matches!(
+                self.columns.as_slice(),
+                [SelectItem::Expr(
+                    Expr::Aggregate(Aggregate {
+                        function: AggregateFunction::Count,
+                        arg: None,
+                        ..
+                    }),
+                    None
+                )]
+            )
+            && self
+                .limit
+                .is_none_or(|limit| limit.count > 0 && limit.offset == 0);
+        let (columns, rows) = filter_rows(&*storage, &table_name, self.constraints.as_ref())?;
+        let names_columns: Vec<String> = columns.iter().map(|column| column.name.clone()).collect();
+        let mut columns_return: Vec<Column> = Vec::new();
+        let mut exprs_return: Vec<Expr> = Vec::new();
+        for item in self.columns {
+            match item {
+                SelectItem::All => {
+                    columns_return.extend(columns.iter().cloned());
+                    exprs_return.extend(names_columns.iter().cloned().map(Expr::Column));
+                }
+                SelectItem::Expr(expr, alias) => {
+                    // a column keeps its type, any other expression is named after its text
+                    let mut column_return = match &expr {
+                        Expr::Column(name) => columns
+                            .iter()
+                            .find(|column| column.name == *name)
+                            .cloned()
+                            .ok_or_else(|| QueryExecutionError::ColumnDoesNotExist(name.clone()))?,
+                        _ => Column {
+                            name: expr.to_string(),
+                            type_info: SqlType::Unknown,
+                            constraints: ColumnConstraints::default(),
+                        },
+                    };
+                    if let Some(alias) = alias {
+                        column_return.name = alias;
                     }
-                };
-                let mut columns_return: Vec<Column> = Vec::new();
-                let mut rows_return: Vec<RowValue> = Vec::new();
-                let columns_get = self.columns;
-                for column_get in columns_get {
-                    if column_get == "*" {
-                        for column in &columns {
-                            columns_return.push(column.clone());
-                        }
-                    } else {
-                        let columns_clone = columns.clone();
-                        if let Some(matched_column) =
-                            columns_clone.iter().find(|&col| col.name == column_get)
-                        {
-                            columns_return.push(matched_column.clone());
-                        }
+                    columns_return.push(column_return);
+                    exprs_return.push(fold_constant(expr)?);
+                }
+            }
+        }
+        let order_keys: Vec<OrderKey> = self
+            .order_by
+            .iter()
+            .map(|item| order_key(item, &columns_return))
+            .collect::<Result<_, _>>()?;
+        let sort_fail = |err: io::Error| QueryExecutionError::SortFail(err.to_string());
+        let mut rows_return: Vec<RowValue> = Vec::new();
+        let mut sort = (!self.order_by.is_empty()).then(|| {
+            ExternalSort::new(sort_memory(), |a: &[SqlValue], b: &[SqlValue]| {
+                compare_order(a, b, &self.order_by)
+            })
+        });
+        // the ORDER BY keys and the result row of a row of the table
+        let project = |exprs: &[Expr], keys: &[OrderKey], row: &RowValue| {
+            let row_return: Vec<SqlValue> = exprs
+                .iter()
+                .map(|expr| expr.eval(row, &names_columns))
+                .collect::<Result<_, _>>()?;
+            let key: Vec<SqlValue> = keys
+                .iter()
+                .map(|key| match key {
+                    OrderKey::Result(position) => Ok(row_return[*position].clone()),
+                    OrderKey::Row(expr) => expr.eval(row, &names_columns),
+                })
+                .collect::<Result<_, QueryExecutionError>>()?;
+            Ok((key, RowValue { values: row_return }))
+        };
+        let exprs_order = order_keys.iter().filter_map(|key| match key {
+            OrderKey::Row(expr) => Some(expr),
+            OrderKey::Result(_) => None,
+        });
+        let exprs_all: Vec<&Expr> = exprs_return.iter().chain(exprs_order).collect();
+        let having = self
+            .having
+            .as_ref()
+            .map(|having| fold_constants(having, &columns))
+            .transpose()?;
+        let grouped = !self.group_by.is_empty()
+            || having.is_some()
+            || exprs_all.iter().any(|expr| expr.has_aggregate());
+        let projected: Box<dyn Iterator<Item = Result<_, QueryExecutionError>>> = if grouped {
+            let positions_group: Vec<usize> = self
+                .group_by
+                .iter()
+                .map(|name| {
+                    names_columns
+                        .iter()
+                        .position(|other| other == name)
+                        .ok_or_else(|| QueryExecutionError::ColumnDoesNotExist(name.clone()))
+                })
+                .collect::<Result<_, _>>()?;
+            // a column has the same value in every row of a group only if it is grouped by
+            let bare_columns = exprs_all
+                .iter()
+                .flat_map(|expr| expr.bare_columns())
+                .chain(having.iter().flat_map(|having| having.bare_columns()));
+            for name in bare_columns {
+                if !self.group_by.iter().any(|other| other == name) {
+                    return Err(QueryExecutionError::NotAggregated(name.to_string()));
+                }
+            }
+            let mut aggregates = Vec::new();
+            for expr in &exprs_all {
+                expr.collect_aggregates(&mut aggregates);
+            }
+            if let Some(having) = &having {
+                having.collect_aggregates(&mut aggregates);
+            }
+            let groups = group_rows(rows, &positions_group, &aggregates, &names_columns)?;
+            if count_rows {
+                if let [(_, accumulators)] = groups.as_slice() {
+                    if let SqlValue::BigInt(count) = accumulators[0].finish()? {
+                        return Ok(ExecuteResponse::Count(count as usize));
                     }
                 }
-                for row_mapping in rows_mapping {
-                    let mut row_return: Vec<SqlValue> = Vec::new();
-                    for column_return in &columns_return {
-                        let row_mapping_values = row_mapping.values.clone();
-                        let columns_clone = columns.clone();
-                        let uindex = columns_clone
-                            .iter()
-                            .position(|r_name| r_name.name == column_return.name)
-                            .unwrap();
-                        row_return.push(row_mapping_values[uindex].clone());
+            }
+            // the result row of a group, None if it does not meet HAVING
+            let finish_group = |row: RowValue, accumulators: Vec<Accumulator>| {
+                let values: Vec<SqlValue> = accumulators
+                    .iter()
+                    .map(Accumulator::finish)
+                    .collect::<Result<_, _>>()?;
+                if let Some(having) = &having {
+                    let having = having.replace_aggregates(&aggregates, &values);
+                    if compare_condition(&having, &row, &names_columns)? != Some(true) {
+                        return Ok(None);
                     }
-                    let rowvalue_return = RowValue { values: row_return };
-                    rows_return.push(rowvalue_return)
                 }
-                let sqltable_return = SqlTable {
-                    columns: columns_return,
-                    rows: rows_return,
-                };
-                let box_sqltable_return: Box<SqlTable> = Box::new(sqltable_return);
-                return Ok(ExecuteResponse::View(box_sqltable_return));
+                let exprs: Vec<Expr> = exprs_return
+                    .iter()
+                    .map(|expr| expr.replace_aggregates(&aggregates, &values))
+                    .collect();
+                let keys: Vec<OrderKey> = order_keys
+                    .iter()
+                    .map(|key| match key {
+                        OrderKey::Result(position) => OrderKey::Result(*position),
+                        OrderKey::Row(expr) => {
+                            OrderKey::Row(expr.replace_aggregates(&aggregates, &values))
+                        }
+                    })
+                    .collect();
+                project(&exprs, &keys, &row).map(Some)
+            };
+            let results: Vec<_> = groups
+                .into_iter()
+                .filter_map(|(row, accumulators)| finish_group(row, accumulators).transpose())
+                .collect();
+            Box::new(results.into_iter())
+        } else {
+            Box::new(rows.map(|row| project(&exprs_return, &order_keys, &row?.1)))
+        };
+        // without ORDER BY the rows past the limit are never read
+        let projected = match (&sort, self.limit) {
+            (None, Some(limit)) => Box::new(limit_rows(projected, limit)),
+            _ => projected,
+        };
+        for projected in projected {
+            let (key, row_return) = projected?;
+            match &mut sort {
+                Some(sort) => sort.push(key, row_return).map_err(sort_fail)?,
+                None => rows_return.push(row_return),
             }
-            Err(err) => match err.kind() {
-                std::io::ErrorKind::NotFound => Err(QueryExecutionError::TableNotFound(table_name)),
-                _ => Err(QueryExecutionError::TableOpenfail(table_name)),
-            },
         }
+        // the sort is stable, so rows with equal keys keep the order of the table
+        if let Some(sort) = sort {
+            let sorted = sort
+                .finish()
+                .map_err(sort_fail)?
+                .map(|entry| entry.map(|(_, row)| row).map_err(sort_fail));
+            rows_return = match self.limit {
+                Some(limit) => limit_rows(sorted, limit).collect::<Result<_, _>>()?,
+                None => sorted.collect::<Result<_, _>>()?,
+            };
+        }
+        let sqltable_return = SqlTable {
+            columns: columns_return,
+            rows: rows_return,
+        };
+        Ok(ExecuteResponse::View(Box::new(sqltable_return)))
     }
 }
 
 impl Executable for UpdateStatement {
     // Replace Record
-    fn check_and_execute(
+    fn check_and_execute<S: StorageEngine + ?Sized>(
         self,
-        storage_util: StoreUtil,
+        storage: &mut S,
     ) -> Result<ExecuteResponse, QueryExecutionError> {
-        let table_name = self.table.clone();
+        let table_name = self.table;
         let wc = match self.constraints {
             Some(wc_tmp) => wc_tmp,
             None => {
                 return Err(QueryExecutionError::NoConditionsObtained());
             }
         };
-        let sets_new = self.sets;
-        match storage_util.load(table_name.clone()) {
-            //check the result of loading
-            Ok(table) => {
-                let rows_old: Vec<RowValue> = table.rows;
-                let columns_old = table.columns;
-                let mut names_old: Vec<String> = Vec::new();
-                for column_old in &columns_old {
-                    names_old.push(column_old.name.clone());
-                }
-                let mut rows_new: Vec<RowValue> = Vec::new();
-                for row_old in rows_old {
-                    if compare_condition(wc.clone(), &row_old, &names_old) {
-                        let mut row_new: Vec<SqlValue> = Vec::new();
-                        let mut row_old_value = row_old.values.clone();
-                        for name_old in &names_old {
-                            let mut flag = 1;
-                            for set_new in &sets_new {
-                                if name_old == &set_new.column {
-                                    flag = 0;
-                                    row_new.push(set_new.value.clone());
-                                    row_old_value.drain(0..1);
-                                    break;
-                                }
-                            }
-                            if flag == 1 {
-                                row_new.push(row_old_value[0].clone());
-                                row_old_value.drain(0..1);
-                            }
+        let sets_new = self
+            .sets
+            .into_iter()
+            .map(|set_new| {
+                Ok(SetItem {
+                    column: set_new.column,
+                    value: fold_constant(set_new.value)?,
+                })
+            })
+            .collect::<Result<Vec<_>, QueryExecutionError>>()?;
+        let (columns, rows_old) = matching_rows(storage, &table_name, Some(&wc))?;
+        let names: Vec<String> = columns.iter().map(|column| column.name.clone()).collect();
+        let rows_new: Vec<(RowId, RowValue)> = rows_old
+            .iter()
+            .map(|(id, row_old)| {
+                // the new values are computed from the old row
+                let row_new: Vec<SqlValue> = columns
+                    .iter()
+                    .zip(row_old.values.iter().cloned())
+                    .map(|(column, value_old)| {
+                        match sets_new
+                            .iter()
+                            .find(|set_new| set_new.column == column.name)
+                        {
+                            Some(set_new) => set_new.value.eval(row_old, &names),
+                            None => Ok(value_old),
                         }
-                        let row_add = RowValue { values: row_new };
-                        rows_new.push(row_add.clone());
-                    } else {
-                        rows_new.push(row_old.clone());
-                    }
-                }
-                let table_new = SqlTable {
-                    columns: columns_old,
-                    rows: rows_new,
-                };
-                match storage_util.save(table_name.clone(), &table_new) {
-                    Ok(_) => Ok(ExecuteResponse::Message("update final".to_string())),
-                    Err(_) => Err(QueryExecutionError::TableSavefail(table_name)),
-                }
-            }
-            Err(err) => match err.kind() {
-                std::io::ErrorKind::NotFound => Err(QueryExecutionError::TableNotFound(table_name)),
-                _ => Err(QueryExecutionError::TableOpenfail(table_name)),
-            },
+                    })
+                    .collect::<Result<_, _>>()?;
+                Ok((*id, coerce_row(&columns, RowValue { values: row_new })?))
+            })
+            .collect::<Result<_, QueryExecutionError>>()?;
+        let rows: Vec<(Option<RowId>, &RowValue)> =
+            rows_new.iter().map(|(id, row)| (Some(*id), row)).collect();
+        let changed =
+            |column: &Column| sets_new.iter().any(|set_new| set_new.column == column.name);
+        check_constraints(storage, &table_name, &columns, &rows, changed)?;
+        // only a primary key or a unique column is referred to
+        let key_changed = columns.iter().any(|column| {
+            (column.constraints.primary_key || column.constraints.unique) && changed(column)
+        });
+        if key_changed {
+            check_referenced(storage, &table_name, &columns, &rows_old, &rows_new)?;
+        }
+        match storage.replace_rows(&table_name, rows_new) {
+            Ok(_) => Ok(ExecuteResponse::Message("update final".to_string())),
+            Err(err) => Err(save_error(err, table_name)),
+        }
+    }
+}
+
+impl Executable for CreateIndexStatement {
+    // create an index on columns of a table
+    fn check_and_execute<S: StorageEngine + ?Sized>(
+        self,
+        storage: &mut S,
+    ) -> Result<ExecuteResponse, QueryExecutionError> {
+        let columns = storage
+            .scan(&self.table)
+            .map_err(|err| open_error(err, self.table.clone()))?
+            .columns;
+        if let Some(name) = compare_name(&self.columns, &columns) {
+            return Err(QueryExecutionError::ColumnDoesNotExist(name));
+        }
+        let index = IndexInfo {
+            name: self.name,
+            table: self.table,
+            columns: self.columns,
+            unique: self.unique,
+            kind: self.kind,
+        };
+        match storage.create_index(&index) {
+            Ok(()) => Ok(ExecuteResponse::Message(format!(
+                "create index {} successful",
+                index.name
+            ))),
+            Err(err) => Err(index_error(err, index.name)),
+        }
+    }
+}
+
+impl Executable for DropIndexStatement {
+    // delete an index
+    fn check_and_execute<S: StorageEngine + ?Sized>(
+        self,
+        storage: &mut S,
+    ) -> Result<ExecuteResponse, QueryExecutionError> {
+        match storage.drop_index(&self.name) {
+            Ok(()) => Ok(ExecuteResponse::Message(format!(
+                "drop index {} successful",
+                self.name
+            ))),
+            Err(err) => Err(index_error(err, self.name)),
         }
     }
 }
 
 impl Executable for SqlQuery {
-    fn check_and_execute(
+    fn check_and_execute<S: StorageEngine + ?Sized>(
         self,
-        storage_util: StoreUtil,
+        storage: &mut S,
     ) -> Result<ExecuteResponse, QueryExecutionError> {
-        match self {
-            SqlQuery::Create(stmt) => stmt.check_and_execute(storage_util),
-            SqlQuery::Drop(stmt) => stmt.check_and_execute(storage_util),
-            SqlQuery::Insert(stmt) => stmt.check_and_execute(storage_util),
-            SqlQuery::Delete(stmt) => stmt.check_and_execute(storage_util),
-            SqlQuery::Update(stmt) => stmt.check_and_execute(storage_util),
-            SqlQuery::Select(stmt) => stmt.check_and_execute(storage_util),
+        let table_name = match &self {
+            SqlQuery::Select(stmt) => {
+                let _lock = storage
+                    .lock(&[&stmt.table], LockMode::Shared)
+                    .map_err(|err| lock_error(err, stmt.table.clone()))?;
+                return stmt.clone().check_and_execute(storage);
+            }
+            SqlQuery::Create(CreateStatement { table, .. })
+            | SqlQuery::Drop(DropStatement { table })
+            | SqlQuery::Insert(InsertStatement { table, .. })
+            | SqlQuery::Delete(DeleteStatement { table, .. })
+            | SqlQuery::Update(UpdateStatement { table, .. })
+            | SqlQuery::CreateIndex(CreateIndexStatement { table, .. }) => table.clone(),
+            // the lock of the data directory is enough, the table of the index is not known yet
+            SqlQuery::DropIndex(DropIndexStatement { name }) => name.clone(),
+            SqlQuery::Begin
+            | SqlQuery::Commit
+            | SqlQuery::Rollback
+            | SqlQuery::Savepoint(_)
+            | SqlQuery::Release(_)
+            | SqlQuery::RollbackTo(_) => {
+                return Err(QueryExecutionError::TransactionOutsideSession())
+            }
+        };
+        let tables: &[&str] = match &self {
+            SqlQuery::DropIndex(_) => &[],
+            _ => &[&table_name],
+        };
+        let _lock = storage
+            .lock(tables, LockMode::Exclusive)
+            .map_err(|err| lock_error(err, table_name.clone()))?;
+        // the changes of a statement are written to the storage atomically
+        storage
+            .begin()
+            .map_err(|_| QueryExecutionError::TableSavefail(table_name.clone()))?;
+        let result = match self {
+            SqlQuery::Create(stmt) => stmt.check_and_execute(storage),
+            SqlQuery::Drop(stmt) => stmt.check_and_execute(storage),
+            SqlQuery::Insert(stmt) => stmt.check_and_execute(storage),
+            SqlQuery::Delete(stmt) => stmt.check_and_execute(storage),
+            SqlQuery::Update(stmt) => stmt.check_and_execute(storage),
+            SqlQuery::CreateIndex(stmt) => stmt.check_and_execute(storage),
+            SqlQuery::DropIndex(stmt) => stmt.check_and_execute(storage),
+            SqlQuery::Select(stmt) => stmt.check_and_execute(storage),
+            _ => unreachable!(),
+        };
+        match result {
+            Ok(response) => match storage.commit() {
+                Ok(()) => Ok(response),
+                Err(err) => Err(save_error(err, table_name)),
+            },
+            Err(err) => match storage.rollback() {
+                Ok(()) => Err(err),
+                Err(err) => Err(save_error(err, table_name)),
+            },
         }
     }
 }
 
-impl Into<String> for SqlValue {
-    fn into(self) -> String {
-        match self {
+impl From<SqlValue> for String {
+    fn from(value: SqlValue) -> Self {
+        match value {
             SqlValue::String(s) => s,
-            SqlValue::Int(i) => i.to_string(),
-            _ => String::from("Unknow"),
+            other => other.to_string(),
         }
     }
 }
@@ -514,10 +1550,12 @@ mod tests {
                 Column {
                     name: "id".into(),
                     type_info: SqlType::Int,
+                    constraints: ColumnConstraints::default(),
                 },
                 Column {
                     name: "des".into(),
                     type_info: SqlType::String,
+                    constraints: ColumnConstraints::default(),
                 },
             ],
             rows: vec![
@@ -544,30 +1582,36 @@ mod tests {
 
 #[cfg(test)]
 mod tests_create {
+    use super::super::super::storage::MemoryStorage;
     use super::*;
     #[test]
     fn test_check_and_execute_success() {
         let col1 = Column {
             name: "col1".to_string(),
             type_info: SqlType::Int,
+            constraints: ColumnConstraints::default(),
         };
         let col2 = Column {
             name: "col2".to_string(),
             type_info: SqlType::String,
+            constraints: ColumnConstraints::default(),
         };
         let col3 = Column {
             name: "col3".to_string(),
             type_info: SqlType::Unknown,
+            constraints: ColumnConstraints::default(),
         };
 
         let create_statement = CreateStatement {
             table: "test_table_new".to_string(),
             columns: vec![col1, col2, col3],
+            primary_key: Vec::new(),
+            foreign_keys: Vec::new(),
         };
 
-        let store_util = StoreUtil::Csv(r"E:\git_commits\rust_db".to_string());
+        let mut store_util = MemoryStorage::new();
 
-        match create_statement.check_and_execute(store_util) {
+        match create_statement.check_and_execute(&mut store_util) {
             Ok(response) => {
                 assert_eq!(
                     response,
@@ -583,6 +1627,7 @@ mod tests_create {
 
 #[cfg(test)]
 mod tests_drop {
+    use super::super::super::storage::{MemoryStorage, StorageEngine};
     use super::*;
     #[test]
     fn test_check_and_execute_success() {
@@ -590,9 +1635,12 @@ mod tests_drop {
             table: "test_table_drop".to_string(),
         };
 
-        let store_util = StoreUtil::Csv(r"E:\git_commits\rust_db".to_string());
+        let mut store_util = MemoryStorage::new();
+        store_util
+            .save("test_table_drop", &SqlTable::default())
+            .unwrap();
 
-        match drop_statement.check_and_execute(store_util) {
+        match drop_statement.check_and_execute(&mut store_util) {
             Ok(response) => {
                 assert_eq!(
                     response,
@@ -608,6 +1656,7 @@ mod tests_drop {
 
 #[cfg(test)]
 mod tests_insert {
+    use super::super::super::storage::{MemoryStorage, StorageEngine};
     use super::*;
     #[test]
     fn test_check_and_execute_success() {
@@ -618,18 +1667,36 @@ mod tests_insert {
                 String::from("col2"),
                 String::from("col3"),
             ]),
-            values: RowValue {
-                values: vec![
-                    SqlValue::Int(123),
-                    SqlValue::String(String::from("abc")),
-                    SqlValue::Unknown,
-                ],
-            },
+            values: vec![
+                Expr::Value(SqlValue::Int(123)),
+                Expr::Value(SqlValue::String(String::from("abc"))),
+                Expr::Value(SqlValue::Null),
+            ],
         };
 
-        let store_util = StoreUtil::Csv(r"E:\git_commits\rust_db".to_string());
+        let mut store_util = MemoryStorage::new();
+        let columns = vec![
+            Column {
+                name: "col1".to_string(),
+                type_info: SqlType::Int,
+                constraints: ColumnConstraints::default(),
+            },
+            Column {
+                name: "col2".to_string(),
+                type_info: SqlType::String,
+                constraints: ColumnConstraints::default(),
+            },
+            Column {
+                name: "col3".to_string(),
+                type_info: SqlType::Unknown,
+                constraints: ColumnConstraints::default(),
+            },
+        ];
+        store_util
+            .save("test_table", &SqlTable::new(columns))
+            .unwrap();
 
-        match expected.check_and_execute(store_util) {
+        match expected.check_and_execute(&mut store_util) {
             Ok(response) => {
                 assert_eq!(
                     response,
@@ -642,3 +1709,1162 @@ mod tests_insert {
         }
     }
 }
+
+#[cfg(test)]
+mod tests_query {
+    use super::super::super::sql_analyzer::parser::Parse;
+    use super::super::super::storage::MemoryStorage;
+    use super::super::sort;
+    use super::*;
+    use chrono::{Local, NaiveDate, NaiveTime};
+    use rust_decimal::Decimal;
+
+    fn run(storage: &mut MemoryStorage, sql: &str) -> ExecuteResponse {
+        SqlQuery::parse_format_error(sql)
+            .unwrap()
+            .check_and_execute(storage)
+            .unwrap()
+    }
+
+    fn select_ids(storage: &mut MemoryStorage, sql: &str) -> Vec<SqlValue> {
+        match run(storage, sql) {
+            ExecuteResponse::View(table) => table
+                .rows
+                .into_iter()
+                .map(|row| row.values[0].clone())
+                .collect(),
+            res => panic!("Expected View but got {res:?}"),
+        }
+    }
+
+    #[test]
+    fn test_select_update_delete() {
+        let mut storage = MemoryStorage::new();
+        run(&mut storage, "CREATE TABLE foo (id int, name string);");
+        run(&mut storage, "INSERT INTO foo VALUES (1, 'a');");
+        run(&mut storage, "INSERT INTO foo VALUES (2, 'b');");
+        run(&mut storage, "INSERT INTO foo (id) VALUES (3);");
+
+        assert_eq!(
+            select_ids(&mut storage, "SELECT id FROM foo WHERE id >= 2;"),
+            vec![SqlValue::Int(2), SqlValue::Int(3)]
+        );
+        run(&mut storage, "UPDATE foo SET id = 4 WHERE name = 'b';");
+        assert_eq!(
+            select_ids(&mut storage, "SELECT id, name FROM foo WHERE name = 'b';"),
+            vec![SqlValue::Int(4)]
+        );
+        run(&mut storage, "DELETE FROM foo WHERE id < 4;");
+        assert_eq!(
+            select_ids(&mut storage, "SELECT * FROM foo;"),
+            vec![SqlValue::Int(4)]
+        );
+    }
+
+    #[test]
+    fn test_index() {
+        let mut storage = MemoryStorage::new();
+        run(&mut storage, "CREATE TABLE foo (id int, name string);");
+        for id in 0..20 {
+            run(
+                &mut storage,
+                &format!("INSERT INTO foo VALUES ({id}, 'n{id}');"),
+            );
+        }
+        run(&mut storage, "CREATE UNIQUE INDEX foo_id ON foo (id);");
+        assert_eq!(
+            select_ids(
+                &mut storage,
+                "SELECT id FROM foo WHERE id > 15 AND name <> 'n17';"
+            ),
+            vec![SqlValue::Int(16), SqlValue::Int(18), SqlValue::Int(19)]
+        );
+        let query = SqlQuery::parse_format_error("INSERT INTO foo VALUES (3, 'x');").unwrap();
+        assert!(matches!(
+            query.check_and_execute(&mut storage),
+            Err(QueryExecutionError::UniqueViolation(index)) if index == "foo_id"
+        ));
+
+        // the index follows the changes of the rows
+        run(&mut storage, "UPDATE foo SET id = 30 WHERE id = 3;");
+        run(&mut storage, "DELETE FROM foo WHERE id < 10;");
+        assert_eq!(
+            select_ids(&mut storage, "SELECT id FROM foo WHERE id <= 10;"),
+            vec![SqlValue::Int(10)]
+        );
+        assert_eq!(
+            select_ids(&mut storage, "SELECT id FROM foo WHERE id = 30;"),
+            vec![SqlValue::Int(30)]
+        );
+
+        run(&mut storage, "DROP INDEX foo_id;");
+        let query = SqlQuery::parse_format_error("DROP INDEX foo_id;").unwrap();
+        assert!(matches!(
+            query.check_and_execute(&mut storage),
+            Err(QueryExecutionError::IndexNotFound(_))
+        ));
+        run(&mut storage, "INSERT INTO foo VALUES (30, 'x');");
+    }
+
+    #[test]
+    fn test_hash_index() {
+        let mut storage = MemoryStorage::new();
+        run(&mut storage, "CREATE TABLE foo (id int, name string);");
+        for id in 0..200 {
+            run(
+                &mut storage,
+                &format!("INSERT INTO foo VALUES ({id}, 'n{id}');"),
+            );
+        }
+        run(
+            &mut storage,
+            "CREATE UNIQUE INDEX foo_id ON foo USING HASH (id);",
+        );
+        assert_eq!(
+            select_ids(&mut storage, "SELECT id FROM foo WHERE id = 123;"),
+            vec![SqlValue::Int(123)]
+        );
+        // ranges are not served by a hash index
+        assert_eq!(
+            select_ids(&mut storage, "SELECT id FROM foo WHERE id > 197;").len(),
+            2
+        );
+        let query = SqlQuery::parse_format_error("UPDATE foo SET id = 5 WHERE id = 6;").unwrap();
+        assert!(matches!(
+            query.check_and_execute(&mut storage),
+            Err(QueryExecutionError::UniqueViolation(_))
+        ));
+    }
+
+    #[test]
+    fn test_primary_key() {
+        let mut storage = MemoryStorage::new();
+        run(
+            &mut storage,
+            "CREATE TABLE foo (id int PRIMARY KEY, name string);",
+        );
+        run(&mut storage, "INSERT INTO foo VALUES (1, 'a');");
+        run(&mut storage, "INSERT INTO foo VALUES (2, 'b');");
+        for sql in [
+            "INSERT INTO foo VALUES (1, 'c');",
+            "UPDATE foo SET id = 2 WHERE id = 1;",
+            "UPDATE foo SET id = 3 WHERE id > 0;",
+        ] {
+            let query = SqlQuery::parse_format_error(sql).unwrap();
+            assert!(matches!(
+                query.check_and_execute(&mut storage),
+                Err(QueryExecutionError::PrimaryKeyViolation(table)) if table == "foo"
+            ));
+        }
+        run(&mut storage, "UPDATE foo SET id = 3 WHERE id = 1;");
+        run(&mut storage, "UPDATE foo SET name = 'c' WHERE id > 0;");
+
+        // a composite key only rejects rows equal on every column of the key
+        run(
+            &mut storage,
+            "CREATE TABLE bar (a int, b int, PRIMARY KEY (a, b));",
+        );
+        run(&mut storage, "INSERT INTO bar VALUES (1, 1);");
+        run(&mut storage, "INSERT INTO bar VALUES (1, 2);");
+        let query = SqlQuery::parse_format_error("INSERT INTO bar VALUES (1, 2);").unwrap();
+        assert!(query.check_and_execute(&mut storage).is_err());
+
+        let query = SqlQuery::parse_format_error(
+            "CREATE TABLE baz (a int PRIMARY KEY, b int, PRIMARY KEY (b));",
+        )
+        .unwrap();
+        assert!(matches!(
+            query.check_and_execute(&mut storage),
+            Err(QueryExecutionError::MultiplePrimaryKeys(_))
+        ));
+    }
+
+    #[test]
+    fn test_column_constraints() {
+        let mut storage = MemoryStorage::new();
+        run(
+            &mut storage,
+            "CREATE TABLE foo (id int NOT NULL, code int UNIQUE, age int DEFAULT 18 CHECK (age > 0));",
+        );
+        run(&mut storage, "INSERT INTO foo (id, code) VALUES (1, 10);");
+        let table = storage.load("foo").unwrap();
+        assert_eq!(table.rows[0].values[2], SqlValue::Int(18));
+        // NULL is not equal to NULL
+        run(&mut storage, "INSERT INTO foo (id) VALUES (2);");
+        run(&mut storage, "INSERT INTO foo (id) VALUES (3);");
+
+        let query = SqlQuery::parse_format_error("INSERT INTO foo (code) VALUES (11);").unwrap();
+        assert!(matches!(
+            query.check_and_execute(&mut storage),
+            Err(QueryExecutionError::NotNullViolation(column)) if column == "id"
+        ));
+        for sql in [
+            "INSERT INTO foo VALUES (4, 10, 20);",
+            "UPDATE foo SET code = 10 WHERE id = 2;",
+        ] {
+            let query = SqlQuery::parse_format_error(sql).unwrap();
+            assert!(matches!(
+                query.check_and_execute(&mut storage),
+                Err(QueryExecutionError::UniqueViolation(index)) if index == "foo_code_key"
+            ));
+        }
+        for sql in [
+            "INSERT INTO foo VALUES (4, 12, 0);",
+            "UPDATE foo SET age = 0 WHERE id = 1;",
+        ] {
+            let query = SqlQuery::parse_format_error(sql).unwrap();
+            assert!(matches!(
+                query.check_and_execute(&mut storage),
+                Err(QueryExecutionError::CheckViolation(column)) if column == "age"
+            ));
+        }
+        run(&mut storage, "UPDATE foo SET code = 12 WHERE id = 2;");
+        assert_eq!(storage.load("foo").unwrap().rows.len(), 3);
+    }
+
+    #[test]
+    fn test_insert_value_count() {
+        let mut storage = MemoryStorage::new();
+        run(&mut storage, "CREATE TABLE t (id int, name string);");
+        for (sql, given, expected) in [
+            ("INSERT INTO t (id, name) VALUES (5);", 1, 2),
+            ("INSERT INTO t (id) VALUES (5, 'a');", 2, 1),
+            ("INSERT INTO t VALUES (5);", 1, 2),
+            ("INSERT INTO t VALUES (5, 'a', 'b');", 3, 2),
+        ] {
+            let query = SqlQuery::parse_format_error(sql).unwrap();
+            match query.check_and_execute(&mut storage) {
+                Err(QueryExecutionError::ValueCountMismatch(count, width)) => {
+                    assert_eq!((count, width), (given, expected))
+                }
+                res => panic!("Expected ValueCountMismatch but got {res:?}"),
+            }
+        }
+        assert!(storage.load("t").unwrap().rows.is_empty());
+        run(&mut storage, "INSERT INTO t (name) VALUES ('a');");
+        assert_eq!(
+            storage.load("t").unwrap().rows[0].values,
+            vec![SqlValue::Null, SqlValue::String(String::from("a"))]
+        );
+    }
+
+    #[test]
+    fn test_foreign_key() {
+        let mut storage = MemoryStorage::new();
+        run(
+            &mut storage,
+            "CREATE TABLE users (id int PRIMARY KEY, name string);",
+        );
+        run(
+            &mut storage,
+            "CREATE TABLE posts (id int PRIMARY KEY, author int REFERENCES users(id) ON DELETE CASCADE);",
+        );
+        run(
+            &mut storage,
+            "CREATE TABLE likes (post int REFERENCES posts(id), editor int, FOREIGN KEY (editor) REFERENCES users(id) ON DELETE SET NULL);",
+        );
+        for sql in [
+            "INSERT INTO users VALUES (1, 'a');",
+            "INSERT INTO users VALUES (2, 'b');",
+            "INSERT INTO posts VALUES (10, 1);",
+            "INSERT INTO posts VALUES (20, 2);",
+            "INSERT INTO posts (id) VALUES (30);",
+            "INSERT INTO likes VALUES (20, 1);",
+        ] {
+            run(&mut storage, sql);
+        }
+        for sql in [
+            "INSERT INTO posts VALUES (40, 3);",
+            "UPDATE likes SET post = 50 WHERE post = 20;",
+        ] {
+            let query = SqlQuery::parse_format_error(sql).unwrap();
+            assert!(matches!(
+                query.check_and_execute(&mut storage),
+                Err(QueryExecutionError::ForeignKeyViolation(_))
+            ));
+        }
+        // the user of a post cannot lose its id, and a liked post cannot be deleted
+        for sql in [
+            "UPDATE users SET id = 3 WHERE id = 2;",
+            "DELETE FROM users WHERE id = 2;",
+            "DELETE FROM posts WHERE id = 20;",
+        ] {
+            let query = SqlQuery::parse_format_error(sql).unwrap();
+            assert!(matches!(
+                query.check_and_execute(&mut storage),
+                Err(QueryExecutionError::RowReferenced(_, _))
+            ));
+        }
+        let query = SqlQuery::parse_format_error("DROP TABLE users;").unwrap();
+        assert!(matches!(
+            query.check_and_execute(&mut storage),
+            Err(QueryExecutionError::TableReferenced(table, _)) if table == "users"
+        ));
+
+        // the posts of the user are deleted and the likes edited by the user lose their editor
+        run(&mut storage, "DELETE FROM users WHERE id = 1;");
+        assert_eq!(
+            select_ids(&mut storage, "SELECT id FROM posts WHERE id > 0;"),
+            vec![SqlValue::Int(20), SqlValue::Int(30)]
+        );
+        assert_eq!(
+            storage.load("likes").unwrap().rows[0].values,
+            vec![SqlValue::Int(20), SqlValue::Null]
+        );
+
+        let query =
+            SqlQuery::parse_format_error("CREATE TABLE bad (name string REFERENCES users(name));")
+                .unwrap();
+        assert!(matches!(
+            query.check_and_execute(&mut storage),
+            Err(QueryExecutionError::InvalidForeignKey(_))
+        ));
+    }
+
+    #[test]
+    fn test_self_reference() {
+        let mut storage = MemoryStorage::new();
+        run(
+            &mut storage,
+            "CREATE TABLE nodes (id int PRIMARY KEY, parent int REFERENCES nodes(id) ON DELETE CASCADE);",
+        );
+        run(&mut storage, "INSERT INTO nodes VALUES (1, 1);");
+        run(&mut storage, "INSERT INTO nodes VALUES (2, 1);");
+        run(&mut storage, "INSERT INTO nodes VALUES (3, 2);");
+        run(&mut storage, "INSERT INTO nodes VALUES (4, 4);");
+        run(&mut storage, "DELETE FROM nodes WHERE id = 1;");
+        assert_eq!(
+            select_ids(&mut storage, "SELECT id FROM nodes WHERE id > 0;"),
+            vec![SqlValue::Int(4)]
+        );
+    }
+
+    #[test]
+    fn test_null() {
+        let mut storage = MemoryStorage::new();
+        run(&mut storage, "CREATE TABLE foo (id int, value int);");
+        run(&mut storage, "CREATE INDEX foo_value ON foo (value);");
+        run(&mut storage, "INSERT INTO foo VALUES (1, 10);");
+        run(&mut storage, "INSERT INTO foo VALUES (2, NULL);");
+        run(&mut storage, "INSERT INTO foo (id) VALUES (3);");
+        let ids = |storage: &mut MemoryStorage, wc: &str| {
+            select_ids(storage, &format!("SELECT id FROM foo WHERE {wc};"))
+        };
+        assert_eq!(
+            ids(&mut storage, "value IS NULL"),
+            vec![SqlValue::Int(2), SqlValue::Int(3)]
+        );
+        assert_eq!(
+            ids(&mut storage, "value IS NOT NULL"),
+            vec![SqlValue::Int(1)]
+        );
+        // a comparison with NULL is unknown, and so is its negation
+        assert!(ids(&mut storage, "value = NULL").is_empty());
+        assert_eq!(ids(&mut storage, "value <> 5"), vec![SqlValue::Int(1)]);
+        assert_eq!(ids(&mut storage, "NOT value = 5"), vec![SqlValue::Int(1)]);
+        // unknown OR true is true, unknown AND false is false
+        assert_eq!(
+            ids(&mut storage, "value > 5 OR id > 1"),
+            vec![SqlValue::Int(1), SqlValue::Int(2), SqlValue::Int(3)]
+        );
+        assert_eq!(
+            ids(&mut storage, "id > 1 AND NOT value > 5"),
+            Vec::<SqlValue>::new()
+        );
+        assert_eq!(
+            ids(&mut storage, "id < 3 AND value IS NULL"),
+            vec![SqlValue::Int(2)]
+        );
+    }
+
+    #[test]
+    fn test_numeric_types() {
+        let mut storage = MemoryStorage::new();
+        run(
+            &mut storage,
+            "CREATE TABLE metrics (id int, total bigint, ratio double, active boolean);",
+        );
+        run(
+            &mut storage,
+            "CREATE INDEX metrics_total ON metrics USING HASH (total);",
+        );
+        run(
+            &mut storage,
+            "CREATE INDEX metrics_ratio ON metrics (ratio);",
+        );
+        run(
+            &mut storage,
+            "INSERT INTO metrics VALUES (1, 5000000000, 0.25, true);",
+        );
+        // the literals are converted to the types of the columns
+        run(&mut storage, "INSERT INTO metrics VALUES (2, 7, 1, false);");
+        let table = storage.load("metrics").unwrap();
+        assert_eq!(
+            table.rows[1].values,
+            vec![
+                SqlValue::Int(2),
+                SqlValue::BigInt(7),
+                SqlValue::Double(1.0),
+                SqlValue::Boolean(false)
+            ]
+        );
+        let ids = |storage: &mut MemoryStorage, wc: &str| {
+            select_ids(storage, &format!("SELECT id FROM metrics WHERE {wc};"))
+        };
+        assert_eq!(ids(&mut storage, "total = 7"), vec![SqlValue::Int(2)]);
+        assert_eq!(
+            ids(&mut storage, "total > 2147483647"),
+            vec![SqlValue::Int(1)]
+        );
+        assert_eq!(ids(&mut storage, "ratio < 0.5"), vec![SqlValue::Int(1)]);
+        assert_eq!(ids(&mut storage, "ratio = 1"), vec![SqlValue::Int(2)]);
+        assert_eq!(ids(&mut storage, "active = TRUE"), vec![SqlValue::Int(1)]);
+        assert_eq!(ids(&mut storage, "id < 1.5"), vec![SqlValue::Int(1)]);
+
+        for sql in [
+            "INSERT INTO metrics VALUES (3000000000, 1, 1.0, true);",
+            "INSERT INTO metrics VALUES (3, 1.5, 1.0, true);",
+            "UPDATE metrics SET active = 1 WHERE id = 1;",
+        ] {
+            let query = SqlQuery::parse_format_error(sql).unwrap();
+            assert!(matches!(
+                query.check_and_execute(&mut storage),
+                Err(QueryExecutionError::TypeDoesNotMatch(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_dates() {
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+        let time = |h, m| NaiveTime::from_hms_opt(h, m, 0).unwrap();
+        let mut storage = MemoryStorage::new();
+        run(
+            &mut storage,
+            "CREATE TABLE events (id int, day date, at time, created timestamp DEFAULT NOW());",
+        );
+        run(&mut storage, "CREATE INDEX events_day ON events (day);");
+        // dates and times may be written as strings
+        run(
+            &mut storage,
+            "INSERT INTO events VALUES (1, '2024-01-31', '10:30', '2024-01-31 23:00:00');",
+        );
+        run(
+            &mut storage,
+            "INSERT INTO events VALUES (2, DATE '2024-02-29' + 1, TIME '23:30' + INTERVAL '1' HOUR, \
+             TIMESTAMP '2024-02-29 12:00:00' + INTERVAL '12' HOUR);",
+        );
+        let table = storage.load("events").unwrap();
+        assert_eq!(
+            table.rows[1].values,
+            vec![
+                SqlValue::Int(2),
+                SqlValue::Date(date(2024, 3, 1)),
+                SqlValue::Time(time(0, 30)),
+                SqlValue::Timestamp(date(2024, 3, 1).and_time(time(0, 0))),
+            ]
+        );
+        let ids = |storage: &mut MemoryStorage, wc: &str| {
+            select_ids(storage, &format!("SELECT id FROM events WHERE {wc};"))
+        };
+        assert_eq!(
+            ids(&mut storage, "day > '2024-02-01'"),
+            vec![SqlValue::Int(2)]
+        );
+        assert_eq!(
+            ids(&mut storage, "DATE '2024-02-01' > day"),
+            vec![SqlValue::Int(1)]
+        );
+        assert_eq!(
+            ids(&mut storage, "day + INTERVAL '1' MONTH = DATE '2024-02-29'"),
+            vec![SqlValue::Int(1)]
+        );
+        assert_eq!(
+            ids(&mut storage, "created >= DATE '2024-03-01'"),
+            vec![SqlValue::Int(2)]
+        );
+        assert_eq!(
+            ids(&mut storage, "at < TIME '12:00'"),
+            vec![SqlValue::Int(1), SqlValue::Int(2)]
+        );
+
+        let view = match run(
+            &mut storage,
+            "SELECT EXTRACT(YEAR FROM day), day - DATE '2024-01-01' AS days, at FROM events WHERE id = 2;",
+        ) {
+            ExecuteResponse::View(view) => view,
+            res => panic!("Expected View but got {res:?}"),
+        };
+        let names: Vec<&str> = view
+            .columns
+            .iter()
+            .map(|column| column.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["EXTRACT(YEAR FROM day)", "days", "at"]);
+        assert_eq!(
+            view.rows[0].values,
+            vec![
+                SqlValue::Int(2024),
+                SqlValue::Int(60),
+                SqlValue::Time(time(0, 30))
+            ]
+        );
+
+        // the new value is computed from the old row
+        run(
+            &mut storage,
+            "UPDATE events SET day = day + 1 WHERE id = 1;",
+        );
+        assert_eq!(
+            ids(&mut storage, "day = DATE '2024-02-01'"),
+            vec![SqlValue::Int(1)]
+        );
+        let before = Local::now().naive_local();
+        run(&mut storage, "INSERT INTO events (id) VALUES (3);");
+        let after = Local::now().naive_local();
+        let created = storage.load("events").unwrap().rows[2].values[3].clone();
+        assert!(matches!(created, SqlValue::Timestamp(t) if before <= t && t <= after));
+
+        for sql in [
+            "INSERT INTO events VALUES (4, '2024-13-01', NULL, NULL);",
+            "SELECT day + 'x' FROM events;",
+            "SELECT EXTRACT(YEAR FROM at) FROM events;",
+            "SELECT id FROM events WHERE at + INTERVAL '1' DAY > at;",
+        ] {
+            let query = SqlQuery::parse_format_error(sql).unwrap();
+            assert!(matches!(
+                query.check_and_execute(&mut storage),
+                Err(QueryExecutionError::TypeDoesNotMatch(_))
+            ));
+        }
+        let query =
+            SqlQuery::parse_format_error("SELECT day + INTERVAL '100000000' YEAR FROM events;")
+                .unwrap();
+        assert!(matches!(
+            query.check_and_execute(&mut storage),
+            Err(QueryExecutionError::ValueOutOfRange(_))
+        ));
+    }
+
+    #[test]
+    fn test_decimal() {
+        let mut storage = MemoryStorage::new();
+        run(
+            &mut storage,
+            "CREATE TABLE invoices (id int, amount decimal(10, 2), rate double);",
+        );
+        run(
+            &mut storage,
+            "CREATE INDEX invoices_amount ON invoices USING HASH (amount);",
+        );
+        // the values are rounded half away from zero to the scale of the column
+        for (id, amount) in [(1, "19.999"), (2, "0.005"), (3, "-0.005"), (4, "12345678")] {
+            run(
+                &mut storage,
+                &format!("INSERT INTO invoices VALUES ({id}, {amount}, 0.1);"),
+            );
+        }
+        let amounts: Vec<SqlValue> = storage
+            .load("invoices")
+            .unwrap()
+            .rows
+            .into_iter()
+            .map(|row| row.values[1].clone())
+            .collect();
+        assert_eq!(
+            amounts,
+            vec![
+                SqlValue::Decimal(Decimal::new(2000, 2)),
+                SqlValue::Decimal(Decimal::new(1, 2)),
+                SqlValue::Decimal(Decimal::new(-1, 2)),
+                SqlValue::Decimal(Decimal::new(1234567800, 2)),
+            ]
+        );
+        assert_eq!(amounts[0].to_string(), "20.00");
+        let ids = |storage: &mut MemoryStorage, wc: &str| {
+            select_ids(storage, &format!("SELECT id FROM invoices WHERE {wc};"))
+        };
+        assert_eq!(ids(&mut storage, "amount = 20"), vec![SqlValue::Int(1)]);
+        assert_eq!(ids(&mut storage, "amount = 0.010"), vec![SqlValue::Int(2)]);
+        assert_eq!(ids(&mut storage, "amount < 0"), vec![SqlValue::Int(3)]);
+
+        // the sums are exact, unlike with doubles
+        run(
+            &mut storage,
+            "UPDATE invoices SET amount = amount + 0.1 + 0.2 WHERE id = 1;",
+        );
+        assert_eq!(ids(&mut storage, "amount = 20.3"), vec![SqlValue::Int(1)]);
+        assert!(ids(&mut storage, "rate + 0.2 = 0.3").is_empty());
+        assert_eq!(
+            ids(&mut storage, "amount - 0.3 = 20 AND id = 1"),
+            vec![SqlValue::Int(1)]
+        );
+
+        for sql in [
+            "INSERT INTO invoices VALUES (5, 100000000, 0);",
+            "INSERT INTO invoices VALUES (5, 99999999.995, 0);",
+            "UPDATE invoices SET amount = amount + 99999999 WHERE id = 4;",
+        ] {
+            let query = SqlQuery::parse_format_error(sql).unwrap();
+            assert!(matches!(
+                query.check_and_execute(&mut storage),
+                Err(QueryExecutionError::ValueOutOfRange(_))
+            ));
+        }
+        let query =
+            SqlQuery::parse_format_error("INSERT INTO invoices VALUES (5, 'a', 0);").unwrap();
+        assert!(matches!(
+            query.check_and_execute(&mut storage),
+            Err(QueryExecutionError::TypeDoesNotMatch(_))
+        ));
+    }
+
+    #[test]
+    fn test_multiply_and_divide() {
+        let mut storage = MemoryStorage::new();
+        run(
+            &mut storage,
+            "CREATE TABLE n (id int, i int, b bigint, d decimal(20, 4), f double);",
+        );
+        run(&mut storage, "INSERT INTO n VALUES (1, 7, 7, 1.25, 0.5);");
+        let value = |storage: &mut MemoryStorage, expr: &str| {
+            let sql = format!("SELECT {expr} FROM n;");
+            match SqlQuery::parse_format_error(&sql)
+                .unwrap()
+                .check_and_execute(storage)
+            {
+                Ok(ExecuteResponse::View(table)) => Ok(table.rows[0].values[0].clone()),
+                Ok(res) => panic!("Expected View but got {res:?}"),
+                Err(err) => Err(err),
+            }
+        };
+        let decimal = |text: &str| SqlValue::Decimal(text.parse().unwrap());
+        // the integer quotient is truncated toward zero
+        assert_eq!(value(&mut storage, "i * 2 + 1").unwrap(), SqlValue::Int(15));
+        assert_eq!(
+            value(&mut storage, "(0 - i) / 2").unwrap(),
+            SqlValue::Int(-3)
+        );
+        assert_eq!(value(&mut storage, "b / 2").unwrap(), SqlValue::BigInt(3));
+        assert_eq!(value(&mut storage, "f * i").unwrap(), SqlValue::Double(3.5));
+        // the scale of a product is the sum of the scales
+        for (expr, expected) in [
+            ("d * 2", "2.5000"),
+            ("d * d", "1.56250000"),
+            ("d * 0.10", "0.125000"),
+        ] {
+            let product = value(&mut storage, expr).unwrap();
+            assert_eq!(product, decimal(expected));
+            assert_eq!(product.to_string(), expected);
+        }
+        // a quotient has 6 digits more than the larger scale, rounded half away from zero
+        for (expr, expected) in [
+            ("d / 3", "0.4166666667"),
+            ("i / 3.0", "2.3333333"),
+            ("-2 / 3.0", "-0.6666667"),
+            ("d / 0.5", "2.5000000000"),
+        ] {
+            let quotient = value(&mut storage, expr).unwrap();
+            assert_eq!(quotient.to_string(), expected);
+        }
+        run(&mut storage, "UPDATE n SET d = d * 3 / 2 WHERE id = 1;");
+        assert_eq!(value(&mut storage, "d").unwrap(), decimal("1.8750"));
+
+        for expr in ["i / 0", "d / 0.00", "f / 0", "NULL / 0 + i / (i - 7)"] {
+            assert!(matches!(
+                value(&mut storage, expr),
+                Err(QueryExecutionError::DivisionByZero(_))
+            ));
+        }
+        for expr in [
+            "b * 9223372036854775807",
+            "(0 - 9223372036854775807 - 1) / -1",
+            "7922816251426433759354395033.5 * 20",
+            "0.00000000000001 * 0.000000000000001",
+        ] {
+            assert!(matches!(
+                value(&mut storage, expr),
+                Err(QueryExecutionError::ValueOutOfRange(_))
+            ));
+        }
+        assert_eq!(value(&mut storage, "NULL * d").unwrap(), SqlValue::Null);
+        assert!(matches!(
+            value(&mut storage, "'a' * 2"),
+            Err(QueryExecutionError::TypeDoesNotMatch(_))
+        ));
+    }
+
+    #[test]
+    fn test_string_types() {
+        let mut storage = MemoryStorage::new();
+        run(
+            &mut storage,
+            "CREATE TABLE users (id int, code char(3), name varchar(5), photo blob);",
+        );
+        run(&mut storage, "CREATE INDEX users_code ON users (code);");
+        run(
+            &mut storage,
+            "INSERT INTO users VALUES (1, 'ab', 'alice', X'00FF');",
+        );
+        // spaces past the length are cut, a length counts characters
+        run(
+            &mut storage,
+            "INSERT INTO users VALUES (2, 'xyz   ', 'héllo  ', X'');",
+        );
+        let table = storage.load("users").unwrap();
+        assert_eq!(
+            table.rows[0].values,
+            vec![
+                SqlValue::Int(1),
+                SqlValue::String(String::from("ab ")),
+                SqlValue::String(String::from("alice")),
+                SqlValue::Blob(vec![0x00, 0xff]),
+            ]
+        );
+        assert_eq!(
+            table.rows[1].values[1..3],
+            [
+                SqlValue::String(String::from("xyz")),
+                SqlValue::String(String::from("héllo")),
+            ]
+        );
+        let ids = |storage: &mut MemoryStorage, wc: &str| {
+            select_ids(storage, &format!("SELECT id FROM users WHERE {wc};"))
+        };
+        // a string compared with a char column is padded
+        assert_eq!(ids(&mut storage, "code = 'ab'"), vec![SqlValue::Int(1)]);
+        assert_eq!(ids(&mut storage, "'ab' = code"), vec![SqlValue::Int(1)]);
+        assert_eq!(ids(&mut storage, "photo = X'00ff'"), vec![SqlValue::Int(1)]);
+        assert_eq!(
+            ids(&mut storage, "photo < X'01'"),
+            vec![SqlValue::Int(1), SqlValue::Int(2)]
+        );
+
+        for (sql, column, length) in [
+            (
+                "INSERT INTO users VALUES (3, 'abc', 'alicia', NULL);",
+                "name",
+                5,
+            ),
+            ("UPDATE users SET code = 'abcd' WHERE id = 1;", "code", 3),
+        ] {
+            let query = SqlQuery::parse_format_error(sql).unwrap();
+            match query.check_and_execute(&mut storage) {
+                Err(QueryExecutionError::ValueTooLong(name, max)) => {
+                    assert_eq!((name.as_str(), max), (column, length))
+                }
+                res => panic!("Expected ValueTooLong but got {res:?}"),
+            }
+        }
+        let query =
+            SqlQuery::parse_format_error("INSERT INTO users VALUES (3, 'abc', 'bob', 'photo');")
+                .unwrap();
+        assert!(matches!(
+            query.check_and_execute(&mut storage),
+            Err(QueryExecutionError::TypeDoesNotMatch(_))
+        ));
+    }
+
+    #[test]
+    fn test_order_by() {
+        let mut storage = MemoryStorage::new();
+        run(
+            &mut storage,
+            "CREATE TABLE people (id int, name string, age int);",
+        );
+        for values in [
+            "1, 'bob', 30",
+            "2, 'alice', NULL",
+            "3, 'carol', 25",
+            "4, 'alice', 35",
+            "5, 'dave', 30",
+        ] {
+            run(
+                &mut storage,
+                &format!("INSERT INTO people VALUES ({values});"),
+            );
+        }
+        let ids = |storage: &mut MemoryStorage, order_by: &str| -> Vec<i32> {
+            select_ids(
+                storage,
+                &format!(
+                    "SELECT id, name AS who, age - 1 AS before FROM people ORDER BY {order_by};"
+                ),
+            )
+            .into_iter()
+            .map(|id| match id {
+                SqlValue::Int(id) => id,
+                other => panic!("Expected an id but got {other:?}"),
+            })
+            .collect()
+        };
+        // NULL is larger than any value, rows with equal keys keep their order
+        assert_eq!(ids(&mut storage, "age"), vec![3, 1, 5, 4, 2]);
+        assert_eq!(ids(&mut storage, "age DESC"), vec![2, 4, 1, 5, 3]);
+        assert_eq!(ids(&mut storage, "age NULLS FIRST"), vec![2, 3, 1, 5, 4]);
+        assert_eq!(
+            ids(&mut storage, "age DESC NULLS LAST"),
+            vec![4, 1, 5, 3, 2]
+        );
+        assert_eq!(ids(&mut storage, "name, age DESC"), vec![2, 4, 1, 3, 5]);
+        assert_eq!(ids(&mut storage, "age, id DESC"), vec![3, 5, 1, 4, 2]);
+        // a result column by its alias or its position, or an expression of the table
+        assert_eq!(ids(&mut storage, "who DESC, 1"), vec![5, 3, 1, 2, 4]);
+        assert_eq!(ids(&mut storage, "before, 2 DESC"), vec![3, 5, 1, 4, 2]);
+        assert_eq!(ids(&mut storage, "0 - id"), vec![5, 4, 3, 2, 1]);
+
+        for sql in [
+            "SELECT id FROM people ORDER BY 2;",
+            "SELECT id FROM people ORDER BY missing;",
+        ] {
+            let query = SqlQuery::parse_format_error(sql).unwrap();
+            assert!(matches!(
+                query.check_and_execute(&mut storage),
+                Err(QueryExecutionError::ColumnDoesNotExist(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_order_by_spills() {
+        let mut storage = MemoryStorage::new();
+        run(&mut storage, "CREATE TABLE items (id int, price int);");
+        for id in 0..60 {
+            let price = match id % 7 {
+                0 => String::from("NULL"),
+                rest => (rest * 10).to_string(),
+            };
+            run(
+                &mut storage,
+                &format!("INSERT INTO items VALUES ({id}, {price});"),
+            );
+        }
+        let sql = "SELECT id FROM items ORDER BY price DESC NULLS LAST, id;";
+        let in_memory = select_ids(&mut storage, sql);
+        // with a budget of one byte every row is spilled to its own run
+        sort::set_sort_memory(1);
+        let spilled = select_ids(&mut storage, sql);
+        sort::set_sort_memory(sort::DEFAULT_SORT_MEMORY);
+        assert_eq!(spilled, in_memory);
+        assert_eq!(spilled.len(), 60);
+        assert_eq!(spilled[0], SqlValue::Int(6));
+        assert_eq!(spilled[59], SqlValue::Int(56));
+    }
+
+    #[test]
+    fn test_limit() {
+        let mut storage = MemoryStorage::new();
+        run(&mut storage, "CREATE TABLE numbers (id int, n bigint);");
+        for (id, n) in [(1, 0), (2, 0), (3, 0), (4, 1)] {
+            run(
+                &mut storage,
+                &format!("INSERT INTO numbers VALUES ({id}, {n});"),
+            );
+        }
+        let int = |ids: &[i32]| ids.iter().map(|id| SqlValue::Int(*id)).collect::<Vec<_>>();
+        assert_eq!(
+            select_ids(&mut storage, "SELECT id FROM numbers LIMIT 2;"),
+            int(&[1, 2])
+        );
+        assert_eq!(
+            select_ids(&mut storage, "SELECT id FROM numbers LIMIT 2 OFFSET 1;"),
+            int(&[2, 3])
+        );
+        assert_eq!(
+            select_ids(&mut storage, "SELECT id FROM numbers LIMIT 10 OFFSET 3;"),
+            int(&[4])
+        );
+        assert_eq!(
+            select_ids(
+                &mut storage,
+                "SELECT id FROM numbers WHERE n = 0 LIMIT 5 OFFSET 1;"
+            ),
+            int(&[2, 3])
+        );
+        assert!(select_ids(&mut storage, "SELECT id FROM numbers LIMIT 0;").is_empty());
+        assert!(select_ids(&mut storage, "SELECT id FROM numbers LIMIT 1 OFFSET 9;").is_empty());
+        assert_eq!(
+            select_ids(
+                &mut storage,
+                "SELECT id FROM numbers ORDER BY n DESC, id LIMIT 2 OFFSET 1;"
+            ),
+            int(&[1, 2])
+        );
+
+        // the last row overflows, it is only read when the limit reaches it
+        let overflow = "SELECT n + 9223372036854775807 FROM numbers";
+        let execute = |storage: &mut MemoryStorage, sql: &str| {
+            SqlQuery::parse_format_error(sql)
+                .unwrap()
+                .check_and_execute(storage)
+        };
+        assert!(execute(&mut storage, &format!("{overflow} LIMIT 3;")).is_ok());
+        for sql in [
+            format!("{overflow};"),
+            format!("{overflow} LIMIT 1 OFFSET 3;"),
+            format!("{overflow} ORDER BY n LIMIT 1;"),
+        ] {
+            assert!(matches!(
+                execute(&mut storage, &sql),
+                Err(QueryExecutionError::ValueOutOfRange(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_aggregates() {
+        let mut storage = MemoryStorage::new();
+        run(
+            &mut storage,
+            "CREATE TABLE sales (id int, region string, amount int, price double, cost decimal(6, 2));",
+        );
+        for values in [
+            "1, 'north', 10, 1.5e0, 2.50",
+            "2, 'south', 20, 2.5e0, 1.25",
+            "3, 'north', 10, NULL, NULL",
+            "4, NULL, NULL, 0.5e0, 3.00",
+        ] {
+            run(
+                &mut storage,
+                &format!("INSERT INTO sales VALUES ({values});"),
+            );
+        }
+        let row = |storage: &mut MemoryStorage, sql: &str| match run(storage, sql) {
+            ExecuteResponse::View(table) => {
+                assert_eq!(table.rows.len(), 1);
+                table.rows[0].values.clone()
+            }
+            res => panic!("Expected View but got {res:?}"),
+        };
+        assert_eq!(
+            row(
+                &mut storage,
+                "SELECT COUNT(*), COUNT(region), COUNT(DISTINCT region), COUNT(DISTINCT amount) FROM sales;"
+            ),
+            vec![
+                SqlValue::BigInt(4),
+                SqlValue::BigInt(3),
+                SqlValue::BigInt(2),
+                SqlValue::BigInt(2)
+            ]
+        );
+        assert_eq!(
+            row(
+                &mut storage,
+                "SELECT SUM(amount), SUM(DISTINCT amount), AVG(amount), MIN(amount), MAX(region) FROM sales;"
+            ),
+            vec![
+                SqlValue::Int(40),
+                SqlValue::Int(30),
+                SqlValue::Decimal(Decimal::new(4, 0) * Decimal::new(10, 0) / Decimal::new(3, 0)),
+                SqlValue::Int(10),
+                SqlValue::String(String::from("south"))
+            ]
+        );
+        assert_eq!(
+            row(
+                &mut storage,
+                "SELECT SUM(price), AVG(price), SUM(cost), AVG(cost), MAX(amount) - MIN(amount) AS spread FROM sales WHERE id < 4;"
+            ),
+            vec![
+                SqlValue::Double(4.0),
+                SqlValue::Double(2.0),
+                SqlValue::Decimal(Decimal::new(375, 2)),
+                SqlValue::Decimal(Decimal::new(1875, 3)),
+                SqlValue::Int(10)
+            ]
+        );
+        // no row matches, only COUNT is not NULL
+        assert_eq!(
+            row(
+                &mut storage,
+                "SELECT COUNT(amount), SUM(amount), AVG(amount), MIN(region) FROM sales WHERE id > 9;"
+            ),
+            vec![
+                SqlValue::BigInt(0),
+                SqlValue::Null,
+                SqlValue::Null,
+                SqlValue::Null
+            ]
+        );
+        match run(&mut storage, "SELECT COUNT(*) FROM sales;") {
+            ExecuteResponse::Count(count) => assert_eq!(count, 4),
+            res => panic!("Expected Count but got {res:?}"),
+        }
+        match run(
+            &mut storage,
+            "SELECT COUNT(*) FROM sales WHERE region = 'north';",
+        ) {
+            ExecuteResponse::Count(count) => assert_eq!(count, 2),
+            res => panic!("Expected Count but got {res:?}"),
+        }
+        assert_eq!(
+            select_ids(&mut storage, "SELECT COUNT(*) AS total FROM sales;"),
+            vec![SqlValue::BigInt(4)]
+        );
+        assert!(
+            select_ids(&mut storage, "SELECT COUNT(*) FROM sales LIMIT 1 OFFSET 1;").is_empty()
+        );
+
+        for (sql, expected) in [
+            ("SELECT id, COUNT(*) FROM sales;", "id"),
+            ("SELECT COUNT(*) FROM sales ORDER BY amount;", "amount"),
+            ("SELECT * FROM sales ORDER BY MAX(id);", "id"),
+        ] {
+            let query = SqlQuery::parse_format_error(sql).unwrap();
+            match query.check_and_execute(&mut storage) {
+                Err(QueryExecutionError::NotAggregated(name)) => assert_eq!(name, expected),
+                res => panic!("Expected NotAggregated but got {res:?}"),
+            }
+        }
+        for sql in [
+            "SELECT id FROM sales WHERE COUNT(*) > 1;",
+            "SELECT SUM(COUNT(*)) FROM sales;",
+            "INSERT INTO sales VALUES (5, 'east', MAX(amount), NULL, NULL);",
+        ] {
+            let query = SqlQuery::parse_format_error(sql).unwrap();
+            assert!(matches!(
+                query.check_and_execute(&mut storage),
+                Err(QueryExecutionError::AggregateNotAllowed(_))
+            ));
+        }
+        let query = SqlQuery::parse_format_error("SELECT SUM(region) FROM sales;").unwrap();
+        assert!(matches!(
+            query.check_and_execute(&mut storage),
+            Err(QueryExecutionError::TypeDoesNotMatch(_))
+        ));
+    }
+
+    #[test]
+    fn test_group_by() {
+        let mut storage = MemoryStorage::new();
+        run(
+            &mut storage,
+            "CREATE TABLE sales (id int, region string, kind string, amount int);",
+        );
+        for values in [
+            "1, 'north', 'a', 10",
+            "2, 'south', 'a', 20",
+            "3, 'north', 'b', 5",
+            "4, NULL, 'a', 7",
+            "5, 'north', 'a', NULL",
+            "6, NULL, 'b', 3",
+            "7, 'south', 'a', 1",
+        ] {
+            run(
+                &mut storage,
+                &format!("INSERT INTO sales VALUES ({values});"),
+            );
+        }
+        let rows = |storage: &mut MemoryStorage, sql: &str| match run(storage, sql) {
+            ExecuteResponse::View(table) => table
+                .rows
+                .into_iter()
+                .map(|row| row.values)
+                .collect::<Vec<_>>(),
+            res => panic!("Expected View but got {res:?}"),
+        };
+        let text = |text: &str| SqlValue::String(String::from(text));
+        // the groups are in the order of their first row, NULL makes a group
+        assert_eq!(
+            rows(
+                &mut storage,
+                "SELECT region, COUNT(*), SUM(amount) AS total FROM sales GROUP BY region;"
+            ),
+            vec![
+                vec![text("north"), SqlValue::BigInt(3), SqlValue::Int(15)],
+                vec![text("south"), SqlValue::BigInt(2), SqlValue::Int(21)],
+                vec![SqlValue::Null, SqlValue::BigInt(2), SqlValue::Int(10)],
+            ]
+        );
+        assert_eq!(
+            rows(
+                &mut storage,
+                "SELECT region, kind, COUNT(amount) FROM sales WHERE id > 1 GROUP BY region, kind ORDER BY region, kind DESC;"
+            ),
+            vec![
+                vec![text("north"), text("b"), SqlValue::BigInt(1)],
+                vec![text("north"), text("a"), SqlValue::BigInt(0)],
+                vec![text("south"), text("a"), SqlValue::BigInt(2)],
+                vec![SqlValue::Null, text("b"), SqlValue::BigInt(1)],
+                vec![SqlValue::Null, text("a"), SqlValue::BigInt(1)],
+            ]
+        );
+        assert_eq!(
+            rows(
+                &mut storage,
+                "SELECT region, MAX(amount) - MIN(amount) AS spread FROM sales GROUP BY region HAVING COUNT(amount) > 1 AND region IS NOT NULL ORDER BY spread DESC;"
+            ),
+            vec![
+                vec![text("south"), SqlValue::Int(19)],
+                vec![text("north"), SqlValue::Int(5)],
+            ]
+        );
+        assert_eq!(
+            rows(
+                &mut storage,
+                "SELECT kind FROM sales GROUP BY kind HAVING SUM(amount) < 20 OR kind = 'x';"
+            ),
+            vec![vec![text("b")]]
+        );
+        assert_eq!(
+            rows(
+                &mut storage,
+                "SELECT region FROM sales GROUP BY region ORDER BY COUNT(*) DESC, region LIMIT 1 OFFSET 1;"
+            ),
+            vec![vec![text("south")]]
+        );
+        // without GROUP BY HAVING filters the single group
+        assert_eq!(
+            rows(
+                &mut storage,
+                "SELECT COUNT(*) FROM sales HAVING MIN(id) > 1;"
+            ),
+            Vec::<Vec<SqlValue>>::new()
+        );
+        assert_eq!(
+            rows(&mut storage, "SELECT COUNT(*) FROM sales GROUP BY kind;"),
+            vec![vec![SqlValue::BigInt(5)], vec![SqlValue::BigInt(2)]]
+        );
+        assert!(rows(
+            &mut storage,
+            "SELECT region, COUNT(*) FROM sales WHERE id > 9 GROUP BY region;"
+        )
+        .is_empty());
+
+        for (sql, expected) in [
+            ("SELECT region, kind FROM sales GROUP BY region;", "kind"),
+            ("SELECT * FROM sales GROUP BY region;", "id"),
+            (
+                "SELECT region FROM sales GROUP BY region HAVING amount > 1;",
+                "amount",
+            ),
+            (
+                "SELECT region FROM sales GROUP BY region ORDER BY kind;",
+                "kind",
+            ),
+        ] {
+            let query = SqlQuery::parse_format_error(sql).unwrap();
+            match query.check_and_execute(&mut storage) {
+                Err(QueryExecutionError::NotAggregated(name)) => assert_eq!(name, expected),
+                res => panic!("Expected NotAggregated but got {res:?}"),
+            }
+        }
+        let query =
+            SqlQuery::parse_format_error("SELECT COUNT(*) FROM sales GROUP BY missing;").unwrap();
+        assert!(matches!(
+            query.check_and_execute(&mut storage),
+            Err(QueryExecutionError::ColumnDoesNotExist(_))
+        ));
+    }
+
+    #[test]
+    fn test_table_not_found() {
+        let mut storage = MemoryStorage::new();
+        let query = SqlQuery::parse_format_error("SELECT * FROM foo;").unwrap();
+        assert!(matches!(
+            query.check_and_execute(&mut storage),
+            Err(QueryExecutionError::TableNotFound(_))
+        ));
+    }
+}
@@ -0,0 +1,293 @@
+use super::super::sql_analyzer::types::*;
+use super::types::QueryExecutionError;
+use chrono::{Datelike, Duration, Local, Months, NaiveDateTime, Timelike};
+use rust_decimal::{Decimal, RoundingStrategy};
+
+/// digits a quotient of decimals has after the point beyond the larger scale of its operands
+const DIVISION_SCALE: u32 = 6;
+
+/// the interval as a duration, None if it is out of range or counted in months
+fn duration(amount: i64, field: DateField) -> Option<Duration> {
+    let seconds = match field {
+        DateField::Day => 24 * 60 * 60,
+        DateField::Hour => 60 * 60,
+        DateField::Minute => 60,
+        DateField::Second => 1,
+        _ => return None,
+    };
+    // a duration keeps milliseconds in an i64
+    amount
+        .checked_mul(seconds)
+        .filter(|seconds| seconds.unsigned_abs() <= i64::MAX as u64 / 1000)
+        .map(Duration::seconds)
+}
+
+/// the interval as a number of months, None if it is counted in days or less
+fn months(amount: i64, field: DateField) -> Option<i64> {
+    match field {
+        DateField::Year => amount.checked_mul(12),
+        DateField::Month => Some(amount),
+        _ => None,
+    }
+}
+
+fn add_months(timestamp: NaiveDateTime, months: i64) -> Option<NaiveDateTime> {
+    let count = Months::new(u32::try_from(months.unsigned_abs()).ok()?);
+    if months < 0 {
+        timestamp.checked_sub_months(count)
+    } else {
+        timestamp.checked_add_months(count)
+    }
+}
+
+/// the value moved by an interval, a date stays a date unless the interval has hours or less
+fn shift(value: SqlValue, amount: i64, field: DateField) -> Result<SqlValue, QueryExecutionError> {
+    let out_of_range = || {
+        QueryExecutionError::ValueOutOfRange(format!("{value} + {}", Expr::Interval(amount, field)))
+    };
+    let shifted = match &value {
+        SqlValue::Null => return Ok(SqlValue::Null),
+        SqlValue::Date(_) | SqlValue::Timestamp(_) => {
+            let timestamp = value.as_timestamp().ok_or_else(out_of_range)?;
+            let shifted = match months(amount, field) {
+                Some(months) => add_months(timestamp, months),
+                None => duration(amount, field)
+                    .and_then(|duration| timestamp.checked_add_signed(duration)),
+            }
+            .ok_or_else(out_of_range)?;
+            match (&value, field) {
+                (SqlValue::Date(_), DateField::Year | DateField::Month | DateField::Day) => {
+                    SqlValue::Date(shifted.date())
+                }
+                _ => SqlValue::Timestamp(shifted),
+            }
+        }
+        // a time of day wraps around midnight
+        SqlValue::Time(time)
+            if matches!(
+                field,
+                DateField::Hour | DateField::Minute | DateField::Second
+            ) =>
+        {
+            let duration = duration(amount % (24 * 60 * 60), field).ok_or_else(out_of_range)?;
+            SqlValue::Time(time.overflowing_add_signed(duration).0)
+        }
+        _ => {
+            return Err(QueryExecutionError::TypeDoesNotMatch(format!(
+                "{value} + {}",
+                Expr::Interval(amount, field)
+            )))
+        }
+    };
+    Ok(shifted)
+}
+
+/// sum or difference of two values: numbers, or a date and a number of days.
+/// The difference of two dates is their number of days
+pub(super) fn arithmetic(
+    left: SqlValue,
+    right: SqlValue,
+    add: bool,
+) -> Result<SqlValue, QueryExecutionError> {
+    let text = || format!("{left} {} {right}", if add { "+" } else { "-" });
+    let out_of_range = || QueryExecutionError::ValueOutOfRange(text());
+    let integer = |value: i64, narrow: bool| match i32::try_from(value) {
+        Ok(value) if narrow => SqlValue::Int(value),
+        _ => SqlValue::BigInt(value),
+    };
+    match (&left, &right) {
+        (SqlValue::Null, _) | (_, SqlValue::Null) => Ok(SqlValue::Null),
+        (SqlValue::Date(a), SqlValue::Date(b)) if !add => {
+            Ok(integer(a.signed_duration_since(*b).num_days(), true))
+        }
+        (SqlValue::Date(date), days) | (days, SqlValue::Date(date))
+            if days.as_i64().is_some() && (add || matches!(left, SqlValue::Date(_))) =>
+        {
+            let days = days.as_i64().and_then(|days| match add {
+                true => Some(days),
+                false => days.checked_neg(),
+            });
+            days.and_then(|days| duration(days, DateField::Day))
+                .and_then(|duration| date.checked_add_signed(duration))
+                .map(SqlValue::Date)
+                .ok_or_else(out_of_range)
+        }
+        _ => {
+            if let (Some(a), Some(b)) = (left.as_i64(), right.as_i64()) {
+                let narrow = matches!(left, SqlValue::Int(_)) && matches!(right, SqlValue::Int(_));
+                let result = if add {
+                    a.checked_add(b)
+                } else {
+                    a.checked_sub(b)
+                };
+                return result
+                    .map(|result| integer(result, narrow))
+                    .ok_or_else(out_of_range);
+            }
+            // a decimal stays exact with integers and decimals
+            if let (Some(a), Some(b)) = (left.as_decimal(), right.as_decimal()) {
+                let result = if add {
+                    a.checked_add(b)
+                } else {
+                    a.checked_sub(b)
+                };
+                return result.map(SqlValue::Decimal).ok_or_else(out_of_range);
+            }
+            match (left.as_f64(), right.as_f64()) {
+                (Some(a), Some(b)) => Ok(SqlValue::Double(if add { a + b } else { a - b })),
+                _ => Err(QueryExecutionError::TypeDoesNotMatch(text())),
+            }
+        }
+    }
+}
+
+/// the exact product of two decimals, its scale is the sum of their scales.
+/// None if it does not fit in a decimal
+fn decimal_product(a: Decimal, b: Decimal) -> Option<Decimal> {
+    let mantissa = a.mantissa().checked_mul(b.mantissa())?;
+    Decimal::try_from_i128_with_scale(mantissa, a.scale() + b.scale()).ok()
+}
+
+/// the quotient of two decimals rounded half away from zero to `DIVISION_SCALE` digits
+/// more than the larger scale of the operands, at most `MAX_DECIMAL_PRECISION` digits
+fn decimal_quotient(a: Decimal, b: Decimal) -> Option<Decimal> {
+    let scale = (a.scale().max(b.scale()) + DIVISION_SCALE).min(MAX_DECIMAL_PRECISION);
+    let mut quotient = a
+        .checked_div(b)?
+        .round_dp_with_strategy(scale, RoundingStrategy::MidpointAwayFromZero);
+    // pad with zeros, a large quotient keeps the digits that fit
+    quotient.rescale(scale);
+    Some(quotient)
+}
+
+/// product or quotient of two numbers.
+/// Integers stay integers and their quotient is truncated toward zero,
+/// decimals stay exact with integers and decimals, see `decimal_product` and `decimal_quotient`
+pub(super) fn product(
+    left: SqlValue,
+    right: SqlValue,
+    multiply: bool,
+) -> Result<SqlValue, QueryExecutionError> {
+    let text = || format!("{left} {} {right}", if multiply { "*" } else { "/" });
+    let out_of_range = || QueryExecutionError::ValueOutOfRange(text());
+    if matches!(left, SqlValue::Null) || matches!(right, SqlValue::Null) {
+        return Ok(SqlValue::Null);
+    }
+    if !multiply && right.as_f64() == Some(0.0) {
+        return Err(QueryExecutionError::DivisionByZero(text()));
+    }
+    if let (Some(a), Some(b)) = (left.as_i64(), right.as_i64()) {
+        let result = if multiply {
+            a.checked_mul(b)
+        } else {
+            a.checked_div(b)
+        };
+        let narrow = matches!(left, SqlValue::Int(_)) && matches!(right, SqlValue::Int(_));
+        return match result.map(|result| (result, i32::try_from(result))) {
+            Some((_, Ok(result))) if narrow => Ok(SqlValue::Int(result)),
+            Some((result, _)) => Ok(SqlValue::BigInt(result)),
+            None => Err(out_of_range()),
+        };
+    }
+    if let (Some(a), Some(b)) = (left.as_decimal(), right.as_decimal()) {
+        let result = if multiply {
+            decimal_product(a, b)
+        } else {
+            decimal_quotient(a, b)
+        };
+        return result.map(SqlValue::Decimal).ok_or_else(out_of_range);
+    }
+    match (left.as_f64(), right.as_f64()) {
+        (Some(a), Some(b)) => Ok(SqlValue::Double(if multiply { a * b } else { a / b })),
+        _ => Err(QueryExecutionError::TypeDoesNotMatch(text())),
+    }
+}
+
+/// a part of a date, a time or a timestamp, a date is at midnight
+fn extract(field: DateField, value: SqlValue) -> Result<SqlValue, QueryExecutionError> {
+    let part = match (&value, field) {
+        (SqlValue::Null, _) => return Ok(SqlValue::Null),
+        (SqlValue::Time(time), DateField::Hour) => time.hour() as i64,
+        (SqlValue::Time(time), DateField::Minute) => time.minute() as i64,
+        (SqlValue::Time(time), DateField::Second) => time.second() as i64,
+        (SqlValue::Time(time), DateField::Epoch) => time.num_seconds_from_midnight() as i64,
+        (SqlValue::Date(_) | SqlValue::Timestamp(_), _) => {
+            let timestamp = value.as_timestamp().ok_or_else(|| {
+                QueryExecutionError::ValueOutOfRange(format!("EXTRACT({field} FROM {value})"))
+            })?;
+            match field {
+                DateField::Year => timestamp.year() as i64,
+                DateField::Month => timestamp.month() as i64,
+                DateField::Day => timestamp.day() as i64,
+                DateField::Hour => timestamp.hour() as i64,
+                DateField::Minute => timestamp.minute() as i64,
+                DateField::Second => timestamp.second() as i64,
+                DateField::Epoch => return Ok(SqlValue::BigInt(timestamp.timestamp())),
+            }
+        }
+        _ => {
+            return Err(QueryExecutionError::TypeDoesNotMatch(format!(
+                "EXTRACT({field} FROM {value})"
+            )))
+        }
+    };
+    Ok(match field {
+        DateField::Epoch => SqlValue::BigInt(part),
+        _ => SqlValue::Int(part as i32),
+    })
+}
+
+impl Expr {
+    /// whether the expression has the same value for every row
+    pub fn is_constant(&self) -> bool {
+        match self {
+            Expr::Column(_) => false,
+            Expr::Add(left, right)
+            | Expr::Sub(left, right)
+            | Expr::Mul(left, right)
+            | Expr::Div(left, right) => left.is_constant() && right.is_constant(),
+            Expr::Extract(_, expr) => expr.is_constant(),
+            Expr::Aggregate(_) => false,
+            _ => true,
+        }
+    }
+
+    /// the value of the expression for a row of a table with the columns `names`
+    pub fn eval(&self, row: &RowValue, names: &[String]) -> Result<SqlValue, QueryExecutionError> {
+        match self {
+            Expr::Value(value) => Ok(value.clone()),
+            Expr::Column(name) => match names.iter().position(|other| other == name) {
+                Some(position) => Ok(row.values.get(position).cloned().unwrap_or(SqlValue::Null)),
+                None => Err(QueryExecutionError::ColumnDoesNotExist(name.clone())),
+            },
+            Expr::Now => Ok(SqlValue::Timestamp(Local::now().naive_local())),
+            Expr::CurrentDate => Ok(SqlValue::Date(Local::now().date_naive())),
+            Expr::Add(left, right) => match (left.as_ref(), right.as_ref()) {
+                (value, Expr::Interval(amount, field)) | (Expr::Interval(amount, field), value) => {
+                    shift(value.eval(row, names)?, *amount, *field)
+                }
+                _ => arithmetic(left.eval(row, names)?, right.eval(row, names)?, true),
+            },
+            Expr::Sub(left, right) => match right.as_ref() {
+                Expr::Interval(amount, field) => {
+                    let amount = amount
+                        .checked_neg()
+                        .ok_or_else(|| QueryExecutionError::ValueOutOfRange(self.to_string()))?;
+                    shift(left.eval(row, names)?, amount, *field)
+                }
+                _ => arithmetic(left.eval(row, names)?, right.eval(row, names)?, false),
+            },
+            Expr::Mul(left, right) => {
+                product(left.eval(row, names)?, right.eval(row, names)?, true)
+            }
+            Expr::Div(left, right) => {
+                product(left.eval(row, names)?, right.eval(row, names)?, false)
+            }
+            // an interval has no value of its own
+            Expr::Interval(_, _) => Err(QueryExecutionError::TypeDoesNotMatch(self.to_string())),
+            Expr::Extract(field, expr) => extract(*field, expr.eval(row, names)?),
+            // the value of an aggregate is given by the rows it is computed over
+            Expr::Aggregate(_) => Err(QueryExecutionError::AggregateNotAllowed(self.to_string())),
+        }
+    }
+}
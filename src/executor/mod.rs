@@ -1,2 +1,6 @@
+pub mod aggregate;
 pub mod execute;
+pub mod expr;
+pub mod session;
+pub mod sort;
 pub mod types;
@@ -0,0 +1,654 @@
+use super::super::sql_analyzer::types::SqlQuery;
+use super::super::storage::{table_not_found, LockGuard, LockMode, PendingIndexes, StorageEngine};
+use super::execute::{index_error, lock_error, save_error};
+use super::types::*;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::io;
+
+/// Changes of an open transaction which are not in the storage yet
+#[derive(Clone, Default)]
+struct Pending {
+    /// tables changed by the transaction, None if the table was dropped
+    tables: HashMap<String, Option<SqlTable>>,
+    /// fingerprint of each changed table when the transaction first changed it
+    bases: HashMap<String, Option<u64>>,
+    indexes: PendingIndexes,
+}
+
+impl Pending {
+    /// the tables written by the commit, in the order they are locked
+    fn table_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.tables.keys().map(String::as_str).collect();
+        names.extend(self.indexes.tables());
+        names.sort();
+        names.dedup();
+        names
+    }
+}
+
+/// A storage engine that keeps the changes of a transaction in memory
+/// on top of the tables of the underlying storage
+struct TransactionStorage<'a> {
+    inner: &'a mut dyn StorageEngine,
+    pending: &'a mut Pending,
+    /// state of `pending` before the statements started with `begin`
+    statements: Vec<Pending>,
+}
+
+impl TransactionStorage<'_> {
+    /// remember the table the transaction started from before it is first changed
+    fn keep_base(&mut self, name: &str) -> Result<(), io::Error> {
+        if !self.pending.bases.contains_key(name) {
+            let base = fingerprint(self.inner, name)?;
+            self.pending.bases.insert(name.to_string(), base);
+        }
+        Ok(())
+    }
+}
+
+/// a hash of the columns and rows of the table in the storage, None if the table does not exist
+fn fingerprint(storage: &dyn StorageEngine, name: &str) -> Result<Option<u64>, io::Error> {
+    if !storage.exists(name) {
+        return Ok(None);
+    }
+    let scan = storage.scan(name)?;
+    let mut hasher = DefaultHasher::new();
+    scan.columns.hash(&mut hasher);
+    for row in scan.rows {
+        row?.1.hash(&mut hasher);
+    }
+    Ok(Some(hasher.finish()))
+}
+
+impl StorageEngine for TransactionStorage<'_> {
+    fn exists(&self, name: &str) -> bool {
+        match self.pending.tables.get(name) {
+            Some(table) => table.is_some(),
+            None => self.inner.exists(name),
+        }
+    }
+
+    fn list_tables(&self) -> Result<Vec<String>, io::Error> {
+        let mut tables: Vec<String> = self
+            .inner
+            .list_tables()?
+            .into_iter()
+            .filter(|name| !self.pending.tables.contains_key(name))
+            .collect();
+        for (name, table) in self.pending.tables.iter() {
+            if table.is_some() {
+                tables.push(name.clone());
+            }
+        }
+        tables.sort();
+        Ok(tables)
+    }
+
+    fn load(&self, name: &str) -> Result<SqlTable, io::Error> {
+        match self.pending.tables.get(name) {
+            Some(Some(table)) => Ok(table.clone()),
+            Some(None) => Err(table_not_found()),
+            None => self.inner.load(name),
+        }
+    }
+
+    fn save(&mut self, name: &str, table: &SqlTable) -> Result<(), io::Error> {
+        self.keep_base(name)?;
+        self.pending
+            .tables
+            .insert(name.to_string(), Some(table.clone()));
+        Ok(())
+    }
+
+    fn delete(&mut self, name: &str) -> Result<(), io::Error> {
+        if !self.exists(name) {
+            return Err(table_not_found());
+        }
+        self.keep_base(name)?;
+        self.pending.tables.insert(name.to_string(), None);
+        self.pending.indexes.drop_table(self.inner, name);
+        Ok(())
+    }
+
+    // the indexes are changed in the storage when the transaction is committed,
+    // until then the rows are found by scanning the tables
+    fn create_index(&mut self, index: &IndexInfo) -> Result<(), io::Error> {
+        if self.inner.supports_indexes() && !self.exists(&index.table) {
+            return Err(table_not_found());
+        }
+        self.pending.indexes.create_index(self.inner, index)
+    }
+
+    fn drop_index(&mut self, name: &str) -> Result<(), io::Error> {
+        self.pending.indexes.drop_index(self.inner, name)
+    }
+
+    fn lock(&mut self, names: &[&str], mode: LockMode) -> Result<LockGuard, io::Error> {
+        self.inner.lock(names, mode)
+    }
+
+    fn begin(&mut self) -> Result<(), io::Error> {
+        self.statements.push(self.pending.clone());
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<(), io::Error> {
+        self.statements.pop();
+        Ok(())
+    }
+
+    /// a failed statement leaves the transaction as it was before the statement
+    fn rollback(&mut self) -> Result<(), io::Error> {
+        if let Some(pending) = self.statements.pop() {
+            *self.pending = pending;
+        }
+        Ok(())
+    }
+}
+
+/// State of an open transaction
+#[derive(Default)]
+struct Transaction {
+    /// changes made by the transaction
+    pending: Pending,
+    /// savepoints in the order they were set, with the state of `pending` at that point
+    savepoints: Vec<(String, Pending)>,
+}
+
+impl Transaction {
+    /// position of the latest savepoint called `name`
+    fn savepoint(&self, name: &str) -> Result<usize, QueryExecutionError> {
+        self.savepoints
+            .iter()
+            .rposition(|(savepoint, _)| savepoint == name)
+            .ok_or_else(|| QueryExecutionError::SavepointNotFound(name.to_string()))
+    }
+}
+
+fn transaction_fail(err: io::Error) -> QueryExecutionError {
+    QueryExecutionError::TransactionFail(err.to_string())
+}
+
+/// A connection to a storage engine which runs the statements of the REPL.
+///
+/// Outside a transaction every statement is applied to the storage at once.
+/// After BEGIN the changes are kept in memory until COMMIT applies all of them
+/// atomically, or ROLLBACK discards them.
+/// SAVEPOINT marks a point of the transaction that ROLLBACK TO could go back to.
+pub struct Session {
+    storage: Box<dyn StorageEngine>,
+    transaction: Option<Transaction>,
+}
+
+impl Session {
+    pub fn new(storage: Box<dyn StorageEngine>) -> Self {
+        Self {
+            storage,
+            transaction: None,
+        }
+    }
+
+    pub fn storage(&self) -> &dyn StorageEngine {
+        self.storage.as_ref()
+    }
+
+    pub fn storage_mut(&mut self) -> &mut dyn StorageEngine {
+        self.storage.as_mut()
+    }
+
+    /// whether BEGIN was run without COMMIT or ROLLBACK yet
+    pub fn in_transaction(&self) -> bool {
+        self.transaction.is_some()
+    }
+
+    pub fn execute(&mut self, query: SqlQuery) -> Result<ExecuteResponse, QueryExecutionError> {
+        match query {
+            SqlQuery::Begin => {
+                if self.transaction.is_some() {
+                    return Err(QueryExecutionError::TransactionAlreadyStarted());
+                }
+                // the storage takes the snapshot read by the transaction
+                self.storage.begin().map_err(transaction_fail)?;
+                self.transaction = Some(Transaction::default());
+                Ok(ExecuteResponse::Message("transaction started".to_string()))
+            }
+            SqlQuery::Commit => {
+                let transaction = self
+                    .transaction
+                    .take()
+                    .ok_or(QueryExecutionError::NoActiveTransaction())?;
+                let tables = transaction.pending.table_names();
+                let lock = match self.storage.lock(&tables, LockMode::Exclusive) {
+                    Ok(lock) => lock,
+                    Err(err) => {
+                        let err = lock_error(err, tables.join(", "));
+                        // nothing is written, so COMMIT could be retried or the transaction rolled back
+                        self.transaction = Some(transaction);
+                        return Err(err);
+                    }
+                };
+                self.apply(transaction.pending, lock)?;
+                Ok(ExecuteResponse::Message(
+                    "transaction committed".to_string(),
+                ))
+            }
+            SqlQuery::Rollback => {
+                self.transaction
+                    .take()
+                    .ok_or(QueryExecutionError::NoActiveTransaction())?;
+                self.storage.rollback().map_err(transaction_fail)?;
+                Ok(ExecuteResponse::Message(
+                    "transaction rolled back".to_string(),
+                ))
+            }
+            SqlQuery::Savepoint(name) => {
+                let transaction = self.transaction()?;
+                let pending = transaction.pending.clone();
+                transaction.savepoints.push((name.clone(), pending));
+                Ok(ExecuteResponse::Message(format!("savepoint {name} set")))
+            }
+            SqlQuery::Release(name) => {
+                let transaction = self.transaction()?;
+                // the savepoints set after this one are released too
+                let index = transaction.savepoint(&name)?;
+                transaction.savepoints.truncate(index);
+                Ok(ExecuteResponse::Message(format!(
+                    "savepoint {name} released"
+                )))
+            }
+            SqlQuery::RollbackTo(name) => {
+                let transaction = self.transaction()?;
+                // the savepoint itself is kept, so the same work could be retried
+                let index = transaction.savepoint(&name)?;
+                transaction.savepoints.truncate(index + 1);
+                transaction.pending = transaction.savepoints[index].1.clone();
+                Ok(ExecuteResponse::Message(format!(
+                    "rolled back to savepoint {name}"
+                )))
+            }
+            query => match &mut self.transaction {
+                None => query.check_and_execute(self.storage.as_mut()),
+                Some(transaction) => query.check_and_execute(&mut TransactionStorage {
+                    inner: self.storage.as_mut(),
+                    pending: &mut transaction.pending,
+                    statements: Vec::new(),
+                }),
+            },
+        }
+    }
+
+    fn transaction(&mut self) -> Result<&mut Transaction, QueryExecutionError> {
+        self.transaction
+            .as_mut()
+            .ok_or(QueryExecutionError::NoActiveTransaction())
+    }
+
+    /// write the changes of a transaction to the storage and commit the transaction
+    /// of the storage started by BEGIN, so all or none of them are written.
+    /// Another process may have changed the tables since the transaction copied them,
+    /// which fails with a write conflict instead of losing those changes.
+    /// The indexes of the storage are dropped before the tables are written and created after
+    fn apply(&mut self, pending: Pending, _lock: LockGuard) -> Result<(), QueryExecutionError> {
+        let storage = self.storage.as_mut();
+        let fail = |storage: &mut dyn StorageEngine, err: QueryExecutionError| {
+            let _ = storage.rollback();
+            Err(err)
+        };
+        let mut names: Vec<&String> = pending.tables.keys().collect();
+        names.sort();
+        for name in &names {
+            match fingerprint(storage, name) {
+                Ok(current) if current == pending.bases[*name] => {}
+                Ok(_) => {
+                    return fail(
+                        storage,
+                        QueryExecutionError::WriteConflict(name.to_string()),
+                    )
+                }
+                Err(err) => return fail(storage, save_error(err, name.to_string())),
+            }
+        }
+        for index in pending.indexes.dropped_indexes() {
+            if let Err(err) = storage.drop_index(&index.name) {
+                return fail(storage, index_error(err, index.name.clone()));
+            }
+        }
+        for name in names {
+            let result = match &pending.tables[name] {
+                Some(table) => storage.save(name, table),
+                // the table may have been created by the transaction too
+                None if !storage.exists(name) => Ok(()),
+                None => storage.delete(name),
+            };
+            if let Err(err) = result {
+                return fail(storage, save_error(err, name.clone()));
+            }
+        }
+        for index in pending.indexes.created_indexes() {
+            if let Err(err) = storage.create_index(index) {
+                return fail(storage, index_error(err, index.name.clone()));
+            }
+        }
+        storage.commit().map_err(transaction_fail)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::sql_analyzer::parser::Parse;
+    use super::super::super::storage::{open_storage, CsvStorage, MemoryStorage, MvccStorage};
+    use super::*;
+    use std::time::Duration;
+
+    fn run(session: &mut Session, sql: &str) -> Result<ExecuteResponse, QueryExecutionError> {
+        session.execute(SqlQuery::parse_from_raw(sql).unwrap().1)
+    }
+
+    fn count(session: &mut Session, table: &str) -> usize {
+        match run(session, &format!("SELECT * FROM {table};")).unwrap() {
+            ExecuteResponse::View(view) => view.rows.len(),
+            res => panic!("unexpected response {res:?}"),
+        }
+    }
+
+    #[test]
+    fn test_commit_and_rollback() {
+        let mut session = Session::new(Box::new(MemoryStorage::new()));
+        run(&mut session, "CREATE TABLE a (id int);").unwrap();
+
+        run(&mut session, "BEGIN;").unwrap();
+        assert!(run(&mut session, "BEGIN;").is_err());
+        run(&mut session, "INSERT INTO a VALUES (1);").unwrap();
+        run(&mut session, "CREATE TABLE b (id int);").unwrap();
+        run(&mut session, "INSERT INTO b VALUES (2);").unwrap();
+        // the transaction sees its own changes, the storage does not
+        assert_eq!(count(&mut session, "a"), 1);
+        assert!(!session.storage().exists("b"));
+        run(&mut session, "ROLLBACK;").unwrap();
+        assert_eq!(count(&mut session, "a"), 0);
+        assert!(run(&mut session, "SELECT * FROM b;").is_err());
+
+        run(&mut session, "BEGIN TRANSACTION;").unwrap();
+        run(&mut session, "INSERT INTO a VALUES (1);").unwrap();
+        run(&mut session, "DROP TABLE a;").unwrap();
+        run(&mut session, "CREATE TABLE a (name string);").unwrap();
+        run(&mut session, "INSERT INTO a VALUES ('x');").unwrap();
+        // a failed statement does not end the transaction
+        assert!(run(&mut session, "INSERT INTO c VALUES (1);").is_err());
+        assert!(session.in_transaction());
+        run(&mut session, "COMMIT;").unwrap();
+        assert!(!session.in_transaction());
+        assert_eq!(count(&mut session, "a"), 1);
+        assert!(run(&mut session, "COMMIT;").is_err());
+    }
+
+    #[test]
+    fn test_savepoints() {
+        let mut session = Session::new(Box::new(MemoryStorage::new()));
+        run(&mut session, "CREATE TABLE a (id int);").unwrap();
+        assert!(run(&mut session, "SAVEPOINT s1;").is_err());
+
+        run(&mut session, "BEGIN;").unwrap();
+        run(&mut session, "INSERT INTO a VALUES (1);").unwrap();
+        run(&mut session, "SAVEPOINT s1;").unwrap();
+        run(&mut session, "INSERT INTO a VALUES (2);").unwrap();
+        run(&mut session, "SAVEPOINT s2;").unwrap();
+        run(&mut session, "INSERT INTO a VALUES (3);").unwrap();
+        run(&mut session, "ROLLBACK TO s1;").unwrap();
+        assert_eq!(count(&mut session, "a"), 1);
+        // s2 was set after s1 so it is gone, s1 could be used again
+        assert!(run(&mut session, "ROLLBACK TO s2;").is_err());
+        run(&mut session, "INSERT INTO a VALUES (4);").unwrap();
+        run(&mut session, "ROLLBACK TO SAVEPOINT s1;").unwrap();
+        assert_eq!(count(&mut session, "a"), 1);
+
+        run(&mut session, "INSERT INTO a VALUES (5);").unwrap();
+        run(&mut session, "RELEASE s1;").unwrap();
+        assert!(run(&mut session, "ROLLBACK TO s1;").is_err());
+        run(&mut session, "COMMIT;").unwrap();
+        assert_eq!(count(&mut session, "a"), 2);
+    }
+
+    #[test]
+    fn test_concurrent_sessions() {
+        let storage = MvccStorage::new(Box::new(MemoryStorage::new()));
+        let mut first = Session::new(Box::new(storage.session()));
+        let mut second = Session::new(Box::new(storage));
+        run(&mut first, "CREATE TABLE a (id int, name string);").unwrap();
+        run(&mut first, "INSERT INTO a VALUES (1, 'x');").unwrap();
+
+        run(&mut first, "BEGIN;").unwrap();
+        assert_eq!(count(&mut first, "a"), 1);
+        run(&mut second, "INSERT INTO a VALUES (2, 'y');").unwrap();
+        run(&mut second, "UPDATE a SET name = 'z' WHERE id = 1;").unwrap();
+        // the transaction reads its snapshot
+        assert_eq!(count(&mut first, "a"), 1);
+        run(&mut first, "UPDATE a SET name = 'w' WHERE id = 1;").unwrap();
+        assert!(matches!(
+            run(&mut first, "COMMIT;"),
+            Err(QueryExecutionError::WriteConflict(_))
+        ));
+        assert!(!first.in_transaction());
+        assert_eq!(count(&mut first, "a"), 2);
+    }
+
+    #[test]
+    fn test_commit_multiple_tables() {
+        let root = std::env::temp_dir().join("rust_db_test_session_commit");
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        let root = root.to_string_lossy().to_string();
+
+        let mut session = Session::new(Box::new(CsvStorage::open(&root).unwrap()));
+        run(&mut session, "CREATE TABLE a (id int);").unwrap();
+        run(&mut session, "BEGIN;").unwrap();
+        run(&mut session, "INSERT INTO a VALUES (1);").unwrap();
+        run(&mut session, "CREATE TABLE b (id int);").unwrap();
+        run(&mut session, "INSERT INTO b VALUES (2);").unwrap();
+        assert!(CsvStorage::new(&root).load("a").unwrap().rows.is_empty());
+        run(&mut session, "COMMIT;").unwrap();
+
+        let storage = CsvStorage::new(&root);
+        assert_eq!(storage.load("a").unwrap().rows.len(), 1);
+        assert_eq!(storage.load("b").unwrap().rows.len(), 1);
+    }
+
+    #[test]
+    fn test_open_storage() {
+        for kind in ["csv", "heap"] {
+            let root = std::env::temp_dir().join(format!("rust_db_test_session_open_{kind}"));
+            let _ = std::fs::remove_dir_all(&root);
+            let root = root.to_string_lossy().to_string();
+
+            let mut session = Session::new(open_storage(kind, &root).unwrap());
+            run(&mut session, "CREATE TABLE t (id int PRIMARY KEY, v int);").unwrap();
+            run(&mut session, "INSERT INTO t VALUES (1, 2);").unwrap();
+            run(&mut session, "BEGIN;").unwrap();
+            run(&mut session, "INSERT INTO t VALUES (2, 3);").unwrap();
+            run(&mut session, "ROLLBACK;").unwrap();
+            assert_eq!(session.storage().load("t").unwrap().rows.len(), 1);
+
+            let storage = open_storage(kind, &root).unwrap();
+            assert_eq!(storage.load("t").unwrap().rows.len(), 1);
+        }
+    }
+
+    #[test]
+    fn test_commit_after_other_process() {
+        for kind in ["csv", "heap"] {
+            let root = std::env::temp_dir().join(format!("rust_db_test_session_conflict_{kind}"));
+            let _ = std::fs::remove_dir_all(&root);
+            let root = root.to_string_lossy().to_string();
+
+            let mut session = Session::new(open_storage(kind, &root).unwrap());
+            run(&mut session, "CREATE TABLE t (id int);").unwrap();
+            run(&mut session, "CREATE TABLE u (id int);").unwrap();
+            run(&mut session, "INSERT INTO t VALUES (1);").unwrap();
+            run(&mut session, "BEGIN;").unwrap();
+            run(&mut session, "INSERT INTO t VALUES (3);").unwrap();
+
+            // another process inserts a row once the transaction copied the table
+            let mut other = Session::new(open_storage(kind, &root).unwrap());
+            run(&mut other, "INSERT INTO t VALUES (2);").unwrap();
+            assert!(matches!(
+                run(&mut session, "COMMIT;"),
+                Err(QueryExecutionError::WriteConflict(_))
+            ));
+            assert!(!session.in_transaction());
+            assert_eq!(count(&mut session, "t"), 2);
+
+            // the tables changed by the other process are not in the way
+            run(&mut session, "BEGIN;").unwrap();
+            run(&mut session, "INSERT INTO t VALUES (3);").unwrap();
+            run(&mut other, "INSERT INTO u VALUES (4);").unwrap();
+            run(&mut session, "COMMIT;").unwrap();
+            assert_eq!(count(&mut session, "t"), 3);
+            assert_eq!(count(&mut session, "u"), 1);
+        }
+    }
+
+    #[test]
+    fn test_heap_statements_touch_few_pages() {
+        let root = std::env::temp_dir().join("rust_db_test_session_heap_pages");
+        let _ = std::fs::remove_dir_all(&root);
+        let mut session = Session::new(open_storage("heap", &root.to_string_lossy()).unwrap());
+        run(
+            &mut session,
+            "CREATE TABLE t (id int PRIMARY KEY, name varchar(300));",
+        )
+        .unwrap();
+        let name = "x".repeat(300);
+        for id in 0..500 {
+            run(
+                &mut session,
+                &format!("INSERT INTO t VALUES ({id}, '{name}');"),
+            )
+            .unwrap();
+        }
+        let pages = |session: &Session| {
+            let stats = session.storage().pool_stats();
+            stats.reads + stats.writes
+        };
+
+        // a whole table scan reads every page of the table
+        let before = pages(&session);
+        assert_eq!(count(&mut session, "t"), 500);
+        let table_pages = pages(&session) - before;
+        assert!(table_pages > 20);
+
+        // a single row statement touches a few pages, without rewriting the table
+        for sql in [
+            format!("INSERT INTO t VALUES (500, '{name}');"),
+            String::from("SELECT * FROM t WHERE id = 250;"),
+            String::from("UPDATE t SET name = 'y' WHERE id = 250;"),
+            String::from("DELETE FROM t WHERE id = 251;"),
+        ] {
+            let before = pages(&session);
+            run(&mut session, &sql).unwrap();
+            assert!(pages(&session) - before < 10, "{sql}");
+        }
+        assert_eq!(count(&mut session, "t"), 500);
+    }
+
+    fn index_names(session: &Session, table: &str) -> Vec<String> {
+        let mut names: Vec<String> = session
+            .storage()
+            .indexes(table)
+            .into_iter()
+            .map(|index| index.name)
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_indexes_in_transaction() {
+        let mut session = Session::new(Box::new(MemoryStorage::new()));
+        run(&mut session, "BEGIN;").unwrap();
+        run(
+            &mut session,
+            "CREATE TABLE a (id int PRIMARY KEY, code int UNIQUE, name string);",
+        )
+        .unwrap();
+        run(&mut session, "INSERT INTO a VALUES (1, 10, 'x');").unwrap();
+        run(&mut session, "CREATE INDEX a_name ON a (name);").unwrap();
+        assert!(run(&mut session, "CREATE INDEX a_name ON a (id);").is_err());
+        // the indexes are created in the storage on commit
+        assert!(index_names(&session, "a").is_empty());
+        run(&mut session, "COMMIT;").unwrap();
+        assert_eq!(
+            index_names(&session, "a"),
+            vec!["a_code_key", "a_name", "a_pkey"]
+        );
+        assert!(matches!(
+            run(&mut session, "INSERT INTO a VALUES (2, 10, 'y');"),
+            Err(QueryExecutionError::UniqueViolation(_))
+        ));
+
+        run(&mut session, "BEGIN;").unwrap();
+        run(&mut session, "DROP INDEX a_name;").unwrap();
+        assert!(run(&mut session, "DROP INDEX a_name;").is_err());
+        run(&mut session, "SAVEPOINT s1;").unwrap();
+        run(&mut session, "CREATE INDEX a_code ON a (code);").unwrap();
+        run(&mut session, "ROLLBACK TO SAVEPOINT s1;").unwrap();
+        run(&mut session, "COMMIT;").unwrap();
+        assert_eq!(index_names(&session, "a"), vec!["a_code_key", "a_pkey"]);
+
+        // the indexes of a dropped table are dropped with it
+        run(&mut session, "BEGIN;").unwrap();
+        run(&mut session, "DROP TABLE a;").unwrap();
+        run(&mut session, "CREATE TABLE a (id int PRIMARY KEY);").unwrap();
+        run(&mut session, "COMMIT;").unwrap();
+        assert_eq!(index_names(&session, "a"), vec!["a_pkey"]);
+    }
+
+    #[test]
+    fn test_locked_by_other_process() {
+        let root = std::env::temp_dir().join("rust_db_test_session_lock");
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        let root = root.to_string_lossy().to_string();
+
+        let mut session = Session::new(Box::new(CsvStorage::open(&root).unwrap()));
+        session
+            .storage_mut()
+            .set_busy_timeout(Duration::from_millis(50));
+        run(&mut session, "CREATE TABLE a (id int);").unwrap();
+
+        // another process is reading the table
+        let mut other = CsvStorage::new(&root);
+        let lock = other.lock(&["a"], LockMode::Shared).unwrap();
+        assert_eq!(count(&mut session, "a"), 0);
+        assert!(matches!(
+            run(&mut session, "INSERT INTO a VALUES (1);"),
+            Err(QueryExecutionError::Locked(_))
+        ));
+        drop(lock);
+        run(&mut session, "INSERT INTO a VALUES (1);").unwrap();
+
+        // or writing it
+        let lock = other.lock(&["a"], LockMode::Exclusive).unwrap();
+        assert!(matches!(
+            run(&mut session, "SELECT * FROM a;"),
+            Err(QueryExecutionError::Locked(_))
+        ));
+        drop(lock);
+        assert_eq!(count(&mut session, "a"), 1);
+
+        // a commit which does not get its lock keeps the transaction open
+        run(&mut session, "BEGIN;").unwrap();
+        run(&mut session, "INSERT INTO a VALUES (2);").unwrap();
+        let lock = other.lock(&["a"], LockMode::Shared).unwrap();
+        assert!(matches!(
+            run(&mut session, "COMMIT;"),
+            Err(QueryExecutionError::Locked(_))
+        ));
+        assert!(session.in_transaction());
+        drop(lock);
+        run(&mut session, "COMMIT;").unwrap();
+        assert!(!session.in_transaction());
+        assert_eq!(count(&mut session, "a"), 2);
+    }
+}
@@ -0,0 +1,314 @@
+use super::super::sql_analyzer::types::*;
+use super::super::storage::{decode_row, encode_row};
+use std::cmp::Ordering;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+
+/// How many bytes of rows a sort keeps in memory by default
+pub const DEFAULT_SORT_MEMORY: usize = 64 * 1024 * 1024;
+/// at most this many runs are merged at once, more runs are merged in several passes
+const MERGE_FAN_IN: usize = 16;
+
+static SORT_MEMORY: AtomicUsize = AtomicUsize::new(DEFAULT_SORT_MEMORY);
+/// numbers the temporary files of the runs
+static RUN_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// how many bytes of rows ORDER BY keeps in memory before it spills to disk
+pub fn sort_memory() -> usize {
+    SORT_MEMORY.load(AtomicOrdering::Relaxed)
+}
+
+pub fn set_sort_memory(bytes: usize) {
+    SORT_MEMORY.store(bytes, AtomicOrdering::Relaxed);
+}
+
+/// a key of a sort and its row
+type Entry = (Vec<SqlValue>, RowValue);
+
+/// A sorted run written to a temporary file, the file is removed on drop.
+/// Every entry is its length (u32) followed by the key and the row encoded as one record
+struct Run {
+    path: PathBuf,
+}
+
+impl Run {
+    fn write(entries: impl Iterator<Item = Result<Entry, io::Error>>) -> Result<Self, io::Error> {
+        let number = RUN_COUNTER.fetch_add(1, AtomicOrdering::Relaxed);
+        let path =
+            std::env::temp_dir().join(format!("rust_db_sort_{}_{number}.run", std::process::id()));
+        let run = Run { path };
+        let mut writer = BufWriter::new(File::create(&run.path)?);
+        for entry in entries {
+            let (key, row) = entry?;
+            let bytes = encode_entry(&key, &row);
+            writer.write_all(&(bytes.len() as u32).to_le_bytes())?;
+            writer.write_all(&bytes)?;
+        }
+        writer.flush()?;
+        Ok(run)
+    }
+
+    fn reader(&self) -> Result<RunReader, io::Error> {
+        Ok(RunReader {
+            reader: BufReader::new(File::open(&self.path)?),
+        })
+    }
+}
+
+impl Drop for Run {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+fn encode_entry(key: &[SqlValue], row: &RowValue) -> Vec<u8> {
+    let mut values = key.to_vec();
+    values.extend(row.values.iter().cloned());
+    let mut bytes = (key.len() as u16).to_le_bytes().to_vec();
+    bytes.extend(encode_row(&RowValue { values }));
+    bytes
+}
+
+fn decode_entry(bytes: &[u8]) -> Result<Entry, io::Error> {
+    let (length, record) = bytes
+        .split_first_chunk::<2>()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Sort run is broken"))?;
+    let mut values = decode_row(record)?.values;
+    let row = values.split_off(usize::from(u16::from_le_bytes(*length)).min(values.len()));
+    Ok((values, RowValue { values: row }))
+}
+
+struct RunReader {
+    reader: BufReader<File>,
+}
+
+impl Iterator for RunReader {
+    type Item = Result<Entry, io::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut length = [0; 4];
+        match self.reader.read_exact(&mut length) {
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return None,
+            Err(err) => return Some(Err(err)),
+            Ok(()) => {}
+        }
+        let mut bytes = vec![0; u32::from_le_bytes(length) as usize];
+        Some(
+            self.reader
+                .read_exact(&mut bytes)
+                .and_then(|_| decode_entry(&bytes)),
+        )
+    }
+}
+
+/// Merges sorted runs, an entry of an earlier run goes first when the keys are equal
+pub struct Merge<F> {
+    readers: Vec<RunReader>,
+    heads: Vec<Option<Entry>>,
+    compare: F,
+    /// the files of the runs are removed when the merge is dropped
+    _runs: Vec<Run>,
+}
+
+impl<F: Fn(&[SqlValue], &[SqlValue]) -> Ordering> Merge<F> {
+    fn new(runs: Vec<Run>, compare: F) -> Result<Self, io::Error> {
+        let mut readers = runs
+            .iter()
+            .map(Run::reader)
+            .collect::<Result<Vec<_>, _>>()?;
+        let heads = readers
+            .iter_mut()
+            .map(|reader| reader.next().transpose())
+            .collect::<Result<_, _>>()?;
+        Ok(Merge {
+            readers,
+            heads,
+            compare,
+            _runs: runs,
+        })
+    }
+}
+
+impl<F: Fn(&[SqlValue], &[SqlValue]) -> Ordering> Iterator for Merge<F> {
+    type Item = Result<Entry, io::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // the fan-in is small, so the smallest head is found by a scan
+        let mut smallest: Option<usize> = None;
+        for (index, head) in self.heads.iter().enumerate() {
+            let Some((key, _)) = head else { continue };
+            let smaller = match smallest.and_then(|smallest| self.heads[smallest].as_ref()) {
+                Some((other, _)) => (self.compare)(key, other).is_lt(),
+                None => true,
+            };
+            if smaller {
+                smallest = Some(index);
+            }
+        }
+        let index = smallest?;
+        let next = match self.readers[index].next().transpose() {
+            Ok(next) => next,
+            Err(err) => return Some(Err(err)),
+        };
+        std::mem::replace(&mut self.heads[index], next).map(Ok)
+    }
+}
+
+/// The rows of an `ExternalSort` in the order of their keys
+pub enum Sorted<F> {
+    /// all the rows fit in the budget
+    Memory(std::vec::IntoIter<Entry>),
+    /// the rows are read back from the runs
+    Runs(Merge<F>),
+}
+
+impl<F: Fn(&[SqlValue], &[SqlValue]) -> Ordering> Iterator for Sorted<F> {
+    type Item = Result<Entry, io::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Sorted::Memory(entries) => entries.next().map(Ok),
+            Sorted::Runs(merge) => merge.next(),
+        }
+    }
+}
+
+/// Sorts rows by their keys within a memory budget.
+///
+/// The rows are kept in memory until their encoded size passes the budget,
+/// then they are sorted and spilled to a temporary file as a run.
+/// `finish` merges the runs, rows with equal keys keep the order they were pushed in.
+pub struct ExternalSort<F> {
+    compare: F,
+    budget: usize,
+    entries: Vec<Entry>,
+    size: usize,
+    runs: Vec<Run>,
+}
+
+impl<F: Fn(&[SqlValue], &[SqlValue]) -> Ordering> ExternalSort<F> {
+    pub fn new(budget: usize, compare: F) -> Self {
+        Self {
+            compare,
+            budget,
+            entries: Vec::new(),
+            size: 0,
+            runs: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, key: Vec<SqlValue>, row: RowValue) -> Result<(), io::Error> {
+        self.size += encode_entry(&key, &row).len();
+        self.entries.push((key, row));
+        if self.size > self.budget {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    /// number of runs written to disk so far
+    pub fn spilled_runs(&self) -> usize {
+        self.runs.len()
+    }
+
+    fn sort_entries(&mut self) {
+        let compare = &self.compare;
+        self.entries.sort_by(|(a, _), (b, _)| compare(a, b));
+    }
+
+    fn spill(&mut self) -> Result<(), io::Error> {
+        self.sort_entries();
+        self.runs.push(Run::write(self.entries.drain(..).map(Ok))?);
+        self.size = 0;
+        Ok(())
+    }
+
+    /// the rows in the order of their keys
+    pub fn finish(mut self) -> Result<Sorted<F>, io::Error> {
+        if self.runs.is_empty() {
+            self.sort_entries();
+            return Ok(Sorted::Memory(self.entries.into_iter()));
+        }
+        if !self.entries.is_empty() {
+            self.spill()?;
+        }
+        // merge neighbouring runs, so equal keys stay in the order of the runs
+        let mut runs = std::mem::take(&mut self.runs);
+        while runs.len() > MERGE_FAN_IN {
+            let mut merged = Vec::with_capacity(runs.len().div_ceil(MERGE_FAN_IN));
+            while !runs.is_empty() {
+                let group: Vec<Run> = runs.drain(..MERGE_FAN_IN.min(runs.len())).collect();
+                merged.push(Run::write(Merge::new(group, &self.compare)?)?);
+            }
+            runs = merged;
+        }
+        Ok(Sorted::Runs(Merge::new(runs, self.compare)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compare(a: &[SqlValue], b: &[SqlValue]) -> Ordering {
+        a[0].compare(&b[0]).unwrap_or(Ordering::Equal)
+    }
+
+    fn sorted_ids(budget: usize, keys: &[i32]) -> (usize, Vec<i32>) {
+        let mut sort = ExternalSort::new(budget, compare);
+        for (id, key) in keys.iter().enumerate() {
+            let row = RowValue {
+                values: vec![SqlValue::Int(id as i32), SqlValue::String("x".repeat(20))],
+            };
+            sort.push(vec![SqlValue::Int(*key)], row).unwrap();
+        }
+        let runs = sort.spilled_runs();
+        let ids = sort
+            .finish()
+            .unwrap()
+            .map(|entry| match entry.unwrap().1.values[0] {
+                SqlValue::Int(id) => id,
+                _ => panic!("Expected an id"),
+            })
+            .collect();
+        (runs, ids)
+    }
+
+    #[test]
+    fn test_sort_in_memory() {
+        let (runs, ids) = sorted_ids(DEFAULT_SORT_MEMORY, &[3, 1, 2, 1]);
+        assert_eq!(runs, 0);
+        assert_eq!(ids, vec![1, 3, 2, 0]);
+    }
+
+    #[test]
+    fn test_sort_spills() {
+        // every row is a run, and the runs are merged in several passes
+        let keys: Vec<i32> = (0..500).map(|i| (i * 37) % 100).collect();
+        let (runs, ids) = sorted_ids(1, &keys);
+        assert_eq!(runs, 500);
+        let mut expected: Vec<i32> = (0..500).collect();
+        expected.sort_by_key(|id| keys[*id as usize]);
+        assert_eq!(ids, expected);
+
+        let (runs, ids) = sorted_ids(200, &keys);
+        assert!(runs > MERGE_FAN_IN);
+        assert_eq!(ids, expected);
+    }
+
+    #[test]
+    fn test_run_round_trip() {
+        let key = vec![SqlValue::Null, SqlValue::String(String::from("a"))];
+        let row = RowValue {
+            values: vec![SqlValue::Double(1.5), SqlValue::Blob(vec![0, 255])],
+        };
+        let run = Run::write([Ok((key.clone(), row.clone()))].into_iter()).unwrap();
+        let path = run.path.clone();
+        let entries: Vec<_> = run.reader().unwrap().collect::<Result<_, _>>().unwrap();
+        assert_eq!(entries, vec![(key, row)]);
+        drop(run);
+        assert!(!path.exists());
+    }
+}
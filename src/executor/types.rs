@@ -16,6 +16,18 @@ pub struct SqlTable {
     pub rows: Vec<RowValue>,
 }
 
+/// An index on some columns of a table, kept by the storage
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexInfo {
+    pub name: String,
+    pub table: String,
+    pub columns: Vec<String>,
+    /// no two rows have the same key
+    pub unique: bool,
+    #[serde(default)]
+    pub kind: IndexKind,
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum ExecuteResponse {
     Message(String),
@@ -46,18 +58,72 @@ pub enum QueryExecutionError {
     TableDeletefail(String),
     #[error("Table {0} save fail")]
     TableSavefail(String),
+    #[error("Row of {1} bytes is too large for table {0}, a row takes at most {2} bytes")]
+    RowTooLarge(String, usize, usize),
     #[error("Table {0} open fail")]
     TableOpenfail(String),
     #[error("The value which want to delete is null")]
     ValueIsNull(String),
     #[error("No conditions obtained")]
     NoConditionsObtained(),
+    #[error("A transaction is already started")]
+    TransactionAlreadyStarted(),
+    #[error("No transaction is started")]
+    NoActiveTransaction(),
+    #[error("Savepoint {0} does not exist")]
+    SavepointNotFound(String),
+    #[error("Transaction statements need a session")]
+    TransactionOutsideSession(),
+    #[error("Transaction fail: {0}")]
+    TransactionFail(String),
+    #[error("{0} values were given for {1} columns")]
+    ValueCountMismatch(usize, usize),
+    #[error("Sort fail: {0}")]
+    SortFail(String),
+    #[error("Aggregate {0} is only allowed in the result columns, HAVING and ORDER BY")]
+    AggregateNotAllowed(String),
+    #[error("Column {0} must appear in GROUP BY or be used in an aggregate function")]
+    NotAggregated(String),
+    #[error("Table {0} was changed by a concurrent transaction")]
+    WriteConflict(String),
+    #[error("Table {0} is locked by another process")]
+    Locked(String),
+    #[error("Index {0} was not found")]
+    IndexNotFound(String),
+    #[error("Index {0} already exists")]
+    IndexAlreadyExists(String),
+    #[error("Duplicate key in unique index {0}")]
+    UniqueViolation(String),
+    #[error("Index {0} fail: {1}")]
+    IndexFail(String, String),
+    #[error("Duplicate primary key in table {0}")]
+    PrimaryKeyViolation(String),
+    #[error("Table {0} has more than one primary key")]
+    MultiplePrimaryKeys(String),
+    #[error("Column {0} could not be null")]
+    NotNullViolation(String),
+    #[error("Check constraint of column {0} failed")]
+    CheckViolation(String),
+    #[error("Column {0} refers to a row which does not exist")]
+    ForeignKeyViolation(String),
+    #[error("Column {0} does not refer to a primary key or unique column")]
+    InvalidForeignKey(String),
+    #[error("Rows of table {0} are referenced by table {1}")]
+    RowReferenced(String, String),
+    #[error("Table {0} is referenced by table {1}")]
+    TableReferenced(String, String),
+    #[error("Value of {0} is out of range")]
+    ValueOutOfRange(String),
+    #[error("Division by zero in {0}")]
+    DivisionByZero(String),
+    #[error("Value of column {0} is longer than {1} characters")]
+    ValueTooLong(String, u32),
 }
 
 pub trait Executable {
     /// The error should be check error with error message
-    fn check_and_execute(
+    fn check_and_execute<S: StorageEngine + ?Sized>(
         self,
-        storage_util: StoreUtil,
+        storage: &mut S,
     ) -> Result<ExecuteResponse, QueryExecutionError>;
 }
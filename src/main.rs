@@ -2,23 +2,25 @@ pub mod executor;
 pub mod sql_analyzer;
 pub mod storage;
 
-use executor::types::Executable;
+use executor::session::Session;
+use executor::sort;
 use miette::GraphicalReportHandler;
 use rustyline::error::ReadlineError;
 use rustyline::history::FileHistory;
 use rustyline::{Editor, Result};
 use sql_analyzer::parser::Parse;
 use sql_analyzer::types::SqlQuery;
+use std::time::Duration;
 use storage::*;
 
 const HISTORY_FILE: &str = "./data/history.txt";
+const DATA_DIR: &str = "./data";
 
-fn parse_and_execute(line: &str) {
-    let path_root = StoreUtil::Csv(String::from(r"./data/"));
-    let parse_result = SqlQuery::parse_format_error(&line);
+fn parse_and_execute(line: &str, session: &mut Session) {
+    let parse_result = SqlQuery::parse_format_error(line);
     match parse_result {
         Ok(query) => {
-            let res = query.check_and_execute(path_root);
+            let res = session.execute(query);
             match res {
                 Ok(exec_res) => println!("{exec_res}"),
                 Err(e) => {
@@ -42,12 +44,84 @@ fn parse_and_execute(line: &str) {
     }
 }
 
+/// The session used by the REPL and the name of its storage backend
+struct Backend {
+    kind: String,
+    session: Session,
+}
+
+/// handle REPL commands like `.backend json`, `.timeout 1000` or `.sort_memory 1048576`, return false if `request` is not a command
+fn run_command(request: &str, backend: &mut Backend) -> bool {
+    let mut words = request.split_whitespace();
+    match words.next() {
+        Some(".backend") => {
+            match words.next() {
+                None => println!("current backend: {}", backend.kind),
+                Some(kind) => match open_storage(kind, DATA_DIR) {
+                    Ok(storage) => {
+                        if backend.session.in_transaction() {
+                            match backend.session.execute(SqlQuery::Rollback) {
+                                Ok(_) => println!("the open transaction is rolled back"),
+                                Err(err) => println!("{err}"),
+                            }
+                        }
+                        *backend = Backend {
+                            kind: kind.to_lowercase(),
+                            session: Session::new(storage),
+                        };
+                        println!("switch to {} backend", backend.kind);
+                    }
+                    Err(err) => println!("{err}"),
+                },
+            }
+            true
+        }
+        Some(".timeout") => {
+            match words.next().map(|ms| ms.parse::<u64>()) {
+                Some(Ok(ms)) => {
+                    let timeout = Duration::from_millis(ms);
+                    backend.session.storage_mut().set_busy_timeout(timeout);
+                    println!("wait at most {ms} ms for locked tables");
+                }
+                _ => println!("usage: .timeout <milliseconds>"),
+            }
+            true
+        }
+        Some(".sort_memory") => {
+            match words.next().map(|bytes| bytes.parse::<usize>()) {
+                None => println!("sort memory: {} bytes", sort::sort_memory()),
+                Some(Ok(bytes)) => {
+                    sort::set_sort_memory(bytes);
+                    println!("ORDER BY keeps at most {bytes} bytes of rows in memory");
+                }
+                _ => println!("usage: .sort_memory <bytes>"),
+            }
+            true
+        }
+        _ => false,
+    }
+}
+
 fn main() -> Result<()> {
     let mut rl = Editor::<(), FileHistory>::new()?;
     if rl.load_history(HISTORY_FILE).is_err() {
         println!("No previous history.");
     }
-    //path
+    // the backend could be chosen by the first argument, e.g. `rust_db :memory:`
+    let kind = std::env::args().nth(1).unwrap_or(String::from("csv"));
+    let mut backend = match open_storage(&kind, DATA_DIR) {
+        Ok(storage) => Backend {
+            kind: kind.to_lowercase(),
+            session: Session::new(storage),
+        },
+        Err(err) => {
+            println!("{err}, use csv instead");
+            Backend {
+                kind: String::from("csv"),
+                session: Session::new(open_storage("csv", DATA_DIR)?),
+            }
+        }
+    };
     loop {
         let readline = rl.readline(">> ");
         match readline {
@@ -64,7 +138,9 @@ fn main() -> Result<()> {
                         break;
                     }
                     let _ = rl.add_history_entry(request);
-                    parse_and_execute(request);
+                    if !run_command(request, &mut backend) {
+                        parse_and_execute(request, &mut backend.session);
+                    }
                 }
                 if exit_flag {
                     break;
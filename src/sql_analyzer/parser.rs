@@ -5,14 +5,15 @@ use super::types::*;
 use nom::{
     branch::alt,
     bytes::complete::{tag, take_while1},
-    character::complete::{char, i32 as int32, multispace0, multispace1, none_of},
-    combinator::{all_consuming, cut, map, opt},
+    character::complete::{char, digit0, digit1, multispace0, multispace1, none_of, satisfy},
+    combinator::{all_consuming, cut, map, map_opt, map_res, not, opt, peek, recognize, verify},
     error::context,
     multi::{many0, separated_list1},
-    sequence::{delimited, preceded, separated_pair, tuple},
+    sequence::{delimited, pair, preceded, separated_pair, terminated, tuple},
     Finish,
 };
 use nom_supreme::{tag::complete::tag_no_case, ParserExt};
+use rust_decimal::Decimal;
 /// Parse a unquoted sql identifier
 fn identifier(i: Span) -> ParseResult<String> {
     map(
@@ -21,6 +22,14 @@ fn identifier(i: Span) -> ParseResult<String> {
     )(i)
 }
 
+/// a keyword which is not the start of a longer identifier
+fn keyword<'a>(word: &'static str) -> impl FnMut(Span<'a>) -> ParseResult<'a, Span<'a>> {
+    terminated(
+        tag_no_case(word),
+        not(satisfy(|c: char| c.is_alphanumeric() || c == '_')),
+    )
+}
+
 fn comma_sep<'a, O, F>(f: F) -> impl FnMut(Span<'a>) -> ParseResult<'a, Vec<O>>
 where
     F: FnMut(Span<'a>) -> ParseResult<'a, O>,
@@ -67,7 +76,85 @@ pub trait Parse<'a>: Sized {
     }
 }
 
-// parses "string | int"
+// parses "decimal(<precision>[, <scale>])", numeric is the same
+fn decimal_type(input: Span<'_>) -> ParseResult<'_, SqlType> {
+    let digits = || map_res(digit1, |s: Span| s.fragment().parse::<u32>());
+    context(
+        "Decimal Type",
+        map(
+            preceded(
+                tuple((
+                    alt((tag_no_case("decimal"), tag_no_case("numeric"))),
+                    multispace0,
+                    char('('),
+                )),
+                cut(verify(
+                    delimited(
+                        multispace0,
+                        pair(
+                            digits(),
+                            opt(preceded(
+                                tuple((multispace0, char(','), multispace0)),
+                                digits(),
+                            )),
+                        ),
+                        tuple((multispace0, char(')'))),
+                    ),
+                    |(precision, scale)| {
+                        (1..=MAX_DECIMAL_PRECISION).contains(precision)
+                            && scale.unwrap_or(0) <= *precision
+                    },
+                )),
+            ),
+            |(precision, scale)| SqlType::Decimal(precision, scale.unwrap_or(0)),
+        ),
+    )(input)
+}
+
+/// parses the length of a string type, like "(20)"
+fn type_length(input: Span<'_>) -> ParseResult<'_, u32> {
+    context(
+        "Type Length",
+        delimited(
+            tuple((multispace0, char('('), multispace0)),
+            verify(
+                map_res(digit1, |s: Span| s.fragment().parse::<u32>()),
+                |length| *length > 0,
+            ),
+            tuple((multispace0, char(')'))),
+        ),
+    )(input)
+}
+
+// parses "varchar(n)" or "char[(n)]", a char without a length has one character
+fn string_type(input: Span<'_>) -> ParseResult<'_, SqlType> {
+    alt((
+        map(
+            preceded(
+                alt((
+                    tag_no_case("varchar"),
+                    recognize(tuple((
+                        tag_no_case("character"),
+                        multispace1,
+                        tag_no_case("varying"),
+                    ))),
+                )),
+                cut(type_length),
+            ),
+            SqlType::Varchar,
+        ),
+        map(
+            preceded(
+                alt((tag_no_case("character"), tag_no_case("char"))),
+                opt(type_length),
+            ),
+            |length| SqlType::Char(length.unwrap_or(1)),
+        ),
+    ))(input)
+}
+
+// parses "string | varchar(n) | char(n) | blob | bytea | int | bigint | double | real | boolean
+// | date | time | timestamp | decimal(p, s)"
 impl<'a> Parse<'a> for SqlType {
     fn parse(input: Span<'a>) -> ParseResult<'a, Self> {
         // context will help give better error messages later on
@@ -76,40 +163,235 @@ impl<'a> Parse<'a> for SqlType {
             // alt will try each passed parser and return what ever succeeds
             alt((
                 map(tag_no_case("string"), |_| Self::String),
+                string_type,
+                map(alt((tag_no_case("blob"), tag_no_case("bytea"))), |_| {
+                    Self::Blob
+                }),
                 map(tag_no_case("int"), |_| Self::Int),
+                map(tag_no_case("bigint"), |_| Self::BigInt),
+                map(alt((tag_no_case("double"), tag_no_case("real"))), |_| {
+                    Self::Double
+                }),
+                map(alt((tag_no_case("boolean"), tag_no_case("bool"))), |_| {
+                    Self::Boolean
+                }),
+                decimal_type,
+                map(tag_no_case("date"), |_| Self::Date),
+                map(tag_no_case("timestamp"), |_| Self::Timestamp),
+                map(tag_no_case("time"), |_| Self::Time),
             )),
         )(input)
     }
 }
 
-/// parses "<colName> <colType>"
+fn primary_key(input: Span<'_>) -> ParseResult<'_, ()> {
+    map(
+        tuple((tag_no_case("primary"), multispace1, tag_no_case("key"))),
+        |_| (),
+    )(input)
+}
+
+/// A single constraint of a column definition
+enum ColumnConstraint {
+    PrimaryKey,
+    NotNull,
+    Unique,
+    Default(Expr),
+    Check(WhereConstraint),
+    References(ForeignKey),
+}
+
+fn on_delete(input: Span<'_>) -> ParseResult<'_, OnDelete> {
+    preceded(
+        tuple((
+            multispace1,
+            tag_no_case("on"),
+            multispace1,
+            tag_no_case("delete"),
+            multispace1,
+        )),
+        cut(alt((
+            map(tag_no_case("restrict"), |_| OnDelete::Restrict),
+            map(
+                tuple((tag_no_case("no"), multispace1, tag_no_case("action"))),
+                |_| OnDelete::Restrict,
+            ),
+            map(tag_no_case("cascade"), |_| OnDelete::Cascade),
+            map(
+                tuple((tag_no_case("set"), multispace1, tag_no_case("null"))),
+                |_| OnDelete::SetNull,
+            ),
+        ))),
+    )(input)
+}
+
+// parses "REFERENCES <table> (<column>) [ON DELETE <action>]"
+fn references(input: Span<'_>) -> ParseResult<'_, ForeignKey> {
+    context(
+        "References",
+        map(
+            preceded(
+                tuple((tag_no_case("references"), multispace1)),
+                cut(tuple((
+                    identifier.context("Table Name"),
+                    delimited(
+                        tuple((multispace0, char('('), multispace0)),
+                        identifier.context("Column Name"),
+                        tuple((multispace0, char(')'))),
+                    ),
+                    opt(on_delete),
+                ))),
+            ),
+            |(table, column, on_delete)| ForeignKey {
+                table,
+                column,
+                on_delete: on_delete.unwrap_or_default(),
+            },
+        ),
+    )(input)
+}
+
+fn column_constraint(input: Span<'_>) -> ParseResult<'_, ColumnConstraint> {
+    alt((
+        map(primary_key, |_| ColumnConstraint::PrimaryKey),
+        map(
+            tuple((tag_no_case("not"), multispace1, tag_no_case("null"))),
+            |_| ColumnConstraint::NotNull,
+        ),
+        map(tag_no_case("unique"), |_| ColumnConstraint::Unique),
+        map(
+            preceded(
+                tuple((tag_no_case("default"), multispace1)),
+                cut(Expr::parse),
+            ),
+            ColumnConstraint::Default,
+        ),
+        map(
+            preceded(
+                tuple((tag_no_case("check"), multispace0)),
+                cut(delimited(
+                    char('('),
+                    WhereConstraint::parse_constraits,
+                    tuple((multispace0, char(')'))),
+                )),
+            ),
+            ColumnConstraint::Check,
+        ),
+        map(references, ColumnConstraint::References),
+    ))(input)
+}
+
+/// parses the constraints following the type of a column,
+/// like "PRIMARY KEY", "NOT NULL", "UNIQUE", "DEFAULT 0", "CHECK (age >= 0)"
+/// or "REFERENCES users(id) ON DELETE CASCADE"
+impl<'a> Parse<'a> for ColumnConstraints {
+    fn parse(input: Span<'a>) -> ParseResult<'a, Self> {
+        context(
+            "Column Constraints",
+            map(many0(preceded(multispace1, column_constraint)), |clauses| {
+                let mut constraints = Self::default();
+                for clause in clauses {
+                    match clause {
+                        ColumnConstraint::PrimaryKey => constraints.primary_key = true,
+                        ColumnConstraint::NotNull => constraints.not_null = true,
+                        ColumnConstraint::Unique => constraints.unique = true,
+                        ColumnConstraint::Default(value) => constraints.default = Some(value),
+                        ColumnConstraint::Check(check) => constraints.check = Some(check),
+                        ColumnConstraint::References(references) => {
+                            constraints.references = Some(references)
+                        }
+                    }
+                }
+                constraints
+            }),
+        )(input)
+    }
+}
+
+/// parses "<colName> <colType> <constraints>"
 impl<'a> Parse<'a> for Column {
     fn parse(input: Span<'a>) -> ParseResult<'a, Self> {
         context(
             "Create Column",
             map(
-                separated_pair(
+                tuple((
                     identifier.context("Column Name"),
                     multispace1,
                     SqlType::parse,
-                ),
-                |(name, type_info)| Self { name, type_info },
+                    ColumnConstraints::parse,
+                )),
+                |(name, _, type_info, constraints)| Self {
+                    name,
+                    type_info,
+                    constraints,
+                },
             ),
         )(input)
     }
 }
 
-// parses a comma seperated list of column definitions contained in parens
-fn column_definitions(input: Span<'_>) -> ParseResult<'_, Vec<Column>> {
+/// A column definition or a table constraint in CREATE TABLE
+enum TableElement {
+    Column(Column),
+    PrimaryKey(Vec<String>),
+    ForeignKey(String, ForeignKey),
+}
+
+fn table_element(input: Span<'_>) -> ParseResult<'_, TableElement> {
+    alt((
+        map(
+            preceded(primary_key, insert_columns),
+            TableElement::PrimaryKey,
+        ),
+        // a foreign key has a single column
+        map(
+            preceded(
+                tuple((tag_no_case("foreign"), multispace1, tag_no_case("key"))),
+                cut(tuple((
+                    delimited(
+                        tuple((multispace0, char('('), multispace0)),
+                        identifier.context("Column Name"),
+                        tuple((multispace0, char(')'), multispace0)),
+                    ),
+                    references,
+                ))),
+            ),
+            |(column, references)| TableElement::ForeignKey(column, references),
+        ),
+        map(Column::parse, TableElement::Column),
+    ))(input)
+}
+
+// parses a comma seperated list of column definitions contained in parens,
+// with at most one table-level primary key
+fn column_definitions(input: Span<'_>) -> ParseResult<'_, CreateStatement> {
     context(
         "Column Definitions",
         map(
             delimited(
                 tuple((multispace0, char('('))),
-                comma_sep(Column::parse),
+                verify(comma_sep(table_element), |elements: &Vec<TableElement>| {
+                    elements
+                        .iter()
+                        .filter(|element| matches!(element, TableElement::PrimaryKey(_)))
+                        .count()
+                        <= 1
+                }),
                 tuple((multispace0, char(')'))),
             ),
-            |cols| cols,
+            |elements| {
+                let mut create = CreateStatement::default();
+                for element in elements {
+                    match element {
+                        TableElement::Column(column) => create.columns.push(column),
+                        TableElement::PrimaryKey(names) => create.primary_key = names,
+                        TableElement::ForeignKey(column, references) => {
+                            create.foreign_keys.push((column, references))
+                        }
+                    }
+                }
+                create
+            },
         ),
     )(input)
 }
@@ -134,7 +416,7 @@ impl<'a> Parse<'a> for CreateStatement {
                 cut(column_definitions),
             )
             .context("Create Table"),
-            |(table, columns)| Self { table, columns },
+            |(table, create)| Self { table, ..create },
         )(input)
     }
 }
@@ -158,6 +440,73 @@ impl<'a> Parse<'a> for DropStatement {
     }
 }
 
+impl<'a> Parse<'a> for IndexKind {
+    fn parse(input: Span<'a>) -> ParseResult<'a, Self> {
+        context(
+            "Index Kind",
+            alt((
+                map(tag_no_case("btree"), |_| Self::BTree),
+                map(tag_no_case("hash"), |_| Self::Hash),
+            )),
+        )(input)
+    }
+}
+
+impl<'a> Parse<'a> for CreateIndexStatement {
+    fn parse(input: Span<'a>) -> ParseResult<'a, Self> {
+        map(
+            tuple((
+                preceded(
+                    tuple((multispace0, tag_no_case("create"), multispace1)),
+                    opt(tuple((tag_no_case("unique"), multispace1))),
+                ),
+                preceded(
+                    tuple((tag_no_case("index"), multispace1)),
+                    cut(tuple((
+                        identifier.context("Index Name"),
+                        multispace1,
+                        tag_no_case("on"),
+                        multispace1,
+                        identifier.context("Table Name"),
+                        opt(preceded(
+                            tuple((multispace1, tag_no_case("using"), multispace1)),
+                            IndexKind::parse,
+                        )),
+                        insert_columns,
+                    ))),
+                ),
+            ))
+            .context("Create Index"),
+            |(unique, (name, _, _, _, table, kind, columns))| Self {
+                name,
+                table,
+                columns,
+                unique: unique.is_some(),
+                kind: kind.unwrap_or_default(),
+            },
+        )(input)
+    }
+}
+
+impl<'a> Parse<'a> for DropIndexStatement {
+    fn parse(input: Span<'a>) -> ParseResult<'a, Self> {
+        map(
+            preceded(
+                tuple((
+                    multispace0,
+                    tag_no_case("drop"),
+                    multispace1,
+                    tag_no_case("index"),
+                    multispace1,
+                )),
+                cut(identifier.context("Index Name")),
+            )
+            .context("Drop Index"),
+            |name| Self { name },
+        )(input)
+    }
+}
+
 /// String value in SQL statement should be wrapped with apostrophes
 impl<'a> Parse<'a> for String {
     fn parse(input: Span<'a>) -> ParseResult<'a, Self> {
@@ -168,32 +517,260 @@ impl<'a> Parse<'a> for String {
     }
 }
 
+fn exponent(input: Span<'_>) -> ParseResult<'_, Span<'_>> {
+    recognize(tuple((
+        alt((char('e'), char('E'))),
+        opt(alt((char('+'), char('-')))),
+        digit1,
+    )))(input)
+}
+
+/// parses a number: a decimal if it has a fraction, a double if it has an exponent
+/// or more digits than a decimal keeps, otherwise an int, or a bigint if it does not fit in 32 bits
+fn number(input: Span<'_>) -> ParseResult<'_, SqlValue> {
+    alt((
+        map_res(
+            recognize(tuple((
+                opt(char('-')),
+                digit1,
+                alt((
+                    recognize(tuple((char('.'), digit0, opt(exponent)))),
+                    exponent,
+                )),
+            ))),
+            |s: Span| match Decimal::from_str_exact(s.fragment()) {
+                Ok(d) if !s.fragment().contains(['e', 'E']) => Ok(SqlValue::Decimal(d)),
+                _ => s.fragment().parse::<f64>().map(SqlValue::Double),
+            },
+        ),
+        map_res(recognize(tuple((opt(char('-')), digit1))), |s: Span| {
+            s.fragment()
+                .parse::<i64>()
+                .map(|i| i32::try_from(i).map_or(SqlValue::BigInt(i), SqlValue::Int))
+        }),
+    ))(input)
+}
+
+// parses "DATE '2024-01-31'", "TIME '10:30:00'" or "TIMESTAMP '2024-01-31 10:30:00'"
+fn typed_literal(input: Span<'_>) -> ParseResult<'_, SqlValue> {
+    let (input, type_info) = alt((
+        map(keyword("date"), |_| SqlType::Date),
+        map(keyword("timestamp"), |_| SqlType::Timestamp),
+        map(keyword("time"), |_| SqlType::Time),
+    ))(input)?;
+    context(
+        "Date or Time",
+        preceded(
+            // without a string it is a column named like the type
+            tuple((multispace0, peek(char('\'')))),
+            cut(map_opt(String::parse, move |text| {
+                SqlValue::from_text(&text, &type_info)
+            })),
+        ),
+    )(input)
+}
+
+// parses "X'<hex digits>'"
+fn blob_literal(input: Span<'_>) -> ParseResult<'_, SqlValue> {
+    context(
+        "Blob",
+        preceded(
+            // without a string it is a column named x
+            tuple((tag_no_case("x"), peek(char('\'')))),
+            cut(map_opt(String::parse, |text| {
+                decode_hex(&text).map(SqlValue::Blob)
+            })),
+        ),
+    )(input)
+}
+
 impl<'a> Parse<'a> for SqlValue {
     fn parse(input: Span<'a>) -> ParseResult<'a, Self> {
         context(
             "Sql Value",
             alt((
-                map(int32, |i| Self::Int(i)),
-                map(String::parse, |s| Self::String(s)),
+                number,
+                map(String::parse, Self::String),
+                map(keyword("true"), |_| Self::Boolean(true)),
+                map(keyword("false"), |_| Self::Boolean(false)),
+                map(keyword("null"), |_| Self::Null),
+                typed_literal,
+                blob_literal,
             )),
         )(input)
     }
 }
 
-impl<'a> Parse<'a> for RowValue {
-    fn parse(input: Span<'a>) -> ParseResult<'a, Self> {
-        // context will help give better error messages later on
+fn date_field(input: Span<'_>) -> ParseResult<'_, DateField> {
+    context(
+        "Date Field",
+        alt((
+            map(keyword("year"), |_| DateField::Year),
+            map(keyword("month"), |_| DateField::Month),
+            map(keyword("day"), |_| DateField::Day),
+            map(keyword("hour"), |_| DateField::Hour),
+            map(keyword("minute"), |_| DateField::Minute),
+            map(keyword("second"), |_| DateField::Second),
+            map(keyword("epoch"), |_| DateField::Epoch),
+        )),
+    )(input)
+}
+
+// parses "INTERVAL '<n>' <field>", the quotes may be left out
+fn interval(input: Span<'_>) -> ParseResult<'_, Expr> {
+    context(
+        "Interval",
+        map(
+            preceded(
+                tuple((keyword("interval"), multispace0)),
+                cut(tuple((
+                    alt((
+                        map_res(String::parse, |text| text.trim().parse::<i64>()),
+                        map_res(recognize(pair(opt(char('-')), digit1)), |s: Span| {
+                            s.fragment().parse::<i64>()
+                        }),
+                    )),
+                    multispace1,
+                    verify(date_field, |field| *field != DateField::Epoch),
+                ))),
+            ),
+            |(amount, _, field)| Expr::Interval(amount, field),
+        ),
+    )(input)
+}
+
+// parses "EXTRACT(<field> FROM <expr>)"
+fn extract(input: Span<'_>) -> ParseResult<'_, Expr> {
+    context(
+        "Extract",
+        map(
+            preceded(
+                tuple((keyword("extract"), multispace0, char('('))),
+                cut(tuple((
+                    multispace0,
+                    date_field,
+                    multispace1,
+                    tag_no_case("from"),
+                    multispace1,
+                    Expr::parse,
+                    multispace0,
+                    char(')'),
+                ))),
+            ),
+            |(_, field, _, _, _, expr, _, _)| Expr::Extract(field, Box::new(expr)),
+        ),
+    )(input)
+}
+
+fn aggregate_function(input: Span<'_>) -> ParseResult<'_, AggregateFunction> {
+    alt((
+        map(keyword("count"), |_| AggregateFunction::Count),
+        map(keyword("sum"), |_| AggregateFunction::Sum),
+        map(keyword("avg"), |_| AggregateFunction::Avg),
+        map(keyword("min"), |_| AggregateFunction::Min),
+        map(keyword("max"), |_| AggregateFunction::Max),
+    ))(input)
+}
+
+// parses "count(*)" or "function([distinct] expr)", a name without "(" is a column
+fn aggregate(input: Span<'_>) -> ParseResult<'_, Expr> {
+    context(
+        "Aggregate",
+        map_res(
+            pair(
+                terminated(aggregate_function, tuple((multispace0, char('(')))),
+                cut(terminated(
+                    alt((
+                        map(tuple((multispace0, char('*'))), |_| (false, None)),
+                        pair(
+                            map(opt(tuple((multispace0, keyword("distinct")))), |distinct| {
+                                distinct.is_some()
+                            }),
+                            map(Expr::parse, Some),
+                        ),
+                    )),
+                    tuple((multispace0, char(')'))),
+                )),
+            ),
+            |(function, (distinct, arg))| match (function, &arg) {
+                (AggregateFunction::Count, _) | (_, Some(_)) => Ok(Expr::Aggregate(Aggregate {
+                    function,
+                    distinct,
+                    arg: arg.map(Box::new),
+                })),
+                _ => Err(format!("{function}(*) is not an aggregate")),
+            },
+        ),
+    )(input)
+}
+
+impl<'a> Expr {
+    fn parse_operand(input: Span<'a>) -> ParseResult<'a, Self> {
+        preceded(
+            multispace0,
+            alt((
+                map(SqlValue::parse, Self::Value),
+                interval,
+                extract,
+                aggregate,
+                map(
+                    tuple((
+                        keyword("now"),
+                        multispace0,
+                        char('('),
+                        multispace0,
+                        char(')'),
+                    )),
+                    |_| Self::Now,
+                ),
+                map(keyword("current_timestamp"), |_| Self::Now),
+                map(keyword("current_date"), |_| Self::CurrentDate),
+                delimited(char('('), Self::parse, tuple((multispace0, char(')')))),
+                map(identifier, Self::Column),
+            )),
+        )(input)
+    }
+
+    /// operands joined by * and /, which bind tighter than + and -
+    fn parse_term(input: Span<'a>) -> ParseResult<'a, Self> {
         map(
-            context(
-                "Value of Insert Row",
-                // alt will try each passed parser and return what ever succeeds
-                delimited(
-                    tuple((multispace0, char('('), multispace0)),
-                    comma_sep(SqlValue::parse),
-                    tuple((multispace0, char(')'), multispace0)),
+            pair(
+                Self::parse_operand,
+                many0(pair(
+                    preceded(multispace0, alt((char('*'), char('/')))),
+                    cut(Self::parse_operand),
+                )),
+            ),
+            |(first, rest)| {
+                rest.into_iter().fold(first, |left, (op, right)| match op {
+                    '*' => Self::Mul(Box::new(left), Box::new(right)),
+                    _ => Self::Div(Box::new(left), Box::new(right)),
+                })
+            },
+        )(input)
+    }
+}
+
+/// terms joined by + and -, like "created + INTERVAL '1' DAY" or "price * 2 - discount"
+impl<'a> Parse<'a> for Expr {
+    fn parse(input: Span<'a>) -> ParseResult<'a, Self> {
+        context(
+            "Expression",
+            map(
+                pair(
+                    Self::parse_term,
+                    many0(pair(
+                        preceded(multispace0, alt((char('+'), char('-')))),
+                        cut(Self::parse_term),
+                    )),
                 ),
+                |(first, rest)| {
+                    rest.into_iter().fold(first, |left, (op, right)| match op {
+                        '+' => Self::Add(Box::new(left), Box::new(right)),
+                        _ => Self::Sub(Box::new(left), Box::new(right)),
+                    })
+                },
             ),
-            |values| Self { values },
         )(input)
     }
 }
@@ -228,7 +805,14 @@ impl<'a> Parse<'a> for InsertStatement {
                 opt(insert_columns),
                 cut(preceded(
                     tuple((multispace0, tag_no_case("values"))),
-                    RowValue::parse,
+                    context(
+                        "Value of Insert Row",
+                        delimited(
+                            tuple((multispace0, char('('), multispace0)),
+                            comma_sep(Expr::parse),
+                            tuple((multispace0, char(')'), multispace0)),
+                        ),
+                    ),
                 )),
             ))
             .context("Insert Rows"),
@@ -259,23 +843,49 @@ impl<'a> Parse<'a> for CmpOpt {
 }
 
 impl<'a> WhereConstraint {
-    fn parse_constrait(input: Span<'a>) -> ParseResult<'a, Self> {
+    // parses "<column> IS [NOT] NULL"
+    fn parse_is_null(input: Span<'a>) -> ParseResult<'a, Self> {
         context(
-            "Constrait",
+            "Is Null",
             map(
                 tuple((
                     multispace0,
                     identifier,
-                    multispace0,
-                    CmpOpt::parse,
-                    multispace0,
-                    cut(SqlValue::parse),
+                    multispace1,
+                    tag_no_case("is"),
+                    multispace1,
+                    cut(tuple((
+                        opt(tuple((tag_no_case("not"), multispace1))),
+                        tag_no_case("null"),
+                    ))),
                 )),
-                |(_, column, _, op, _, value)| Self::Constrait(column, op, value),
+                |(_, column, _, _, _, (not, _))| match not {
+                    Some(_) => Self::Not(Box::new(Self::IsNull(column))),
+                    None => Self::IsNull(column),
+                },
             ),
         )(input)
     }
 
+    fn parse_constrait(input: Span<'a>) -> ParseResult<'a, Self> {
+        context(
+            "Constrait",
+            alt((
+                Self::parse_is_null,
+                map(
+                    tuple((Expr::parse, multispace0, CmpOpt::parse, cut(Expr::parse))),
+                    |(left, _, op, right)| match (left, right) {
+                        // a column compared with a value may be looked up in an index
+                        (Expr::Column(column), Expr::Value(value)) => {
+                            Self::Constrait(column, op, value)
+                        }
+                        (left, right) => Self::Compare(left, op, right),
+                    },
+                ),
+            )),
+        )(input)
+    }
+
     fn parse_and(input: Span<'a>) -> ParseResult<'a, Self> {
         context(
             "Constraits combined with `and`",
@@ -284,7 +894,7 @@ impl<'a> WhereConstraint {
                     multispace0,
                     Self::parse_constrait,
                     multispace1,
-                    tag_no_case("and"),
+                    keyword("and"),
                     cut(Self::parse_constraits),
                 )),
                 |(_, cons_l, _, _, cons_r)| Self::And(Box::new(cons_l), Box::new(cons_r)),
@@ -300,7 +910,7 @@ impl<'a> WhereConstraint {
                     multispace0,
                     Self::parse_constrait,
                     multispace1,
-                    tag_no_case("or"),
+                    keyword("or"),
                     cut(Self::parse_constraits),
                 )),
                 |(_, cons_l, _, _, cons_r)| Self::Or(Box::new(cons_l), Box::new(cons_r)),
@@ -322,7 +932,12 @@ impl<'a> WhereConstraint {
     }
 
     fn parse_constraits(input: Span<'a>) -> ParseResult<'a, Self> {
-        alt((Self::parse_not, Self::parse_and, Self::parse_or, cut(Self::parse_constrait)))(input)
+        alt((
+            Self::parse_not,
+            Self::parse_and,
+            Self::parse_or,
+            cut(Self::parse_constrait),
+        ))(input)
     }
 }
 
@@ -339,33 +954,148 @@ impl<'a> Parse<'a> for WhereConstraint {
     }
 }
 
-fn result_columns<'a>(input: Span<'a>) -> ParseResult<'a, Vec<String>> {
-    context(
-        "Result Columns",
-        comma_sep(alt((identifier, map(tag("*"), |_| String::from("*"))))),
-    )(input)
+// parses "*" or "<expr> [AS <alias>]"
+fn select_item(input: Span<'_>) -> ParseResult<'_, SelectItem> {
+    alt((
+        map(tag("*"), |_| SelectItem::All),
+        map(
+            pair(
+                Expr::parse,
+                opt(preceded(
+                    tuple((multispace1, keyword("as"), multispace1)),
+                    cut(identifier.context("Alias")),
+                )),
+            ),
+            |(expr, alias)| SelectItem::Expr(expr, alias),
+        ),
+    ))(input)
 }
 
-impl<'a> Parse<'a> for SelectStatement {
+fn result_columns<'a>(input: Span<'a>) -> ParseResult<'a, Vec<SelectItem>> {
+    context("Result Columns", comma_sep(select_item))(input)
+}
+
+// parses "<expr> [ASC | DESC] [NULLS FIRST | NULLS LAST]"
+impl<'a> Parse<'a> for OrderItem {
     fn parse(input: Span<'a>) -> ParseResult<'a, Self> {
         context(
-            "Select Statement",
+            "Order Item",
             map(
-                preceded(
-                    tuple((multispace0, tag_no_case("select"), multispace0)),
-                    cut(tuple((
-                        result_columns,
-                        multispace0,
-                        tag_no_case("from"),
-                        multispace0,
-                        identifier,
-                        opt(WhereConstraint::parse),
-                    ))),
-                ),
-                |(columns, _, _, _, table, constraints)| Self {
-                    table,
+                tuple((
+                    Expr::parse,
+                    opt(preceded(
+                        multispace1,
+                        alt((
+                            map(keyword("asc"), |_| false),
+                            map(keyword("desc"), |_| true),
+                        )),
+                    )),
+                    opt(preceded(
+                        tuple((multispace1, keyword("nulls"))),
+                        cut(preceded(
+                            multispace1,
+                            alt((
+                                map(keyword("first"), |_| true),
+                                map(keyword("last"), |_| false),
+                            )),
+                        )),
+                    )),
+                )),
+                |(expr, descending, nulls_first)| Self {
+                    expr,
+                    descending: descending.unwrap_or(false),
+                    nulls_first,
+                },
+            ),
+        )(input)
+    }
+}
+
+fn order_by(input: Span<'_>) -> ParseResult<'_, Vec<OrderItem>> {
+    context(
+        "Order By",
+        preceded(
+            tuple((multispace0, keyword("order"), multispace1, keyword("by"))),
+            cut(comma_sep(OrderItem::parse)),
+        ),
+    )(input)
+}
+
+fn group_by(input: Span<'_>) -> ParseResult<'_, Vec<String>> {
+    context(
+        "Group By",
+        preceded(
+            tuple((multispace0, keyword("group"), multispace1, keyword("by"))),
+            cut(comma_sep(identifier)),
+        ),
+    )(input)
+}
+
+fn having(input: Span<'_>) -> ParseResult<'_, WhereConstraint> {
+    context(
+        "Having",
+        preceded(
+            tuple((multispace0, keyword("having"))),
+            cut(WhereConstraint::parse_constraits),
+        ),
+    )(input)
+}
+
+fn row_count(input: Span<'_>) -> ParseResult<'_, u64> {
+    map_res(digit1, |s: Span| s.fragment().parse::<u64>())(input)
+}
+
+// parses "limit n [offset m]"
+fn limit(input: Span<'_>) -> ParseResult<'_, Limit> {
+    context(
+        "Limit",
+        map(
+            preceded(
+                tuple((multispace0, keyword("limit"))),
+                cut(tuple((
+                    preceded(multispace1, row_count),
+                    opt(preceded(
+                        tuple((multispace1, keyword("offset"))),
+                        cut(preceded(multispace1, row_count)),
+                    )),
+                ))),
+            ),
+            |(count, offset)| Limit {
+                count,
+                offset: offset.unwrap_or(0),
+            },
+        ),
+    )(input)
+}
+
+impl<'a> Parse<'a> for SelectStatement {
+    fn parse(input: Span<'a>) -> ParseResult<'a, Self> {
+        context(
+            "Select Statement",
+            map(
+                preceded(
+                    tuple((multispace0, tag_no_case("select"), multispace0)),
+                    cut(tuple((
+                        result_columns,
+                        multispace0,
+                        tag_no_case("from"),
+                        multispace0,
+                        identifier,
+                        opt(WhereConstraint::parse),
+                        opt(group_by),
+                        opt(having),
+                        opt(order_by),
+                        opt(limit),
+                    ))),
+                ),
+                |(columns, _, _, _, table, constraints, group_by, having, order_by, limit)| Self {
+                    table,
                     columns,
                     constraints,
+                    group_by: group_by.unwrap_or_default(),
+                    having,
+                    order_by: order_by.unwrap_or_default(),
+                    limit,
                 },
             ),
         )(input)
@@ -401,7 +1131,7 @@ impl<'a> Parse<'a> for SetItem {
                 separated_pair(
                     identifier,
                     tuple((multispace0, char('='), multispace0)),
-                    SqlValue::parse,
+                    Expr::parse,
                 ),
                 |(column, value)| Self { column, value },
             ),
@@ -436,6 +1166,65 @@ impl<'a> Parse<'a> for UpdateStatement {
     }
 }
 
+/// BEGIN [TRANSACTION], COMMIT or ROLLBACK
+fn transaction_statement(input: Span<'_>) -> ParseResult<'_, SqlQuery> {
+    context(
+        "Transaction Statement",
+        preceded(
+            multispace0,
+            alt((
+                map(
+                    tuple((
+                        tag_no_case("begin"),
+                        opt(preceded(multispace1, tag_no_case("transaction"))),
+                    )),
+                    |_| SqlQuery::Begin,
+                ),
+                map(tag_no_case("commit"), |_| SqlQuery::Commit),
+                savepoint_statement,
+                map(tag_no_case("rollback"), |_| SqlQuery::Rollback),
+            )),
+        ),
+    )(input)
+}
+
+/// SAVEPOINT name, RELEASE [SAVEPOINT] name or ROLLBACK TO [SAVEPOINT] name
+fn savepoint_statement(input: Span<'_>) -> ParseResult<'_, SqlQuery> {
+    // the optional SAVEPOINT keyword is followed by the name
+    fn savepoint_name(input: Span<'_>) -> ParseResult<'_, String> {
+        preceded(
+            opt(tuple((tag_no_case("savepoint"), multispace1))),
+            identifier,
+        )(input)
+    }
+
+    context(
+        "Savepoint Statement",
+        alt((
+            map(
+                preceded(tuple((tag_no_case("savepoint"), multispace1)), identifier),
+                SqlQuery::Savepoint,
+            ),
+            map(
+                preceded(tuple((tag_no_case("release"), multispace1)), savepoint_name),
+                SqlQuery::Release,
+            ),
+            map(
+                preceded(
+                    tuple((
+                        tag_no_case("rollback"),
+                        multispace1,
+                        tag_no_case("to"),
+                        multispace1,
+                    )),
+                    savepoint_name,
+                ),
+                SqlQuery::RollbackTo,
+            ),
+        )),
+    )(input)
+}
+
 impl<'a> Parse<'a> for SqlQuery {
     fn parse(input: Span<'a>) -> ParseResult<'a, Self> {
         let (rest, (query, _, _, _)) = tuple((
@@ -447,6 +1236,9 @@ impl<'a> Parse<'a> for SqlQuery {
                 map(DeleteStatement::parse, SqlQuery::Delete),
                 map(DropStatement::parse, SqlQuery::Drop),
                 map(UpdateStatement::parse, SqlQuery::Update),
+                map(CreateIndexStatement::parse, SqlQuery::CreateIndex),
+                map(DropIndexStatement::parse, SqlQuery::DropIndex),
+                transaction_statement,
             )),
             multispace0,
             char(';'),
@@ -467,16 +1259,21 @@ mod test_create_stmt {
                 Column {
                     name: "col1".into(),
                     type_info: SqlType::Int,
+                    constraints: ColumnConstraints::default(),
                 },
                 Column {
                     name: "col2".into(),
                     type_info: SqlType::String,
+                    constraints: ColumnConstraints::default(),
                 },
                 Column {
                     name: "col3".into(),
                     type_info: SqlType::String,
+                    constraints: ColumnConstraints::default(),
                 },
             ],
+            primary_key: Vec::new(),
+            foreign_keys: Vec::new(),
         };
         assert_eq!(
             CreateStatement::parse_from_raw(
@@ -489,6 +1286,207 @@ mod test_create_stmt {
     }
 }
 
+#[cfg(test)]
+mod test_column_types {
+    use super::*;
+    #[test]
+    fn test_create_types() {
+        let create = CreateStatement::parse_from_raw(
+            "CREATE TABLE foo (a bigint, b double, c real, d boolean, e bool, f int)",
+        )
+        .unwrap()
+        .1;
+        let types: Vec<SqlType> = create
+            .columns
+            .into_iter()
+            .map(|column| column.type_info)
+            .collect();
+        assert_eq!(
+            types,
+            vec![
+                SqlType::BigInt,
+                SqlType::Double,
+                SqlType::Double,
+                SqlType::Boolean,
+                SqlType::Boolean,
+                SqlType::Int,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_string_types() {
+        let create = CreateStatement::parse_from_raw(
+            "CREATE TABLE foo (a varchar(20), b CHAR(3), c char, d character varying (5), e blob, f bytea)",
+        )
+        .unwrap()
+        .1;
+        let types: Vec<SqlType> = create
+            .columns
+            .into_iter()
+            .map(|column| column.type_info)
+            .collect();
+        assert_eq!(
+            types,
+            vec![
+                SqlType::Varchar(20),
+                SqlType::Char(3),
+                SqlType::Char(1),
+                SqlType::Varchar(5),
+                SqlType::Blob,
+                SqlType::Blob,
+            ]
+        );
+        assert!(CreateStatement::parse_from_raw("CREATE TABLE foo (a varchar)").is_err());
+        assert!(CreateStatement::parse_from_raw("CREATE TABLE foo (a varchar(0))").is_err());
+
+        let insert =
+            InsertStatement::parse_from_raw("INSERT INTO foo VALUES (X'DEADbeef', x'', x)")
+                .unwrap()
+                .1;
+        assert_eq!(
+            insert.values,
+            vec![
+                Expr::Value(SqlValue::Blob(vec![0xde, 0xad, 0xbe, 0xef])),
+                Expr::Value(SqlValue::Blob(Vec::new())),
+                Expr::Column(String::from("x")),
+            ]
+        );
+        assert!(InsertStatement::parse_from_raw("INSERT INTO foo VALUES (X'abc')").is_err());
+        assert!(InsertStatement::parse_from_raw("INSERT INTO foo VALUES (X'zz')").is_err());
+    }
+
+    #[test]
+    fn test_decimal_type() {
+        let create =
+            CreateStatement::parse_from_raw("CREATE TABLE foo (a decimal(10, 2), b NUMERIC(5))")
+                .unwrap()
+                .1;
+        let types: Vec<SqlType> = create
+            .columns
+            .into_iter()
+            .map(|column| column.type_info)
+            .collect();
+        assert_eq!(types, vec![SqlType::Decimal(10, 2), SqlType::Decimal(5, 0)]);
+        for sql in [
+            "CREATE TABLE foo (a decimal(0))",
+            "CREATE TABLE foo (a decimal(29, 2))",
+            "CREATE TABLE foo (a decimal(4, 5))",
+        ] {
+            assert!(CreateStatement::parse_from_raw(sql).is_err());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_primary_key {
+    use super::*;
+    #[test]
+    fn test_primary_key() {
+        let create =
+            CreateStatement::parse_from_raw("CREATE TABLE foo (id int primary key, name string)")
+                .unwrap()
+                .1;
+        assert!(create.columns[0].constraints.primary_key);
+        assert!(!create.columns[1].constraints.primary_key);
+        assert!(create.primary_key.is_empty());
+
+        let create = CreateStatement::parse_from_raw(
+            "CREATE TABLE foo (a int, b string, PRIMARY KEY (a, b))",
+        )
+        .unwrap()
+        .1;
+        assert_eq!(create.columns.len(), 2);
+        assert_eq!(
+            create.primary_key,
+            vec![String::from("a"), String::from("b")]
+        );
+
+        assert!(CreateStatement::parse_from_raw(
+            "CREATE TABLE foo (a int, PRIMARY KEY (a), PRIMARY KEY (a))"
+        )
+        .is_err());
+    }
+}
+
+#[cfg(test)]
+mod test_column_constraints {
+    use super::*;
+    #[test]
+    fn test_column_constraints() {
+        let create = CreateStatement::parse_from_raw(
+            "CREATE TABLE foo (id int not null unique, age int default 18 check (age > 0), name string)",
+        )
+        .unwrap()
+        .1;
+        let constraints = &create.columns[0].constraints;
+        assert!(constraints.not_null && constraints.unique && !constraints.primary_key);
+        let constraints = &create.columns[1].constraints;
+        assert_eq!(constraints.default, Some(Expr::Value(SqlValue::Int(18))));
+        assert_eq!(
+            constraints.check,
+            Some(WhereConstraint::Constrait(
+                String::from("age"),
+                CmpOpt::Gt,
+                SqlValue::Int(0)
+            ))
+        );
+        assert_eq!(create.columns[2].constraints, ColumnConstraints::default());
+
+        assert!(CreateStatement::parse_from_raw("CREATE TABLE foo (id int default)").is_err());
+    }
+}
+
+#[cfg(test)]
+mod test_foreign_key {
+    use super::*;
+    #[test]
+    fn test_foreign_key() {
+        let create = CreateStatement::parse_from_raw(
+            "CREATE TABLE foo (id int references bar(id), owner int, foreign key (owner) references users (id) on delete set null)",
+        )
+        .unwrap()
+        .1;
+        assert_eq!(
+            create.columns[0].constraints.references,
+            Some(ForeignKey {
+                table: String::from("bar"),
+                column: String::from("id"),
+                on_delete: OnDelete::Restrict,
+            })
+        );
+        assert_eq!(create.columns.len(), 2);
+        assert_eq!(
+            create.foreign_keys,
+            vec![(
+                String::from("owner"),
+                ForeignKey {
+                    table: String::from("users"),
+                    column: String::from("id"),
+                    on_delete: OnDelete::SetNull,
+                }
+            )]
+        );
+
+        let create = CreateStatement::parse_from_raw(
+            "CREATE TABLE foo (id int REFERENCES bar(id) ON DELETE CASCADE NOT NULL)",
+        )
+        .unwrap()
+        .1;
+        let constraints = &create.columns[0].constraints;
+        assert!(constraints.not_null);
+        assert_eq!(
+            constraints.references.as_ref().unwrap().on_delete,
+            OnDelete::Cascade
+        );
+
+        assert!(CreateStatement::parse_from_raw(
+            "CREATE TABLE foo (id int REFERENCES bar(id) ON DELETE DROP)"
+        )
+        .is_err());
+    }
+}
+
 #[cfg(test)]
 mod test_drop_stmt {
     use super::*;
@@ -512,13 +1510,11 @@ mod test_insert_stmt {
         let expected = InsertStatement {
             table: String::from("foo"),
             columns: None,
-            values: RowValue {
-                values: vec![
-                    SqlValue::String(String::from("abc")),
-                    SqlValue::Int(123),
-                    SqlValue::String(String::from("def")),
-                ],
-            },
+            values: vec![
+                Expr::Value(SqlValue::String(String::from("abc"))),
+                Expr::Value(SqlValue::Int(123)),
+                Expr::Value(SqlValue::String(String::from("def"))),
+            ],
         };
         let parse_result =
             InsertStatement::parse_from_raw("INSERT INTO foo VALUES ('abc', 123, 'def')")
@@ -527,6 +1523,31 @@ mod test_insert_stmt {
         assert_eq!(parse_result, expected)
     }
 
+    #[test]
+    fn test_insert_literals() {
+        let insert = InsertStatement::parse_from_raw(
+            "INSERT INTO foo VALUES (1, -2147483649, 10000000000, 1.5, -2., 3e2, 4.5E-1, true, FALSE, null)",
+        )
+        .unwrap()
+        .1;
+        assert_eq!(
+            insert.values,
+            [
+                SqlValue::Int(1),
+                SqlValue::BigInt(-2147483649),
+                SqlValue::BigInt(10000000000),
+                SqlValue::Decimal(Decimal::new(15, 1)),
+                SqlValue::Decimal(Decimal::new(-2, 0)),
+                SqlValue::Double(300.0),
+                SqlValue::Double(0.45),
+                SqlValue::Boolean(true),
+                SqlValue::Boolean(false),
+                SqlValue::Null,
+            ]
+            .map(Expr::Value)
+        );
+    }
+
     #[test]
     fn test_insert_stmt2() {
         let expected = InsertStatement {
@@ -536,13 +1557,11 @@ mod test_insert_stmt {
                 String::from("id"),
                 String::from("value"),
             ]),
-            values: RowValue {
-                values: vec![
-                    SqlValue::String(String::from("abc")),
-                    SqlValue::Int(123),
-                    SqlValue::String(String::from("def")),
-                ],
-            },
+            values: vec![
+                Expr::Value(SqlValue::String(String::from("abc"))),
+                Expr::Value(SqlValue::Int(123)),
+                Expr::Value(SqlValue::String(String::from("def"))),
+            ],
         };
         let parse_result = InsertStatement::parse_from_raw(
             "INSERT INTO foo (name, id, value) VALUES ('abc', 123, 'def')",
@@ -556,6 +1575,7 @@ mod test_insert_stmt {
 #[cfg(test)]
 mod test_select_stmt {
     use super::*;
+    use chrono::NaiveDate;
     #[test]
     fn test_select_stmt1() {
         let parse_result = SelectStatement::parse_from_raw(
@@ -567,14 +1587,333 @@ mod test_select_stmt {
         }
     }
 
+    #[test]
+    fn test_select_null() {
+        let select =
+            SelectStatement::parse_from_raw("SELECT * FROM foo WHERE a IS NULL AND b is not null")
+                .unwrap()
+                .1;
+        assert_eq!(
+            select.constraints,
+            Some(WhereConstraint::And(
+                Box::new(WhereConstraint::IsNull(String::from("a"))),
+                Box::new(WhereConstraint::Not(Box::new(WhereConstraint::IsNull(
+                    String::from("b")
+                ))))
+            ))
+        );
+        let select = SelectStatement::parse_from_raw("SELECT * FROM foo WHERE a = NULL")
+            .unwrap()
+            .1;
+        assert_eq!(
+            select.constraints,
+            Some(WhereConstraint::Constrait(
+                String::from("a"),
+                CmpOpt::Eq,
+                SqlValue::Null
+            ))
+        );
+        assert!(SelectStatement::parse_from_raw("SELECT * FROM foo WHERE a IS 1").is_err());
+    }
+
+    #[test]
+    fn test_order_by() {
+        let select = SelectStatement::parse_from_raw(
+            "SELECT * FROM foo WHERE a > 1 ORDER BY a, b DESC NULLS LAST, c + 1 asc, 2 nulls first",
+        )
+        .unwrap()
+        .1;
+        let column = |name: &str| Expr::Column(String::from(name));
+        assert_eq!(
+            select.order_by,
+            vec![
+                OrderItem {
+                    expr: column("a"),
+                    descending: false,
+                    nulls_first: None,
+                },
+                OrderItem {
+                    expr: column("b"),
+                    descending: true,
+                    nulls_first: Some(false),
+                },
+                OrderItem {
+                    expr: Expr::Add(
+                        Box::new(column("c")),
+                        Box::new(Expr::Value(SqlValue::Int(1)))
+                    ),
+                    descending: false,
+                    nulls_first: None,
+                },
+                OrderItem {
+                    expr: Expr::Value(SqlValue::Int(2)),
+                    descending: false,
+                    nulls_first: Some(true),
+                },
+            ]
+        );
+        assert!(select.constraints.is_some());
+        let select = SelectStatement::parse_from_raw("SELECT a FROM foo ORDER BY a")
+            .unwrap()
+            .1;
+        assert_eq!(select.order_by.len(), 1);
+        assert!(SelectStatement::parse_from_raw("SELECT a FROM foo ORDER BY").is_err());
+        assert!(SelectStatement::parse_from_raw("SELECT a FROM foo ORDER BY a NULLS").is_err());
+    }
+
+    #[test]
+    fn test_limit() {
+        let select = SelectStatement::parse_from_raw("SELECT a FROM foo LIMIT 10")
+            .unwrap()
+            .1;
+        assert_eq!(
+            select.limit,
+            Some(Limit {
+                count: 10,
+                offset: 0
+            })
+        );
+        let select = SelectStatement::parse_from_raw(
+            "SELECT a FROM foo WHERE a > 1 ORDER BY a desc limit 5 offset 20",
+        )
+        .unwrap()
+        .1;
+        assert_eq!(select.order_by.len(), 1);
+        assert_eq!(
+            select.limit,
+            Some(Limit {
+                count: 5,
+                offset: 20
+            })
+        );
+        assert!(SelectStatement::parse_from_raw("SELECT a FROM foo LIMIT").is_err());
+        assert!(SelectStatement::parse_from_raw("SELECT a FROM foo LIMIT -1").is_err());
+        assert!(SelectStatement::parse_from_raw("SELECT a FROM foo LIMIT 5 OFFSET").is_err());
+    }
+
+    #[test]
+    fn test_aggregates() {
+        let select = SelectStatement::parse_from_raw(
+            "SELECT count(*), COUNT(DISTINCT a), sum(a + 1) AS total, Avg(a), min(b), max(b) - 1, count FROM foo",
+        )
+        .unwrap()
+        .1;
+        let aggregate = |function, distinct, arg: Option<Expr>| {
+            Expr::Aggregate(Aggregate {
+                function,
+                distinct,
+                arg: arg.map(Box::new),
+            })
+        };
+        let column = |name: &str| Expr::Column(String::from(name));
+        assert_eq!(
+            select.columns,
+            vec![
+                SelectItem::Expr(aggregate(AggregateFunction::Count, false, None), None),
+                SelectItem::Expr(
+                    aggregate(AggregateFunction::Count, true, Some(column("a"))),
+                    None
+                ),
+                SelectItem::Expr(
+                    aggregate(
+                        AggregateFunction::Sum,
+                        false,
+                        Some(Expr::Add(
+                            Box::new(column("a")),
+                            Box::new(Expr::Value(SqlValue::Int(1)))
+                        ))
+                    ),
+                    Some(String::from("total"))
+                ),
+                SelectItem::Expr(
+                    aggregate(AggregateFunction::Avg, false, Some(column("a"))),
+                    None
+                ),
+                SelectItem::Expr(
+                    aggregate(AggregateFunction::Min, false, Some(column("b"))),
+                    None
+                ),
+                SelectItem::Expr(
+                    Expr::Sub(
+                        Box::new(aggregate(AggregateFunction::Max, false, Some(column("b")))),
+                        Box::new(Expr::Value(SqlValue::Int(1)))
+                    ),
+                    None
+                ),
+                SelectItem::Expr(column("count"), None),
+            ]
+        );
+        let names: Vec<String> = select
+            .columns
+            .iter()
+            .map(|item| match item {
+                SelectItem::Expr(expr, _) => expr.to_string(),
+                SelectItem::All => String::from("*"),
+            })
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "COUNT(*)",
+                "COUNT(DISTINCT a)",
+                "SUM(a + 1)",
+                "AVG(a)",
+                "MIN(b)",
+                "MAX(b) - 1",
+                "count"
+            ]
+        );
+        assert!(SelectStatement::parse_from_raw("SELECT sum(*) FROM foo").is_err());
+        assert!(SelectStatement::parse_from_raw("SELECT count(a FROM foo").is_err());
+        assert!(SelectStatement::parse_from_raw("SELECT count(DISTINCT *) FROM foo").is_err());
+    }
+
+    #[test]
+    fn test_group_by() {
+        let select = SelectStatement::parse_from_raw(
+            "SELECT region, kind, SUM(amount) FROM sales WHERE amount > 0 GROUP BY region , kind HAVING COUNT(*) > 1 AND region <> 'west' ORDER BY 3 DESC LIMIT 5",
+        )
+        .unwrap()
+        .1;
+        assert_eq!(
+            select.group_by,
+            vec![String::from("region"), String::from("kind")]
+        );
+        assert_eq!(
+            select.having,
+            Some(WhereConstraint::And(
+                Box::new(WhereConstraint::Compare(
+                    Expr::Aggregate(Aggregate {
+                        function: AggregateFunction::Count,
+                        distinct: false,
+                        arg: None,
+                    }),
+                    CmpOpt::Gt,
+                    Expr::Value(SqlValue::Int(1)),
+                )),
+                Box::new(WhereConstraint::Constrait(
+                    String::from("region"),
+                    CmpOpt::Ne,
+                    SqlValue::String(String::from("west")),
+                )),
+            ))
+        );
+        assert!(select.constraints.is_some());
+        assert_eq!(select.order_by.len(), 1);
+        assert!(select.limit.is_some());
+        let select =
+            SelectStatement::parse_from_raw("SELECT COUNT(*) FROM sales HAVING MAX(amount) > 10")
+                .unwrap()
+                .1;
+        assert!(select.group_by.is_empty());
+        assert!(select.having.is_some());
+        assert!(SelectStatement::parse_from_raw("SELECT a FROM foo GROUP BY").is_err());
+        assert!(SelectStatement::parse_from_raw("SELECT a FROM foo GROUP BY a HAVING").is_err());
+    }
+
+    #[test]
+    fn test_select_dates() {
+        let select = SelectStatement::parse_from_raw(
+            "SELECT id, EXTRACT(year FROM born) AS year, born + INTERVAL '1' DAY FROM foo \
+             WHERE born < DATE '2024-01-31' AND NOW() - INTERVAL 2 HOUR > seen",
+        )
+        .unwrap()
+        .1;
+        let born = || Box::new(Expr::Column(String::from("born")));
+        assert_eq!(
+            select.columns,
+            vec![
+                SelectItem::Expr(Expr::Column(String::from("id")), None),
+                SelectItem::Expr(
+                    Expr::Extract(DateField::Year, born()),
+                    Some(String::from("year"))
+                ),
+                SelectItem::Expr(
+                    Expr::Add(born(), Box::new(Expr::Interval(1, DateField::Day))),
+                    None
+                ),
+            ]
+        );
+        assert_eq!(
+            select.constraints,
+            Some(WhereConstraint::And(
+                Box::new(WhereConstraint::Constrait(
+                    String::from("born"),
+                    CmpOpt::Lt,
+                    SqlValue::Date(NaiveDate::from_ymd_opt(2024, 1, 31).unwrap()),
+                )),
+                Box::new(WhereConstraint::Compare(
+                    Expr::Sub(
+                        Box::new(Expr::Now),
+                        Box::new(Expr::Interval(2, DateField::Hour))
+                    ),
+                    CmpOpt::Gt,
+                    Expr::Column(String::from("seen")),
+                )),
+            ))
+        );
+        // a type name without a string is a column
+        let select = SelectStatement::parse_from_raw("SELECT date, time FROM foo")
+            .unwrap()
+            .1;
+        assert_eq!(
+            select.columns,
+            vec![
+                SelectItem::Expr(Expr::Column(String::from("date")), None),
+                SelectItem::Expr(Expr::Column(String::from("time")), None),
+            ]
+        );
+        assert!(SelectStatement::parse_from_raw("SELECT DATE '2024-02-30' FROM foo").is_err());
+        assert!(SelectStatement::parse_from_raw("SELECT a + INTERVAL '1' EPOCH FROM foo").is_err());
+    }
+
+    #[test]
+    fn test_select_arithmetic() {
+        let select = SelectStatement::parse_from_raw("SELECT a + b * 2, (a - b) / c / 2 FROM foo")
+            .unwrap()
+            .1;
+        let column = |name: &str| Box::new(Expr::Column(String::from(name)));
+        let two = || Box::new(Expr::Value(SqlValue::Int(2)));
+        // * and / bind tighter than + and -, and all of them are left associative
+        assert_eq!(
+            select.columns,
+            vec![
+                SelectItem::Expr(
+                    Expr::Add(column("a"), Box::new(Expr::Mul(column("b"), two()))),
+                    None
+                ),
+                SelectItem::Expr(
+                    Expr::Div(
+                        Box::new(Expr::Div(
+                            Box::new(Expr::Sub(column("a"), column("b"))),
+                            column("c")
+                        )),
+                        two()
+                    ),
+                    None
+                ),
+            ]
+        );
+        for text in [
+            "a + b * 2",
+            "(a - b) / c / 2",
+            "a / (b * c)",
+            "a * (b + c) - d",
+        ] {
+            let expr = Expr::parse_from_raw(text).unwrap().1;
+            assert_eq!(expr.to_string(), text);
+        }
+        assert!(SelectStatement::parse_from_raw("SELECT a * FROM foo").is_err());
+    }
+
     #[test]
     fn test_select_stmt2() {
         let expected = SelectStatement {
             table: String::from("foo"),
             columns: vec![
-                String::from("abc"),
-                String::from("value"),
-                String::from("*"),
+                SelectItem::Expr(Expr::Column(String::from("abc")), None),
+                SelectItem::Expr(Expr::Column(String::from("value")), None),
+                SelectItem::All,
             ],
             constraints: Some(WhereConstraint::And(
                 Box::new(WhereConstraint::Constrait(
@@ -588,6 +1927,10 @@ mod test_select_stmt {
                     SqlValue::String(String::from("def")),
                 )),
             )),
+            group_by: vec![],
+            having: None,
+            order_by: vec![],
+            limit: None,
         };
         let parse_result = SelectStatement::parse_from_raw(
             "SELECT abc, value, * from foo WHERE bar = 123 AND abc <= 'def'",
@@ -647,11 +1990,11 @@ mod test_update_stmt {
             sets: vec![
                 SetItem {
                     column: "abc".into(),
-                    value: SqlValue::Int(123),
+                    value: Expr::Value(SqlValue::Int(123)),
                 },
                 SetItem {
                     column: "def".into(),
-                    value: SqlValue::String("xyz".into()),
+                    value: Expr::Value(SqlValue::String("xyz".into())),
                 },
             ],
             constraints: Some(WhereConstraint::And(
@@ -712,14 +2055,110 @@ mod test_query {
         print_error(query);
     }
 
+    #[test]
+    fn test_transaction() {
+        assert_eq!(
+            SqlQuery::parse_from_raw("BEGIN;").unwrap().1,
+            SqlQuery::Begin
+        );
+        assert_eq!(
+            SqlQuery::parse_from_raw("begin transaction ;").unwrap().1,
+            SqlQuery::Begin
+        );
+        assert_eq!(
+            SqlQuery::parse_from_raw("Commit;").unwrap().1,
+            SqlQuery::Commit
+        );
+        assert_eq!(
+            SqlQuery::parse_from_raw("ROLLBACK;").unwrap().1,
+            SqlQuery::Rollback
+        );
+        assert!(SqlQuery::parse_from_raw("BEGIN foo;").is_err());
+    }
+
+    #[test]
+    fn test_index() {
+        assert_eq!(
+            SqlQuery::parse_from_raw("CREATE UNIQUE INDEX by_id ON foo (id);")
+                .unwrap()
+                .1,
+            SqlQuery::CreateIndex(CreateIndexStatement {
+                name: String::from("by_id"),
+                table: String::from("foo"),
+                columns: vec![String::from("id")],
+                unique: true,
+                kind: IndexKind::BTree,
+            })
+        );
+        assert_eq!(
+            SqlQuery::parse_from_raw("CREATE INDEX by_id ON foo USING HASH (id);")
+                .unwrap()
+                .1,
+            SqlQuery::CreateIndex(CreateIndexStatement {
+                name: String::from("by_id"),
+                table: String::from("foo"),
+                columns: vec![String::from("id")],
+                unique: false,
+                kind: IndexKind::Hash,
+            })
+        );
+        assert_eq!(
+            SqlQuery::parse_from_raw("create index by_name on foo(last, first);")
+                .unwrap()
+                .1,
+            SqlQuery::CreateIndex(CreateIndexStatement {
+                name: String::from("by_name"),
+                table: String::from("foo"),
+                columns: vec![String::from("last"), String::from("first")],
+                unique: false,
+                kind: IndexKind::BTree,
+            })
+        );
+        assert_eq!(
+            SqlQuery::parse_from_raw("DROP INDEX by_id;").unwrap().1,
+            SqlQuery::DropIndex(DropIndexStatement {
+                name: String::from("by_id")
+            })
+        );
+        assert!(SqlQuery::parse_from_raw("CREATE INDEX by_id ON foo;").is_err());
+        assert!(SqlQuery::parse_from_raw("CREATE INDEX by_id ON foo USING list (id);").is_err());
+    }
+
+    #[test]
+    fn test_savepoint() {
+        assert_eq!(
+            SqlQuery::parse_from_raw("SAVEPOINT chunk_1;").unwrap().1,
+            SqlQuery::Savepoint(String::from("chunk_1"))
+        );
+        assert_eq!(
+            SqlQuery::parse_from_raw("RELEASE SAVEPOINT a;").unwrap().1,
+            SqlQuery::Release(String::from("a"))
+        );
+        assert_eq!(
+            SqlQuery::parse_from_raw("release a;").unwrap().1,
+            SqlQuery::Release(String::from("a"))
+        );
+        assert_eq!(
+            SqlQuery::parse_from_raw("ROLLBACK TO SAVEPOINT a;")
+                .unwrap()
+                .1,
+            SqlQuery::RollbackTo(String::from("a"))
+        );
+        assert_eq!(
+            SqlQuery::parse_from_raw("rollback to a ;").unwrap().1,
+            SqlQuery::RollbackTo(String::from("a"))
+        );
+        assert!(SqlQuery::parse_from_raw("SAVEPOINT;").is_err());
+    }
+
     #[test]
     fn test_select() {
         let expected = SelectStatement {
             table: String::from("foo"),
             columns: vec![
-                String::from("abc"),
-                String::from("value"),
-                String::from("*"),
+                SelectItem::Expr(Expr::Column(String::from("abc")), None),
+                SelectItem::Expr(Expr::Column(String::from("value")), None),
+                SelectItem::All,
             ],
             constraints: Some(WhereConstraint::And(
                 Box::new(WhereConstraint::Constrait(
@@ -733,6 +2172,10 @@ mod test_query {
                     SqlValue::String(String::from("def")),
                 )),
             )),
+            group_by: vec![],
+            having: None,
+            order_by: vec![],
+            limit: None,
         };
         assert_eq!(
             SqlQuery::parse_from_raw(
@@ -1,7 +1,12 @@
 use super::errors::MyParseError;
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
 use nom::IResult;
 use nom_locate::LocatedSpan;
+use rust_decimal::{Decimal, RoundingStrategy};
 use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
 use tabled::Tabled;
 
 // Use nom_locate's LocatedSpan as a wrapper around a string input
@@ -12,28 +17,500 @@ pub type Span<'a> = LocatedSpan<&'a str>;
 pub type ParseResult<'a, T> = IResult<Span<'a>, T, MyParseError<'a>>;
 
 // many other imports omitted
+/// most digits a DECIMAL column may have
+pub const MAX_DECIMAL_PRECISION: u32 = 28;
+
 /// A colum's type
 #[derive(Debug, Clone, Eq, Hash, PartialEq, Serialize, Deserialize)]
 pub enum SqlType {
     // these are basic for now. Will add more + size max later on
     String,
+    /// string of at most n characters
+    Varchar(u32),
+    /// string of exactly n characters, padded with spaces
+    Char(u32),
+    /// bytes, also called BYTEA
+    Blob,
     Int,
+    /// 64-bit integer
+    BigInt,
+    /// 64-bit floating point, also called REAL
+    Double,
+    /// exact number with at most `precision` digits, `scale` of them after the point
+    Decimal(u32, u32),
+    Boolean,
+    Date,
+    /// time of day
+    Time,
+    /// date and time of day, without a time zone
+    Timestamp,
     Unknown,
 }
 
+/// Constraints on the values of a column
+#[derive(Debug, Clone, Default, Eq, Hash, PartialEq, Serialize, Deserialize)]
+pub struct ColumnConstraints {
+    /// the column is part of the primary key of the table
+    pub primary_key: bool,
+    #[serde(default)]
+    pub not_null: bool,
+    /// no two rows have the same value, except for missing values
+    #[serde(default)]
+    pub unique: bool,
+    /// value of the column when an insert leaves it out
+    #[serde(default)]
+    pub default: Option<Expr>,
+    /// every row has to meet the predicate
+    #[serde(default)]
+    pub check: Option<WhereConstraint>,
+    /// the values have to be found in a column of another table
+    #[serde(default)]
+    pub references: Option<ForeignKey>,
+}
+
+/// What a delete does to the rows referencing the deleted rows
+#[derive(Debug, Clone, Copy, Default, Eq, Hash, PartialEq, Serialize, Deserialize)]
+pub enum OnDelete {
+    /// the delete fails
+    #[default]
+    Restrict,
+    /// the referencing rows are deleted too
+    Cascade,
+    /// the referencing columns lose their value
+    SetNull,
+}
+
+/// The column of another table a column refers to,
+/// it is the primary key or a unique column of that table
+#[derive(Debug, Clone, Eq, Hash, PartialEq, Serialize, Deserialize)]
+pub struct ForeignKey {
+    pub table: String,
+    pub column: String,
+    #[serde(default)]
+    pub on_delete: OnDelete,
+}
+
+impl fmt::Display for ForeignKey {
+    /// the reference as it is written after REFERENCES
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}({})", self.table, self.column)?;
+        match self.on_delete {
+            OnDelete::Restrict => Ok(()),
+            OnDelete::Cascade => write!(f, " ON DELETE CASCADE"),
+            OnDelete::SetNull => write!(f, " ON DELETE SET NULL"),
+        }
+    }
+}
+
+impl fmt::Display for ColumnConstraints {
+    /// the constraints as they are written after the type of the column
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.primary_key {
+            write!(f, " PRIMARY KEY")?;
+        }
+        if self.not_null {
+            write!(f, " NOT NULL")?;
+        }
+        if self.unique {
+            write!(f, " UNIQUE")?;
+        }
+        if let Some(default) = &self.default {
+            write!(f, " DEFAULT {default}")?;
+        }
+        if let Some(check) = &self.check {
+            write!(f, " CHECK ({check})")?;
+        }
+        if let Some(references) = &self.references {
+            write!(f, " REFERENCES {references}")?;
+        }
+        Ok(())
+    }
+}
+
 /// A column's name + type
 #[derive(Debug, Clone, Eq, Hash, PartialEq, Serialize, Deserialize)]
 pub struct Column {
     pub name: String,
     pub type_info: SqlType,
+    #[serde(default)]
+    pub constraints: ColumnConstraints,
 }
 
 /// Values appears in SQL statement, like insert, update..
-#[derive(Clone, Debug, Eq, Hash, PartialEq, Serialize, Deserialize, Tabled)]
+#[derive(Clone, Debug, Serialize, Deserialize, Tabled)]
 pub enum SqlValue {
     String(String),
+    Blob(Vec<u8>),
     Int(i32),
-    Unknown,
+    BigInt(i64),
+    Double(f64),
+    Decimal(Decimal),
+    Boolean(bool),
+    Date(NaiveDate),
+    Time(NaiveTime),
+    Timestamp(NaiveDateTime),
+    /// no value, it is neither equal nor unequal to any value
+    #[serde(alias = "Unknown")]
+    Null,
+}
+
+/// values are equal when they have the same type and the same bits,
+/// so doubles can be kept in sets and maps
+impl PartialEq for SqlValue {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (SqlValue::String(a), SqlValue::String(b)) => a == b,
+            (SqlValue::Blob(a), SqlValue::Blob(b)) => a == b,
+            (SqlValue::Int(a), SqlValue::Int(b)) => a == b,
+            (SqlValue::BigInt(a), SqlValue::BigInt(b)) => a == b,
+            (SqlValue::Double(a), SqlValue::Double(b)) => a.to_bits() == b.to_bits(),
+            // 1.5 and 1.50 are equal
+            (SqlValue::Decimal(a), SqlValue::Decimal(b)) => a == b,
+            (SqlValue::Boolean(a), SqlValue::Boolean(b)) => a == b,
+            (SqlValue::Date(a), SqlValue::Date(b)) => a == b,
+            (SqlValue::Time(a), SqlValue::Time(b)) => a == b,
+            (SqlValue::Timestamp(a), SqlValue::Timestamp(b)) => a == b,
+            (SqlValue::Null, SqlValue::Null) => true,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for SqlValue {}
+
+impl Hash for SqlValue {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            SqlValue::String(s) => s.hash(state),
+            SqlValue::Blob(b) => b.hash(state),
+            SqlValue::Int(i) => i.hash(state),
+            SqlValue::BigInt(i) => i.hash(state),
+            SqlValue::Double(d) => d.to_bits().hash(state),
+            SqlValue::Decimal(d) => d.hash(state),
+            SqlValue::Boolean(b) => b.hash(state),
+            SqlValue::Date(d) => d.hash(state),
+            SqlValue::Time(t) => t.hash(state),
+            SqlValue::Timestamp(t) => t.hash(state),
+            SqlValue::Null => (),
+        }
+    }
+}
+
+/// the bytes as pairs of lowercase hex digits
+pub fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// the bytes written by `encode_hex`, in either case
+pub fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) || !text.is_ascii() {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|at| u8::from_str_radix(&text[at..at + 2], 16).ok())
+        .collect()
+}
+
+/// the text if it has at most `length` characters, trailing spaces past
+/// `length` are cut as in standard SQL. None if it is longer
+fn fit_length(text: &str, length: u32) -> Option<&str> {
+    match text.char_indices().nth(length as usize) {
+        None => Some(text),
+        Some((end, _)) if text[end..].bytes().all(|byte| byte == b' ') => Some(&text[..end]),
+        _ => None,
+    }
+}
+
+impl SqlValue {
+    /// the value of an integer type
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            SqlValue::Int(i) => Some(*i as i64),
+            SqlValue::BigInt(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    /// the value of a numeric type
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            SqlValue::Double(d) => Some(*d),
+            // the nearest double to the decimal
+            SqlValue::Decimal(d) => d.to_string().parse().ok(),
+            _ => self.as_i64().map(|i| i as f64),
+        }
+    }
+
+    /// the value of an integer or a decimal
+    pub fn as_decimal(&self) -> Option<Decimal> {
+        match self {
+            SqlValue::Decimal(d) => Some(*d),
+            _ => self.as_i64().map(Decimal::from),
+        }
+    }
+
+    /// the value of a date or a timestamp, a date is at midnight
+    pub fn as_timestamp(&self) -> Option<NaiveDateTime> {
+        match self {
+            SqlValue::Date(d) => d.and_hms_opt(0, 0, 0),
+            SqlValue::Timestamp(t) => Some(*t),
+            _ => None,
+        }
+    }
+
+    /// order of two values of types which can be compared, None otherwise.
+    /// Numbers of any numeric type are compared by their value, and so are dates with timestamps
+    pub fn compare(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (SqlValue::String(a), SqlValue::String(b)) => Some(a.cmp(b)),
+            (SqlValue::Blob(a), SqlValue::Blob(b)) => Some(a.cmp(b)),
+            (SqlValue::Boolean(a), SqlValue::Boolean(b)) => Some(a.cmp(b)),
+            (SqlValue::Time(a), SqlValue::Time(b)) => Some(a.cmp(b)),
+            _ => {
+                if let (Some(a), Some(b)) = (self.as_timestamp(), other.as_timestamp()) {
+                    return Some(a.cmp(&b));
+                }
+                if let (Some(a), Some(b)) = (self.as_i64(), other.as_i64()) {
+                    return Some(a.cmp(&b));
+                }
+                // decimals are compared exactly with integers and decimals
+                match (self.as_decimal(), other.as_decimal()) {
+                    (Some(a), Some(b)) => Some(a.cmp(&b)),
+                    _ => Some(self.as_f64()?.total_cmp(&other.as_f64()?)),
+                }
+            }
+        }
+    }
+
+    /// read a value of the type from its text, like a field of a csv file,
+    /// dates and times are in ISO-8601: 2024-01-31, 10:30:00 and 2024-01-31 10:30:00
+    pub fn from_text(text: &str, type_info: &SqlType) -> Option<SqlValue> {
+        match type_info {
+            SqlType::String | SqlType::Varchar(_) | SqlType::Char(_) => {
+                Some(SqlValue::String(text.to_string()))
+            }
+            SqlType::Blob => decode_hex(text).map(SqlValue::Blob),
+            SqlType::Int => text.parse().ok().map(SqlValue::Int),
+            SqlType::BigInt => text.parse().ok().map(SqlValue::BigInt),
+            SqlType::Double => text.parse().ok().map(SqlValue::Double),
+            SqlType::Decimal(_, _) => {
+                SqlValue::Decimal(Decimal::from_str_exact(text).ok()?).coerce(type_info)
+            }
+            SqlType::Boolean => text.parse().ok().map(SqlValue::Boolean),
+            SqlType::Date => NaiveDate::parse_from_str(text, "%Y-%m-%d")
+                .ok()
+                .map(SqlValue::Date),
+            SqlType::Time => NaiveTime::parse_from_str(text, "%H:%M:%S%.f")
+                .or_else(|_| NaiveTime::parse_from_str(text, "%H:%M"))
+                .ok()
+                .map(SqlValue::Time),
+            SqlType::Timestamp => NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S%.f")
+                .or_else(|_| NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M:%S%.f"))
+                .ok()
+                .or_else(|| SqlValue::from_text(text, &SqlType::Date)?.as_timestamp())
+                .map(SqlValue::Timestamp),
+            SqlType::Unknown => None,
+        }
+    }
+
+    /// the value converted to the type of a column, None if the type cannot hold it.
+    /// NULL fits any type and a column of unknown type takes any value
+    pub fn coerce(&self, type_info: &SqlType) -> Option<SqlValue> {
+        match (self, type_info) {
+            (SqlValue::Null, _) | (_, SqlType::Unknown) => Some(self.clone()),
+            (SqlValue::String(_), SqlType::String)
+            | (SqlValue::Blob(_), SqlType::Blob)
+            | (SqlValue::Boolean(_), SqlType::Boolean)
+            | (SqlValue::Date(_), SqlType::Date)
+            | (SqlValue::Time(_), SqlType::Time)
+            | (SqlValue::Timestamp(_), SqlType::Timestamp) => Some(self.clone()),
+            (SqlValue::String(text), SqlType::Varchar(length)) => {
+                fit_length(text, *length).map(|text| SqlValue::String(text.to_string()))
+            }
+            (SqlValue::String(text), SqlType::Char(length)) => {
+                let text = fit_length(text, *length)?;
+                Some(SqlValue::String(format!(
+                    "{text:<width$}",
+                    width = *length as usize
+                )))
+            }
+            // dates and times may be written as strings
+            (SqlValue::String(text), SqlType::Date | SqlType::Time | SqlType::Timestamp) => {
+                SqlValue::from_text(text, type_info)
+            }
+            (SqlValue::Date(_), SqlType::Timestamp) => self.as_timestamp().map(SqlValue::Timestamp),
+            (_, SqlType::Int) => i32::try_from(self.as_i64()?).ok().map(SqlValue::Int),
+            (_, SqlType::BigInt) => self.as_i64().map(SqlValue::BigInt),
+            (_, SqlType::Double) => self.as_f64().map(SqlValue::Double),
+            (_, SqlType::Decimal(precision, scale)) => {
+                let value = match self {
+                    // the shortest text of the double, so no digits are made up
+                    SqlValue::Double(d) => Decimal::from_str_exact(&d.to_string()).ok()?,
+                    _ => self.as_decimal()?,
+                };
+                let mut value =
+                    value.round_dp_with_strategy(*scale, RoundingStrategy::MidpointAwayFromZero);
+                let limit = Decimal::from(10i128.checked_pow(precision.checked_sub(*scale)?)?);
+                if value.abs() >= limit {
+                    return None;
+                }
+                value.rescale(*scale);
+                Some(SqlValue::Decimal(value))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for SqlValue {
+    /// the value as a SQL literal
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SqlValue::String(s) => write!(f, "'{s}'"),
+            SqlValue::Blob(b) => write!(f, "X'{}'", encode_hex(b)),
+            SqlValue::Int(i) => write!(f, "{i}"),
+            SqlValue::BigInt(i) => write!(f, "{i}"),
+            // debug keeps the point of whole numbers, so the literal is read back as a double
+            SqlValue::Double(d) => write!(f, "{d:?}"),
+            SqlValue::Decimal(d) => write!(f, "{d}"),
+            SqlValue::Boolean(true) => write!(f, "TRUE"),
+            SqlValue::Boolean(false) => write!(f, "FALSE"),
+            SqlValue::Date(d) => write!(f, "DATE '{d}'"),
+            SqlValue::Time(t) => write!(f, "TIME '{t}'"),
+            SqlValue::Timestamp(t) => write!(f, "TIMESTAMP '{t}'"),
+            SqlValue::Null => write!(f, "NULL"),
+        }
+    }
+}
+
+/// A part of a date or a time, in EXTRACT and INTERVAL
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
+pub enum DateField {
+    Year,
+    Month,
+    Day,
+    Hour,
+    Minute,
+    Second,
+    /// seconds since 1970-01-01 00:00:00, only in EXTRACT
+    Epoch,
+}
+
+impl fmt::Display for DateField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let field = match self {
+            DateField::Year => "YEAR",
+            DateField::Month => "MONTH",
+            DateField::Day => "DAY",
+            DateField::Hour => "HOUR",
+            DateField::Minute => "MINUTE",
+            DateField::Second => "SECOND",
+            DateField::Epoch => "EPOCH",
+        };
+        write!(f, "{field}")
+    }
+}
+
+/// A function computed over the rows of a SELECT
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
+pub enum AggregateFunction {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+impl fmt::Display for AggregateFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let function = match self {
+            AggregateFunction::Count => "COUNT",
+            AggregateFunction::Sum => "SUM",
+            AggregateFunction::Avg => "AVG",
+            AggregateFunction::Min => "MIN",
+            AggregateFunction::Max => "MAX",
+        };
+        write!(f, "{function}")
+    }
+}
+
+/// An aggregate call like COUNT(*) or SUM(DISTINCT price)
+#[derive(Clone, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
+pub struct Aggregate {
+    pub function: AggregateFunction,
+    /// only the distinct values of the argument are aggregated
+    pub distinct: bool,
+    /// None for COUNT(*), which counts the rows
+    pub arg: Option<Box<Expr>>,
+}
+
+impl fmt::Display for Aggregate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let distinct = if self.distinct { "DISTINCT " } else { "" };
+        match &self.arg {
+            Some(arg) => write!(f, "{}({distinct}{arg})", self.function),
+            None => write!(f, "{}(*)", self.function),
+        }
+    }
+}
+
+/// An expression computed when the statement is executed
+#[derive(Clone, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
+pub enum Expr {
+    Value(SqlValue),
+    Column(String),
+    /// NOW() or CURRENT_TIMESTAMP, the time the statement is executed at
+    Now,
+    CurrentDate,
+    Add(Box<Expr>, Box<Expr>),
+    Sub(Box<Expr>, Box<Expr>),
+    Mul(Box<Expr>, Box<Expr>),
+    Div(Box<Expr>, Box<Expr>),
+    /// INTERVAL 'n' field, only as an operand of + and -
+    Interval(i64, DateField),
+    /// EXTRACT(field FROM expr)
+    Extract(DateField, Box<Expr>),
+    /// an aggregate call, only in the result columns, HAVING and ORDER BY of SELECT
+    Aggregate(Aggregate),
+}
+
+impl fmt::Display for Expr {
+    /// the expression as it is written in a statement
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // the operators are left associative and * and / bind tighter than + and -,
+        // so a right operand or an operand of a tighter operator may need parentheses
+        let sum = |expr: &Expr| matches!(expr, Expr::Add(_, _) | Expr::Sub(_, _));
+        let term = |expr: &Expr| sum(expr) || matches!(expr, Expr::Mul(_, _) | Expr::Div(_, _));
+        let operand = |expr: &Expr, wrapped: bool| match wrapped {
+            true => format!("({expr})"),
+            false => expr.to_string(),
+        };
+        match self {
+            Expr::Value(value) => write!(f, "{value}"),
+            Expr::Column(column) => write!(f, "{column}"),
+            Expr::Now => write!(f, "NOW()"),
+            Expr::CurrentDate => write!(f, "CURRENT_DATE"),
+            Expr::Add(left, right) => write!(f, "{left} + {}", operand(right, sum(right))),
+            Expr::Sub(left, right) => write!(f, "{left} - {}", operand(right, sum(right))),
+            Expr::Mul(left, right) => write!(
+                f,
+                "{} * {}",
+                operand(left, sum(left)),
+                operand(right, term(right))
+            ),
+            Expr::Div(left, right) => write!(
+                f,
+                "{} / {}",
+                operand(left, sum(left)),
+                operand(right, term(right))
+            ),
+            Expr::Interval(amount, field) => write!(f, "INTERVAL '{amount}' {field}"),
+            Expr::Extract(field, expr) => write!(f, "EXTRACT({field} FROM {expr})"),
+            Expr::Aggregate(aggregate) => write!(f, "{aggregate}"),
+        }
+    }
 }
 
 /// Vector of SQL Value, used in insert
@@ -60,12 +537,44 @@ pub enum WhereConstraint {
     Not(Box<WhereConstraint>),
     // column, cmp, value
     Constrait(String, CmpOpt, SqlValue),
+    // column IS NULL, IS NOT NULL is its negation
+    IsNull(String),
+    // comparison of expressions which are not a column and a value
+    Compare(Expr, CmpOpt, Expr),
+}
+
+impl fmt::Display for CmpOpt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self {
+            CmpOpt::Eq => "=",
+            CmpOpt::Ne => "<>",
+            CmpOpt::Lt => "<",
+            CmpOpt::Le => "<=",
+            CmpOpt::Gt => ">",
+            CmpOpt::Ge => ">=",
+        };
+        write!(f, "{op}")
+    }
+}
+
+impl fmt::Display for WhereConstraint {
+    /// the constraints as they are written after WHERE
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WhereConstraint::And(left, right) => write!(f, "{left} AND {right}"),
+            WhereConstraint::Or(left, right) => write!(f, "{left} OR {right}"),
+            WhereConstraint::Not(wc) => write!(f, "NOT {wc}"),
+            WhereConstraint::Constrait(column, op, value) => write!(f, "{column} {op} {value}"),
+            WhereConstraint::IsNull(column) => write!(f, "{column} IS NULL"),
+            WhereConstraint::Compare(left, op, right) => write!(f, "{left} {op} {right}"),
+        }
+    }
 }
 
 #[derive(Clone, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
 pub struct SetItem {
     pub column: String,
-    pub value: SqlValue,
+    pub value: Expr,
 }
 
 /// The table and its columns to create
@@ -73,6 +582,10 @@ pub struct SetItem {
 pub struct CreateStatement {
     pub table: String,
     pub columns: Vec<Column>,
+    /// columns of a table-level PRIMARY KEY clause
+    pub primary_key: Vec<String>,
+    /// columns of table-level FOREIGN KEY clauses with the column they refer to
+    pub foreign_keys: Vec<(String, ForeignKey)>,
 }
 
 #[derive(Clone, Debug, Default, Eq, Hash, PartialEq, Serialize, Deserialize)]
@@ -85,14 +598,52 @@ pub struct DropStatement {
 pub struct InsertStatement {
     pub table: String,
     pub columns: Option<Vec<String>>,
-    pub values: RowValue,
+    pub values: Vec<Expr>,
+}
+
+/// An item of the SELECT list
+#[derive(Clone, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
+pub enum SelectItem {
+    /// * for all the columns
+    All,
+    /// an expression with its alias
+    Expr(Expr, Option<String>),
+}
+
+/// A key of ORDER BY
+#[derive(Clone, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
+pub struct OrderItem {
+    /// a column or an expression of the table, a result column by its name or its position
+    pub expr: Expr,
+    pub descending: bool,
+    /// NULLS FIRST or NULLS LAST, NULL is larger than any value by default
+    pub nulls_first: Option<bool>,
+}
+
+/// LIMIT n [OFFSET m]
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, Serialize, Deserialize)]
+pub struct Limit {
+    /// at most this many rows are returned
+    pub count: u64,
+    /// number of rows skipped before the first row returned
+    pub offset: u64,
 }
 
 #[derive(Clone, Debug, Default, Eq, Hash, PartialEq, Serialize, Deserialize)]
 pub struct SelectStatement {
     pub table: String,
-    pub columns: Vec<String>,
+    pub columns: Vec<SelectItem>,
     pub constraints: Option<WhereConstraint>,
+    /// the columns the rows are grouped by
+    #[serde(default)]
+    pub group_by: Vec<String>,
+    /// the condition a group must meet, it could compare aggregates
+    #[serde(default)]
+    pub having: Option<WhereConstraint>,
+    #[serde(default)]
+    pub order_by: Vec<OrderItem>,
+    #[serde(default)]
+    pub limit: Option<Limit>,
 }
 
 #[derive(Clone, Debug, Default, Eq, Hash, PartialEq, Serialize, Deserialize)]
@@ -108,6 +659,31 @@ pub struct UpdateStatement {
     pub constraints: Option<WhereConstraint>,
 }
 
+/// How an index keeps the keys of the rows
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, Serialize, Deserialize)]
+pub enum IndexKind {
+    /// ordered keys, for equality and range lookups
+    #[default]
+    BTree,
+    /// hashed keys, for equality lookups only
+    Hash,
+}
+
+/// CREATE [UNIQUE] INDEX name ON table [USING BTREE | HASH] (column, ...)
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq, Serialize, Deserialize)]
+pub struct CreateIndexStatement {
+    pub name: String,
+    pub table: String,
+    pub columns: Vec<String>,
+    pub unique: bool,
+    pub kind: IndexKind,
+}
+
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq, Serialize, Deserialize)]
+pub struct DropIndexStatement {
+    pub name: String,
+}
+
 #[derive(Clone, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
 pub enum SqlQuery {
     Select(SelectStatement),
@@ -116,4 +692,16 @@ pub enum SqlQuery {
     Delete(DeleteStatement),
     Drop(DropStatement),
     Update(UpdateStatement),
+    CreateIndex(CreateIndexStatement),
+    DropIndex(DropIndexStatement),
+    /// BEGIN [TRANSACTION]
+    Begin,
+    Commit,
+    Rollback,
+    /// SAVEPOINT name
+    Savepoint(String),
+    /// RELEASE [SAVEPOINT] name
+    Release(String),
+    /// ROLLBACK TO [SAVEPOINT] name
+    RollbackTo(String),
 }
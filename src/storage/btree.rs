@@ -0,0 +1,492 @@
+use super::super::sql_analyzer::types::{RowValue, SqlValue};
+use super::buffer_pool::BufferPool;
+use super::page::{Page, PAGE_SIZE};
+use super::record::{decode_row, encode_row};
+use super::RowId;
+use std::cmp::Ordering;
+use std::io;
+use std::ops::Bound;
+
+// Page 0 of a tree keeps a single record: the root page (u32) and the number of pages (u32).
+// Every other page is a node, slot 0 keeps the node header: a kind byte and a link (u32),
+// the next leaf for a leaf node or the leftmost child for an inner node.
+// The following slots keep the entries in order, an entry is the row id (u64),
+// the child page (u32) for an inner node, and the encoded key.
+// Entries are ordered by key and then row id, so equal keys of several rows are allowed.
+// Deleted entries are removed from their leaf but nodes are never merged.
+
+const META_PAGE: u32 = 0;
+const LEAF: u8 = 0;
+const INNER: u8 = 1;
+/// no next leaf, page 0 is never a node
+const NO_PAGE: u32 = 0;
+/// Largest encoded key, so a split node always fits in its page
+pub const MAX_KEY_SIZE: usize = PAGE_SIZE / 4;
+
+fn broken_tree() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "Index is broken")
+}
+
+/// Pages of a tree, kept in a file or in memory
+pub trait PageStore {
+    fn page(&mut self, page_no: u32) -> Result<&Page, io::Error>;
+
+    fn page_mut(&mut self, page_no: u32) -> Result<&mut Page, io::Error>;
+
+    /// add the empty page `page_no` at the end
+    fn new_page(&mut self, page_no: u32) -> Result<&mut Page, io::Error>;
+}
+
+/// Pages of the file at `path`, cached by the buffer pool
+pub struct FilePages<'a> {
+    pub pool: &'a mut BufferPool,
+    pub path: &'a str,
+}
+
+impl PageStore for FilePages<'_> {
+    fn page(&mut self, page_no: u32) -> Result<&Page, io::Error> {
+        self.pool.page(self.path, page_no)
+    }
+
+    fn page_mut(&mut self, page_no: u32) -> Result<&mut Page, io::Error> {
+        self.pool.page_mut(self.path, page_no)
+    }
+
+    fn new_page(&mut self, page_no: u32) -> Result<&mut Page, io::Error> {
+        self.pool.new_page(self.path, page_no)
+    }
+}
+
+impl PageStore for Vec<Page> {
+    fn page(&mut self, page_no: u32) -> Result<&Page, io::Error> {
+        self.get(page_no as usize).ok_or_else(broken_tree)
+    }
+
+    fn page_mut(&mut self, page_no: u32) -> Result<&mut Page, io::Error> {
+        self.get_mut(page_no as usize).ok_or_else(broken_tree)
+    }
+
+    fn new_page(&mut self, page_no: u32) -> Result<&mut Page, io::Error> {
+        if page_no as usize != self.len() {
+            return Err(broken_tree());
+        }
+        self.push(Page::new());
+        Ok(self.last_mut().unwrap())
+    }
+}
+
+/// order of values in an index, values of different types are ordered by their type
+pub fn compare_values(a: &SqlValue, b: &SqlValue) -> Ordering {
+    fn rank(value: &SqlValue) -> u8 {
+        match value {
+            SqlValue::Null => 0,
+            SqlValue::Boolean(_) => 1,
+            SqlValue::Int(_) | SqlValue::BigInt(_) | SqlValue::Double(_) | SqlValue::Decimal(_) => {
+                2
+            }
+            SqlValue::String(_) => 3,
+            SqlValue::Date(_) | SqlValue::Timestamp(_) => 4,
+            SqlValue::Time(_) => 5,
+            SqlValue::Blob(_) => 6,
+        }
+    }
+    // numbers of different types are ordered by their value, and so are dates with timestamps
+    a.compare(b).unwrap_or_else(|| rank(a).cmp(&rank(b)))
+}
+
+pub fn compare_keys(a: &[SqlValue], b: &[SqlValue]) -> Ordering {
+    a.iter()
+        .zip(b)
+        .map(|(a, b)| compare_values(a, b))
+        .find(|order| order.is_ne())
+        .unwrap_or_else(|| a.len().cmp(&b.len()))
+}
+
+#[derive(Clone, Debug)]
+struct Entry {
+    key: Vec<SqlValue>,
+    id: RowId,
+    /// child page of an inner node, holding the entries from this one to the next one
+    child: u32,
+}
+
+impl Entry {
+    fn cmp(&self, key: &[SqlValue], id: RowId) -> Ordering {
+        compare_keys(&self.key, key).then(self.id.cmp(&id))
+    }
+
+    /// the first key column compared to `value`
+    fn cmp_first(&self, value: &SqlValue) -> Ordering {
+        match self.key.first() {
+            Some(first) => compare_values(first, value),
+            None => Ordering::Less,
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = self.id.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&self.child.to_le_bytes());
+        bytes.extend(encode_row(&RowValue {
+            values: self.key.clone(),
+        }));
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, io::Error> {
+        if bytes.len() < 12 {
+            return Err(broken_tree());
+        }
+        let mut id = [0; 8];
+        id.copy_from_slice(&bytes[..8]);
+        let mut child = [0; 4];
+        child.copy_from_slice(&bytes[8..12]);
+        Ok(Self {
+            key: decode_row(&bytes[12..])?.values,
+            id: RowId::from_le_bytes(id),
+            child: u32::from_le_bytes(child),
+        })
+    }
+}
+
+struct Node {
+    leaf: bool,
+    link: u32,
+    entries: Vec<Entry>,
+}
+
+impl Node {
+    fn read(page: &Page) -> Result<Self, io::Error> {
+        let header = page.get(0).ok_or_else(broken_tree)?;
+        if header.len() != 5 {
+            return Err(broken_tree());
+        }
+        let entries = page
+            .records()
+            .skip(1)
+            .map(|(_, record)| Entry::decode(record))
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            leaf: header[0] == LEAF,
+            link: u32::from_le_bytes([header[1], header[2], header[3], header[4]]),
+            entries,
+        })
+    }
+
+    /// the node as a page, None if it does not fit in one
+    fn to_page(&self) -> Option<Page> {
+        let mut page = Page::new();
+        let mut header = vec![if self.leaf { LEAF } else { INNER }];
+        header.extend_from_slice(&self.link.to_le_bytes());
+        page.insert(&header)?;
+        for entry in &self.entries {
+            page.insert(&entry.encode())?;
+        }
+        Some(page)
+    }
+
+    /// child page which could hold the entry
+    fn child(&self, key: &[SqlValue], id: RowId) -> u32 {
+        match self
+            .entries
+            .partition_point(|entry| entry.cmp(key, id).is_le())
+        {
+            0 => self.link,
+            index => self.entries[index - 1].child,
+        }
+    }
+
+    /// leftmost child page which could hold entries whose first key column is `value`
+    fn first_child(&self, value: &SqlValue) -> u32 {
+        match self
+            .entries
+            .partition_point(|entry| entry.cmp_first(value).is_lt())
+        {
+            0 => self.link,
+            index => self.entries[index - 1].child,
+        }
+    }
+}
+
+/// A B+tree from keys to row ids in a page store
+pub struct BTree<'a> {
+    store: &'a mut dyn PageStore,
+}
+
+impl<'a> BTree<'a> {
+    pub fn new(store: &'a mut dyn PageStore) -> Self {
+        Self { store }
+    }
+
+    /// pages of an empty tree: the meta page and an empty root leaf
+    pub fn empty() -> Vec<Page> {
+        let mut meta = Page::new();
+        let mut record = 1u32.to_le_bytes().to_vec();
+        record.extend_from_slice(&2u32.to_le_bytes());
+        meta.insert(&record);
+        let root = Node {
+            leaf: true,
+            link: NO_PAGE,
+            entries: Vec::new(),
+        };
+        vec![meta, root.to_page().unwrap()]
+    }
+
+    /// root page and number of pages
+    fn meta(&mut self) -> Result<(u32, u32), io::Error> {
+        let record = self.store.page(META_PAGE)?.get(0).ok_or_else(broken_tree)?;
+        if record.len() != 8 {
+            return Err(broken_tree());
+        }
+        Ok((
+            u32::from_le_bytes([record[0], record[1], record[2], record[3]]),
+            u32::from_le_bytes([record[4], record[5], record[6], record[7]]),
+        ))
+    }
+
+    fn set_meta(&mut self, root: u32, page_count: u32) -> Result<(), io::Error> {
+        let mut record = root.to_le_bytes().to_vec();
+        record.extend_from_slice(&page_count.to_le_bytes());
+        if !self.store.page_mut(META_PAGE)?.update(0, &record) {
+            return Err(broken_tree());
+        }
+        Ok(())
+    }
+
+    fn node(&mut self, page_no: u32) -> Result<Node, io::Error> {
+        Node::read(self.store.page(page_no)?)
+    }
+
+    /// write the node, false if it does not fit in its page
+    fn write(&mut self, page_no: u32, node: &Node) -> Result<bool, io::Error> {
+        match node.to_page() {
+            Some(page) => {
+                *self.store.page_mut(page_no)? = page;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn allocate(&mut self, node: &Node) -> Result<u32, io::Error> {
+        let (root, page_count) = self.meta()?;
+        self.set_meta(root, page_count + 1)?;
+        *self.store.new_page(page_count)? = node.to_page().ok_or_else(broken_tree)?;
+        Ok(page_count)
+    }
+
+    /// add the key of a row
+    pub fn insert(&mut self, key: Vec<SqlValue>, id: RowId) -> Result<(), io::Error> {
+        let entry = Entry {
+            key,
+            id,
+            child: NO_PAGE,
+        };
+        if entry.encode().len() > MAX_KEY_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Key is too large for an index",
+            ));
+        }
+        let (root, _) = self.meta()?;
+        if let Some(separator) = self.insert_into(root, entry)? {
+            // the root was split, the tree grows by one level
+            let new_root = Node {
+                leaf: false,
+                link: root,
+                entries: vec![separator],
+            };
+            let new_root = self.allocate(&new_root)?;
+            let (_, page_count) = self.meta()?;
+            self.set_meta(new_root, page_count)?;
+        }
+        Ok(())
+    }
+
+    /// insert into the subtree at `page_no`, return the entry pointing to the new node if it was split
+    fn insert_into(&mut self, page_no: u32, entry: Entry) -> Result<Option<Entry>, io::Error> {
+        let mut node = self.node(page_no)?;
+        let entry = if node.leaf {
+            entry
+        } else {
+            match self.insert_into(node.child(&entry.key, entry.id), entry)? {
+                Some(separator) => separator,
+                None => return Ok(None),
+            }
+        };
+        let index = node
+            .entries
+            .partition_point(|other| other.cmp(&entry.key, entry.id).is_lt());
+        node.entries.insert(index, entry);
+        if self.write(page_no, &node)? {
+            return Ok(None);
+        }
+        // split the entries in two halves of about the same size
+        let sizes: Vec<usize> = node.entries.iter().map(|e| e.encode().len()).collect();
+        let total: usize = sizes.iter().sum();
+        let mut half = 0;
+        let mut middle = 0;
+        while middle < sizes.len() - 1 && half + sizes[middle] <= total / 2 {
+            half += sizes[middle];
+            middle += 1;
+        }
+        let mut right_entries = node.entries.split_off(middle.max(1));
+        let (right, separator) = if node.leaf {
+            let first = &right_entries[0];
+            let separator = Entry {
+                key: first.key.clone(),
+                id: first.id,
+                child: NO_PAGE,
+            };
+            let right = Node {
+                leaf: true,
+                link: node.link,
+                entries: right_entries,
+            };
+            (right, separator)
+        } else {
+            // the first entry moves up, its child becomes the leftmost child of the new node
+            let separator = right_entries.remove(0);
+            let right = Node {
+                leaf: false,
+                link: separator.child,
+                entries: right_entries,
+            };
+            (right, separator)
+        };
+        let right_page = self.allocate(&right)?;
+        if node.leaf {
+            node.link = right_page;
+        }
+        if !self.write(page_no, &node)? {
+            return Err(broken_tree());
+        }
+        Ok(Some(Entry {
+            child: right_page,
+            ..separator
+        }))
+    }
+
+    /// remove the key of a row, return false if it was not in the tree
+    pub fn remove(&mut self, key: &[SqlValue], id: RowId) -> Result<bool, io::Error> {
+        let (mut page_no, _) = self.meta()?;
+        loop {
+            let mut node = self.node(page_no)?;
+            if !node.leaf {
+                page_no = node.child(key, id);
+                continue;
+            }
+            let index = node
+                .entries
+                .partition_point(|entry| entry.cmp(key, id).is_lt());
+            if index == node.entries.len() || node.entries[index].cmp(key, id).is_ne() {
+                return Ok(false);
+            }
+            node.entries.remove(index);
+            self.write(page_no, &node)?;
+            return Ok(true);
+        }
+    }
+
+    /// keys and row ids whose first key column is within the bounds, in key order
+    pub fn range(
+        &mut self,
+        lower: Bound<&SqlValue>,
+        upper: Bound<&SqlValue>,
+    ) -> Result<Vec<(Vec<SqlValue>, RowId)>, io::Error> {
+        let (mut page_no, _) = self.meta()?;
+        // go down to the first leaf which may hold the lower bound
+        loop {
+            let node = self.node(page_no)?;
+            if node.leaf {
+                break;
+            }
+            page_no = match lower {
+                Bound::Included(value) | Bound::Excluded(value) => node.first_child(value),
+                Bound::Unbounded => node.link,
+            };
+        }
+        let mut found = Vec::new();
+        while page_no != NO_PAGE {
+            let node = self.node(page_no)?;
+            for entry in node.entries {
+                let above_lower = match lower {
+                    Bound::Included(value) => entry.cmp_first(value).is_ge(),
+                    Bound::Excluded(value) => entry.cmp_first(value).is_gt(),
+                    Bound::Unbounded => true,
+                };
+                let below_upper = match upper {
+                    Bound::Included(value) => entry.cmp_first(value).is_le(),
+                    Bound::Excluded(value) => entry.cmp_first(value).is_lt(),
+                    Bound::Unbounded => true,
+                };
+                if !below_upper {
+                    return Ok(found);
+                }
+                if above_lower {
+                    found.push((entry.key, entry.id));
+                }
+            }
+            page_no = node.link;
+        }
+        Ok(found)
+    }
+
+    /// ids of the rows with exactly this key
+    pub fn get(&mut self, key: &[SqlValue]) -> Result<Vec<RowId>, io::Error> {
+        let first = match key.first() {
+            Some(first) => first,
+            None => return Ok(Vec::new()),
+        };
+        Ok(self
+            .range(Bound::Included(first), Bound::Included(first))?
+            .into_iter()
+            .filter(|(other, _)| compare_keys(other, key).is_eq())
+            .map(|(_, id)| id)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(i: i32) -> Vec<SqlValue> {
+        vec![SqlValue::Int(i), SqlValue::String(format!("value {i}"))]
+    }
+
+    #[test]
+    fn test_insert_range_remove() {
+        let mut pages = BTree::empty();
+        let mut tree = BTree::new(&mut pages);
+        // out of order, with duplicate keys
+        for i in 0..3000 {
+            let n = (i * 7919) % 3000;
+            tree.insert(key(n / 2), n as RowId).unwrap();
+        }
+        let (root, page_count) = tree.meta().unwrap();
+        assert!(root > 1 && page_count > 10);
+
+        let found = tree
+            .range(
+                Bound::Included(&SqlValue::Int(10)),
+                Bound::Excluded(&SqlValue::Int(20)),
+            )
+            .unwrap();
+        let ids: Vec<RowId> = found.iter().map(|(_, id)| *id).collect();
+        assert_eq!(ids, (20..40).collect::<Vec<RowId>>());
+        assert_eq!(tree.get(&key(700)).unwrap(), vec![1400, 1401]);
+
+        assert!(tree.remove(&key(700), 1400).unwrap());
+        assert!(!tree.remove(&key(700), 1400).unwrap());
+        assert_eq!(tree.get(&key(700)).unwrap(), vec![1401]);
+        let all = tree.range(Bound::Unbounded, Bound::Unbounded).unwrap();
+        assert_eq!(all.len(), 2999);
+        assert!(all
+            .windows(2)
+            .all(|pair| compare_keys(&pair[0].0, &pair[1].0).is_le()));
+        let above = tree
+            .range(Bound::Excluded(&SqlValue::Int(1498)), Bound::Unbounded)
+            .unwrap();
+        assert_eq!(above.len(), 2);
+    }
+}
@@ -0,0 +1,191 @@
+use super::page::{Page, PAGE_SIZE};
+use super::wal::Wal;
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// Number of pages cached by default
+pub const DEFAULT_POOL_SIZE: usize = 256;
+
+/// Page `page_no` of the file at the path
+type PageKey = (String, u32);
+
+struct Frame {
+    page: Page,
+    dirty: bool,
+    last_used: u64,
+}
+
+/// Count of page I/O, used to check how many pages a statement touches
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    pub reads: usize,
+    pub writes: usize,
+}
+
+/// A bounded cache of pages shared by all the files of a storage.
+///
+/// Modified pages stay in the pool until the file is flushed or the page is evicted,
+/// the least recently used page is evicted once the pool is full.
+/// Every write goes through the write-ahead log of the pool.
+pub struct BufferPool {
+    capacity: usize,
+    frames: HashMap<PageKey, Frame>,
+    files: HashMap<String, File>,
+    tick: u64,
+    stats: PoolStats,
+    wal: Wal,
+}
+
+impl BufferPool {
+    pub fn new(capacity: usize, wal: Wal) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            frames: HashMap::new(),
+            files: HashMap::new(),
+            tick: 0,
+            stats: PoolStats::default(),
+            wal,
+        }
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        self.stats
+    }
+
+    fn file(&mut self, path: &str) -> Result<&mut File, io::Error> {
+        if !self.files.contains_key(path) {
+            let file = OpenOptions::new().read(true).write(true).open(path)?;
+            self.files.insert(path.to_string(), file);
+        }
+        Ok(self.files.get_mut(path).unwrap())
+    }
+
+    fn write_page(&mut self, path: &str, page_no: u32, page: &Page) -> Result<(), io::Error> {
+        self.file(path)?;
+        let file = self.files.get_mut(path).unwrap();
+        self.wal.write_page(path, file, page_no, page.as_bytes())?;
+        self.stats.writes += 1;
+        Ok(())
+    }
+
+    /// make room for one more page
+    fn evict(&mut self) -> Result<(), io::Error> {
+        if self.frames.len() < self.capacity {
+            return Ok(());
+        }
+        let key = match self.frames.iter().min_by_key(|(_, frame)| frame.last_used) {
+            Some((key, _)) => key.clone(),
+            None => return Ok(()),
+        };
+        let frame = self.frames.remove(&key).unwrap();
+        if frame.dirty {
+            self.write_page(&key.0, key.1, &frame.page)?;
+        }
+        Ok(())
+    }
+
+    fn frame(&mut self, path: &str, page_no: u32) -> Result<&mut Frame, io::Error> {
+        let key = (path.to_string(), page_no);
+        if !self.frames.contains_key(&key) {
+            self.evict()?;
+            let mut bytes = vec![0; PAGE_SIZE];
+            let file = self.file(path)?;
+            file.seek(SeekFrom::Start(page_no as u64 * PAGE_SIZE as u64))?;
+            file.read_exact(&mut bytes)?;
+            self.stats.reads += 1;
+            let frame = Frame {
+                page: Page::from_bytes(&bytes)?,
+                dirty: false,
+                last_used: 0,
+            };
+            self.frames.insert(key.clone(), frame);
+        }
+        self.tick += 1;
+        let frame = self.frames.get_mut(&key).unwrap();
+        frame.last_used = self.tick;
+        Ok(frame)
+    }
+
+    /// read a page
+    pub fn page(&mut self, path: &str, page_no: u32) -> Result<&Page, io::Error> {
+        Ok(&self.frame(path, page_no)?.page)
+    }
+
+    /// get a page to modify, it will be written back when flushed or evicted
+    pub fn page_mut(&mut self, path: &str, page_no: u32) -> Result<&mut Page, io::Error> {
+        let frame = self.frame(path, page_no)?;
+        frame.dirty = true;
+        Ok(&mut frame.page)
+    }
+
+    /// add a new empty page `page_no` at the end of the file
+    pub fn new_page(&mut self, path: &str, page_no: u32) -> Result<&mut Page, io::Error> {
+        self.evict()?;
+        self.tick += 1;
+        let frame = Frame {
+            page: Page::new(),
+            dirty: true,
+            last_used: self.tick,
+        };
+        let key = (path.to_string(), page_no);
+        self.frames.insert(key.clone(), frame);
+        Ok(&mut self.frames.get_mut(&key).unwrap().page)
+    }
+
+    /// write all modified pages of the file back and sync it to disk
+    pub fn flush(&mut self, path: &str) -> Result<(), io::Error> {
+        let mut dirty: Vec<u32> = self
+            .frames
+            .iter()
+            .filter(|((frame_path, _), frame)| frame_path == path && frame.dirty)
+            .map(|((_, page_no), _)| *page_no)
+            .collect();
+        if dirty.is_empty() {
+            return Ok(());
+        }
+        dirty.sort();
+        for page_no in dirty {
+            let key = (path.to_string(), page_no);
+            let page = self.frames[&key].page.clone();
+            self.write_page(path, page_no, &page)?;
+            self.frames.get_mut(&key).unwrap().dirty = false;
+        }
+        self.file(path)?.sync_all()
+    }
+
+    /// forget every page of the file without writing them,
+    /// used when the file is removed or rewritten
+    pub fn discard(&mut self, path: &str) {
+        self.frames.retain(|(frame_path, _), _| frame_path != path);
+        self.files.remove(path);
+    }
+
+    /// forget the pages which are not modified, they are read again from the files.
+    /// The files are opened again too, another process may have replaced them
+    pub fn invalidate(&mut self) {
+        self.frames.retain(|_, frame| frame.dirty);
+        self.files.clear();
+    }
+
+    /// replace the whole file, or remove it if `contents` is None
+    pub fn write_file(&mut self, path: &str, contents: Option<&[u8]>) -> Result<(), io::Error> {
+        self.discard(path);
+        self.wal.write_file(path, contents)
+    }
+
+    pub fn begin(&mut self) -> Result<(), io::Error> {
+        self.wal.begin()
+    }
+
+    pub fn commit(&mut self) -> Result<(), io::Error> {
+        self.wal.commit()
+    }
+
+    /// undo the writes since `begin`, the cached pages may be newer than the files so they are dropped
+    pub fn rollback(&mut self) -> Result<(), io::Error> {
+        self.frames.clear();
+        self.files.clear();
+        self.wal.rollback()
+    }
+}
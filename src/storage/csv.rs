@@ -0,0 +1,246 @@
+use super::super::executor::types::SqlTable;
+use super::super::sql_analyzer::parser::Parse;
+use super::super::sql_analyzer::types::{
+    encode_hex, Column, ColumnConstraints, RowValue, SqlType, SqlValue,
+};
+use super::{LockGuard, LockMode, StorageEngine, TableDir, Wal};
+use csv::{ReaderBuilder, StringRecord, Writer};
+use std::fs;
+use std::io;
+use std::time::Duration;
+
+/// The persistent data table is in csv format.
+///
+/// The table is constructed with the first row as the column name, the second row recording the data format(string, int)
+/// followed by the constraints of the column, and the third row starting to record the data content.
+/// NULL is written as `\N`, a string starting with a backslash gets one more in front
+pub struct CsvStorage {
+    dir: TableDir,
+    wal: Wal,
+}
+
+impl CsvStorage {
+    /// create a storage without write-ahead log
+    pub fn new(path_root: &str) -> Self {
+        Self {
+            dir: TableDir::new(path_root, "csv"),
+            wal: Wal::disabled(),
+        }
+    }
+
+    /// open the storage with a write-ahead log in `path_root`, the tables are recovered first
+    /// and the tables of older versions are migrated
+    pub fn open(path_root: &str) -> Result<Self, io::Error> {
+        let storage = Self {
+            wal: Wal::open(path_root)?,
+            ..Self::new(path_root)
+        };
+        storage.migrate()?;
+        Ok(storage)
+    }
+
+    /// Older versions wrote the table `<name>` to a file without extension,
+    /// such a file is renamed to `<name>.csv` if it reads as a table
+    fn migrate(&self) -> Result<(), io::Error> {
+        let _lock = self.dir.lock(&[], LockMode::Exclusive)?;
+        for entry in fs::read_dir(&self.dir.path_root)? {
+            let path = entry?.path();
+            if !path.is_file() || path.extension().is_some() {
+                continue;
+            }
+            let name = match path.file_name() {
+                Some(name) => name.to_string_lossy().to_string(),
+                None => continue,
+            };
+            if name.starts_with('.') || self.dir.exists(&name) {
+                continue;
+            }
+            if fs::File::open(&path).and_then(load).is_ok() {
+                fs::rename(&path, self.dir.get_path(&name))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl StorageEngine for CsvStorage {
+    fn exists(&self, name: &str) -> bool {
+        self.dir.exists(name)
+    }
+
+    fn list_tables(&self) -> Result<Vec<String>, io::Error> {
+        self.dir.list_tables()
+    }
+
+    fn load(&self, name: &str) -> Result<SqlTable, io::Error> {
+        load(self.dir.open(name)?)
+    }
+
+    fn save(&mut self, name: &str, table: &SqlTable) -> Result<(), io::Error> {
+        let contents = save(table)?;
+        self.dir.write(&mut self.wal, name, &contents)
+    }
+
+    fn delete(&mut self, name: &str) -> Result<(), io::Error> {
+        self.dir.delete(&mut self.wal, name)
+    }
+
+    fn lock(&mut self, names: &[&str], mode: LockMode) -> Result<LockGuard, io::Error> {
+        self.dir.lock(names, mode)
+    }
+
+    fn set_busy_timeout(&mut self, timeout: Duration) {
+        self.dir.busy_timeout = timeout;
+    }
+
+    fn begin(&mut self) -> Result<(), io::Error> {
+        self.wal.begin()
+    }
+
+    fn commit(&mut self) -> Result<(), io::Error> {
+        self.wal.commit()
+    }
+
+    fn rollback(&mut self) -> Result<(), io::Error> {
+        self.wal.rollback()
+    }
+}
+
+/// field of a NULL value
+const NULL_FIELD: &str = "\\N";
+
+/// read a csv table
+fn load(file: impl io::Read) -> Result<SqlTable, io::Error> {
+    let mut csv_reader = ReaderBuilder::new().has_headers(true).from_reader(file);
+    let head: &StringRecord = csv::Reader::headers(&mut csv_reader)?;
+    let columns_name: Vec<String> = head.iter().map(|col| col.to_string()).collect();
+    let records: Vec<StringRecord> =
+        csv::Reader::records(&mut csv_reader).collect::<Result<_, _>>()?;
+    let columns_type: Vec<String> = records
+        .first()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Missing column types"))?
+        .iter()
+        .map(|col| col.to_string())
+        .collect();
+    let columns: Vec<Column> = columns_name
+        .iter()
+        .zip(columns_type.iter())
+        .map(|(name, type_name)| {
+            // the constraints follow the type, as in a column definition
+            let (type_name, constraints) =
+                type_name.split_at(type_name.find(' ').unwrap_or(type_name.len()));
+            Ok(Column {
+                name: name.to_string(),
+                type_info: match type_name {
+                    "String" => SqlType::String,
+                    "Int" => SqlType::Int,
+                    "BigInt" => SqlType::BigInt,
+                    "Double" => SqlType::Double,
+                    "Boolean" => SqlType::Boolean,
+                    "Date" => SqlType::Date,
+                    "Time" => SqlType::Time,
+                    "Timestamp" => SqlType::Timestamp,
+                    "Blob" => SqlType::Blob,
+                    // a type with a size is read as in a column definition
+                    _ => SqlType::parse_format_error(type_name).unwrap_or(SqlType::Unknown),
+                },
+                constraints: ColumnConstraints::parse_format_error(constraints).map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidData, "Invalid column constraints")
+                })?,
+            })
+        })
+        .collect::<Result<_, io::Error>>()?;
+    let rows: Vec<RowValue> = records[1..]
+        .iter()
+        .map(|record| {
+            let values: Vec<SqlValue> = record
+                .iter()
+                .zip(columns.iter())
+                .map(|(field, column)| match column.type_info {
+                    _ if field == NULL_FIELD => SqlValue::Null,
+                    SqlType::String | SqlType::Varchar(_) | SqlType::Char(_) => {
+                        SqlValue::String(field.strip_prefix('\\').unwrap_or(field).to_string())
+                    }
+                    SqlType::Int => field
+                        .to_string()
+                        .parse::<i32>()
+                        .map_or(SqlValue::Null, SqlValue::Int),
+                    SqlType::BigInt => field.parse().map_or(SqlValue::Null, SqlValue::BigInt),
+                    SqlType::Double => field.parse().map_or(SqlValue::Null, SqlValue::Double),
+                    SqlType::Boolean => field.parse().map_or(SqlValue::Null, SqlValue::Boolean),
+                    SqlType::Blob
+                    | SqlType::Decimal(_, _)
+                    | SqlType::Date
+                    | SqlType::Time
+                    | SqlType::Timestamp => {
+                        SqlValue::from_text(field, &column.type_info).unwrap_or(SqlValue::Null)
+                    }
+                    _ => SqlValue::Null,
+                })
+                .collect();
+            RowValue { values }
+        })
+        .collect();
+
+    Ok(SqlTable { columns, rows })
+}
+
+/// write the whole table in csv format
+fn save(table: &SqlTable) -> Result<Vec<u8>, io::Error> {
+    //get infos from table
+    let columns = &table.columns;
+    let columns_name: Vec<String> = columns.iter().map(|column| column.name.clone()).collect();
+    let columns_type: Vec<String> = columns
+        .iter()
+        .map(|column| {
+            let type_name = match column.type_info {
+                SqlType::String => String::from("String"),
+                SqlType::Int => String::from("Int"),
+                SqlType::BigInt => String::from("BigInt"),
+                SqlType::Double => String::from("Double"),
+                SqlType::Boolean => String::from("Boolean"),
+                SqlType::Date => String::from("Date"),
+                SqlType::Time => String::from("Time"),
+                SqlType::Timestamp => String::from("Timestamp"),
+                SqlType::Blob => String::from("Blob"),
+                // sizes are written as in a column definition, without spaces
+                SqlType::Varchar(length) => format!("Varchar({length})"),
+                SqlType::Char(length) => format!("Char({length})"),
+                SqlType::Decimal(precision, scale) => format!("Decimal({precision},{scale})"),
+                _ => String::from("Unknown"),
+            };
+            format!("{type_name}{}", column.constraints)
+        })
+        .collect();
+
+    //write
+    //Temporarily not considering resource consumption for multiple complete storage
+    let mut writer_csv = Writer::from_writer(Vec::new());
+    writer_csv.write_record(columns_name)?;
+    writer_csv.write_record(columns_type)?;
+    for row in &table.rows {
+        let row_values: Vec<String> = row
+            .values
+            .iter()
+            .map(|sql_value| match sql_value {
+                SqlValue::String(s) if s.starts_with('\\') => format!("\\{s}"),
+                SqlValue::String(s) => s.clone(),
+                // bytes are written as hex digits, a csv file only keeps text
+                SqlValue::Blob(b) => encode_hex(b),
+                SqlValue::Int(i) => i.to_string(),
+                SqlValue::BigInt(i) => i.to_string(),
+                SqlValue::Double(d) => d.to_string(),
+                SqlValue::Decimal(d) => d.to_string(),
+                SqlValue::Boolean(b) => b.to_string(),
+                SqlValue::Date(d) => d.to_string(),
+                SqlValue::Time(t) => t.to_string(),
+                SqlValue::Timestamp(t) => t.to_string(),
+                SqlValue::Null => String::from(NULL_FIELD),
+            })
+            .collect();
+        writer_csv.write_record(row_values)?;
+    }
+    writer_csv
+        .into_inner()
+        .map_err(|err| io::Error::other(err.to_string()))
+}
@@ -0,0 +1,352 @@
+use super::super::sql_analyzer::types::{RowValue, SqlValue};
+use super::btree::{compare_keys, PageStore, MAX_KEY_SIZE};
+use super::page::Page;
+use super::record::{decode_row, encode_row};
+use super::RowId;
+use std::io;
+
+// A linear hash index. Page 0 keeps a single record: the last bucket (u32), the masks of the
+// hash for the current round (u32, u32), the number of pages (u32), the number of entries (u64)
+// and the number of overflow pages allocated up to the end of every round (u32 each).
+// A bucket is a chain of pages, slot 0 of a page keeps the next page of the chain (u32)
+// and the following slots keep the entries: the row id (u64) and the encoded key.
+// Round r > 0 adds the buckets 2^(r-1) to 2^r - 1, their pages are allocated together
+// when the first of them is needed, so the page of a bucket is computed from the
+// overflow pages allocated before its round instead of being kept in a directory.
+
+const META_PAGE: u32 = 0;
+/// end of a chain, page 0 is never a bucket
+const NO_PAGE: u32 = 0;
+const ROUNDS: usize = 32;
+const META_SIZE: usize = 24 + ROUNDS * 4;
+/// average number of entries per bucket above which the next bucket is split
+const SPLIT_LOAD: u64 = 64;
+
+fn broken_index() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "Hash index is broken")
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+}
+
+/// FNV-1a hash of the encoded key, it stays the same from one process to another
+fn hash_key(key: &[SqlValue]) -> u32 {
+    let encoded = encode_row(&RowValue {
+        values: key.to_vec(),
+    });
+    encoded.iter().fold(0x811c_9dc5, |hash: u32, byte| {
+        (hash ^ *byte as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
+/// round which added the bucket, bucket 0 is the only one of round 0
+fn round(bucket: u32) -> usize {
+    (u32::BITS - bucket.leading_zeros()) as usize
+}
+
+fn encode_entry(key: &[SqlValue], id: RowId) -> Vec<u8> {
+    let mut bytes = id.to_le_bytes().to_vec();
+    bytes.extend(encode_row(&RowValue {
+        values: key.to_vec(),
+    }));
+    bytes
+}
+
+fn decode_entry(bytes: &[u8]) -> Result<(Vec<SqlValue>, RowId), io::Error> {
+    if bytes.len() < 8 {
+        return Err(broken_index());
+    }
+    let mut id = [0; 8];
+    id.copy_from_slice(&bytes[..8]);
+    Ok((decode_row(&bytes[8..])?.values, RowId::from_le_bytes(id)))
+}
+
+/// an empty page of a chain
+fn chain_page(next: u32) -> Page {
+    let mut page = Page::new();
+    page.insert(&next.to_le_bytes());
+    page
+}
+
+fn next_page(page: &Page) -> Result<u32, io::Error> {
+    match page.get(0) {
+        Some(header) if header.len() == 4 => Ok(read_u32(header, 0)),
+        _ => Err(broken_index()),
+    }
+}
+
+struct Meta {
+    max_bucket: u32,
+    highmask: u32,
+    lowmask: u32,
+    page_count: u32,
+    entry_count: u64,
+    spares: [u32; ROUNDS],
+}
+
+impl Meta {
+    fn read(page: &Page) -> Result<Self, io::Error> {
+        let record = page.get(0).ok_or_else(broken_index)?;
+        if record.len() != META_SIZE {
+            return Err(broken_index());
+        }
+        let mut entry_count = [0; 8];
+        entry_count.copy_from_slice(&record[16..24]);
+        let mut spares = [0; ROUNDS];
+        for (round, spare) in spares.iter_mut().enumerate() {
+            *spare = read_u32(record, 24 + round * 4);
+        }
+        Ok(Self {
+            max_bucket: read_u32(record, 0),
+            highmask: read_u32(record, 4),
+            lowmask: read_u32(record, 8),
+            page_count: read_u32(record, 12),
+            entry_count: u64::from_le_bytes(entry_count),
+            spares,
+        })
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut record = Vec::with_capacity(META_SIZE);
+        for value in [
+            self.max_bucket,
+            self.highmask,
+            self.lowmask,
+            self.page_count,
+        ] {
+            record.extend_from_slice(&value.to_le_bytes());
+        }
+        record.extend_from_slice(&self.entry_count.to_le_bytes());
+        for spare in self.spares {
+            record.extend_from_slice(&spare.to_le_bytes());
+        }
+        record
+    }
+
+    fn bucket(&self, key: &[SqlValue]) -> u32 {
+        let bucket = hash_key(key) & self.highmask;
+        if bucket > self.max_bucket {
+            // the bucket is not split yet
+            bucket & self.lowmask
+        } else {
+            bucket
+        }
+    }
+
+    /// first page of the chain of the bucket
+    fn bucket_page(&self, bucket: u32) -> u32 {
+        match round(bucket) {
+            0 => 1,
+            round => 1 + bucket + self.spares[round - 1],
+        }
+    }
+}
+
+/// A linear hash index from keys to row ids in a page store,
+/// a key is found by reading the meta page and the chain of its bucket
+pub struct HashIndex<'a> {
+    store: &'a mut dyn PageStore,
+}
+
+impl<'a> HashIndex<'a> {
+    pub fn new(store: &'a mut dyn PageStore) -> Self {
+        Self { store }
+    }
+
+    /// pages of an empty index: the meta page and the page of bucket 0
+    pub fn empty() -> Vec<Page> {
+        let meta = Meta {
+            max_bucket: 0,
+            highmask: 0,
+            lowmask: 0,
+            page_count: 2,
+            entry_count: 0,
+            spares: [0; ROUNDS],
+        };
+        let mut page = Page::new();
+        page.insert(&meta.encode());
+        vec![page, chain_page(NO_PAGE)]
+    }
+
+    fn meta(&mut self) -> Result<Meta, io::Error> {
+        Meta::read(self.store.page(META_PAGE)?)
+    }
+
+    fn set_meta(&mut self, meta: &Meta) -> Result<(), io::Error> {
+        if !self.store.page_mut(META_PAGE)?.update(0, &meta.encode()) {
+            return Err(broken_index());
+        }
+        Ok(())
+    }
+
+    /// pages of the chain of the bucket
+    fn chain(&mut self, meta: &Meta, bucket: u32) -> Result<Vec<u32>, io::Error> {
+        let mut page_no = meta.bucket_page(bucket);
+        let mut chain = Vec::new();
+        while page_no != NO_PAGE {
+            if page_no >= meta.page_count || chain.contains(&page_no) {
+                return Err(broken_index());
+            }
+            chain.push(page_no);
+            page_no = next_page(self.store.page(page_no)?)?;
+        }
+        Ok(chain)
+    }
+
+    /// put an encoded entry in the chain of the bucket, a page is added to the chain if it is full
+    fn put(&mut self, meta: &mut Meta, bucket: u32, record: &[u8]) -> Result<(), io::Error> {
+        let chain = self.chain(meta, bucket)?;
+        for page_no in &chain {
+            if self.store.page(*page_no)?.free_space() >= record.len() {
+                self.store.page_mut(*page_no)?.insert(record);
+                return Ok(());
+            }
+        }
+        let page_no = meta.page_count;
+        meta.page_count += 1;
+        meta.spares[round(meta.max_bucket)] += 1;
+        let mut page = chain_page(NO_PAGE);
+        page.insert(record).ok_or_else(broken_index)?;
+        *self.store.new_page(page_no)? = page;
+        let last = *chain.last().ok_or_else(broken_index)?;
+        if !self.store.page_mut(last)?.update(0, &page_no.to_le_bytes()) {
+            return Err(broken_index());
+        }
+        Ok(())
+    }
+
+    /// add the next bucket and move to it the entries of the bucket it is split from
+    fn split(&mut self, meta: &mut Meta) -> Result<(), io::Error> {
+        let new_bucket = meta.max_bucket + 1;
+        let new_round = round(new_bucket);
+        if new_round >= ROUNDS {
+            return Ok(());
+        }
+        if new_bucket > meta.highmask {
+            // a new round starts, the pages of all its buckets are allocated now
+            meta.spares[new_round] = meta.spares[new_round - 1];
+            for _ in 0..1u32 << (new_round - 1) {
+                *self.store.new_page(meta.page_count)? = chain_page(NO_PAGE);
+                meta.page_count += 1;
+            }
+            meta.lowmask = meta.highmask;
+            meta.highmask = new_bucket | meta.lowmask;
+        }
+        meta.max_bucket = new_bucket;
+        let old_bucket = new_bucket & meta.lowmask;
+        let mut moved = Vec::new();
+        for page_no in self.chain(meta, old_bucket)? {
+            let mut slots = Vec::new();
+            for (slot, record) in self.store.page(page_no)?.records().skip(1) {
+                let (key, _) = decode_entry(record)?;
+                if meta.bucket(&key) == new_bucket {
+                    slots.push(slot);
+                    moved.push(record.to_vec());
+                }
+            }
+            if !slots.is_empty() {
+                let page = self.store.page_mut(page_no)?;
+                for slot in slots {
+                    page.delete(slot);
+                }
+            }
+        }
+        for record in moved {
+            self.put(meta, new_bucket, &record)?;
+        }
+        Ok(())
+    }
+
+    /// add the key of a row
+    pub fn insert(&mut self, key: Vec<SqlValue>, id: RowId) -> Result<(), io::Error> {
+        let record = encode_entry(&key, id);
+        if record.len() > MAX_KEY_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Key is too large for an index",
+            ));
+        }
+        let mut meta = self.meta()?;
+        let bucket = meta.bucket(&key);
+        self.put(&mut meta, bucket, &record)?;
+        meta.entry_count += 1;
+        if meta.entry_count > (meta.max_bucket as u64 + 1) * SPLIT_LOAD {
+            self.split(&mut meta)?;
+        }
+        self.set_meta(&meta)
+    }
+
+    /// remove the key of a row, false if it was not in the index
+    pub fn remove(&mut self, key: &[SqlValue], id: RowId) -> Result<bool, io::Error> {
+        let mut meta = self.meta()?;
+        for page_no in self.chain(&meta, meta.bucket(key))? {
+            let mut found = None;
+            for (slot, record) in self.store.page(page_no)?.records().skip(1) {
+                let (other, other_id) = decode_entry(record)?;
+                if other_id == id && compare_keys(&other, key).is_eq() {
+                    found = Some(slot);
+                    break;
+                }
+            }
+            if let Some(slot) = found {
+                self.store.page_mut(page_no)?.delete(slot);
+                meta.entry_count -= 1;
+                self.set_meta(&meta)?;
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// ids of the rows with exactly this key
+    pub fn get(&mut self, key: &[SqlValue]) -> Result<Vec<RowId>, io::Error> {
+        let meta = self.meta()?;
+        let mut ids = Vec::new();
+        for page_no in self.chain(&meta, meta.bucket(key))? {
+            for (_, record) in self.store.page(page_no)?.records().skip(1) {
+                let (other, id) = decode_entry(record)?;
+                if compare_keys(&other, key).is_eq() {
+                    ids.push(id);
+                }
+            }
+        }
+        Ok(ids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(i: i32) -> Vec<SqlValue> {
+        vec![SqlValue::Int(i), SqlValue::String(format!("value {i}"))]
+    }
+
+    #[test]
+    fn test_insert_get_remove() {
+        let mut pages = HashIndex::empty();
+        let mut index = HashIndex::new(&mut pages);
+        // with duplicate keys
+        for i in 0..5000 {
+            index.insert(key(i / 2), i as RowId).unwrap();
+        }
+        let meta = index.meta().unwrap();
+        assert_eq!(meta.entry_count, 5000);
+        assert!(meta.max_bucket >= 5000 / SPLIT_LOAD as u32 - 1);
+        assert_eq!(meta.page_count as usize, pages.len());
+
+        let mut index = HashIndex::new(&mut pages);
+        for i in (0..2500).step_by(97) {
+            let mut ids = index.get(&key(i)).unwrap();
+            ids.sort();
+            assert_eq!(ids, vec![2 * i as RowId, 2 * i as RowId + 1]);
+        }
+        assert!(index.get(&key(2500)).unwrap().is_empty());
+
+        assert!(index.remove(&key(700), 1400).unwrap());
+        assert!(!index.remove(&key(700), 1400).unwrap());
+        assert_eq!(index.get(&key(700)).unwrap(), vec![1401]);
+        assert_eq!(index.meta().unwrap().entry_count, 4999);
+    }
+}
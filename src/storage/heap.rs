@@ -0,0 +1,981 @@
+use super::super::executor::types::{ColumnInfo, IndexInfo, SqlTable};
+use super::super::sql_analyzer::types::{RowValue, SqlValue};
+use super::btree::FilePages;
+use super::buffer_pool::{BufferPool, PoolStats, DEFAULT_POOL_SIZE};
+use super::index::{index_not_found, lookup, IndexCatalog, KeyColumns};
+use super::page::{Page, MAX_RECORD_SIZE, PAGE_SIZE};
+use super::record::{decode_row, encode_row};
+use super::{table_not_found, LockGuard, LockMode, RowId, StorageEngine, TableDir, TableScan, Wal};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::ops::Bound;
+use std::time::Duration;
+
+// A heap file `<name>.heap` is a sequence of slotted pages.
+// Page 0 keeps a single record: the number of pages (u32) followed by the column infos in json,
+// the rows are kept in the following pages in no particular order.
+// The free space map `<name>.fsm` keeps one byte per page, the free space of the page divided by
+// FSM_UNIT. It is only a hint and is rebuilt from the pages when it is missing.
+// The indexes of all the tables are listed in `indexes.catalog`, each index is a B+tree
+// kept in `<index>.idx` which maps the key of a row to its row id.
+// There are no overflow pages, so an encoded row takes at most MAX_RECORD_SIZE bytes,
+// a larger row is rejected with a `RowTooLarge` error.
+
+const FSM_UNIT: usize = 16;
+const HEADER_PAGE: u32 = 0;
+const HEADER_SLOT: usize = 0;
+const CATALOG_FILE: &str = "indexes.catalog";
+
+fn row_id(page_no: u32, slot: usize) -> RowId {
+    ((page_no as u64) << 16) | slot as u64
+}
+
+fn split_row_id(id: RowId) -> (u32, usize) {
+    ((id >> 16) as u32, (id & 0xffff) as usize)
+}
+
+fn broken_heap() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "Heap file is broken")
+}
+
+/// An encoded row does not fit in a page, the size of the row and the limit
+#[derive(Debug)]
+struct RowTooLarge(usize, usize);
+
+impl fmt::Display for RowTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Row of {} bytes is larger than the {} bytes a page keeps",
+            self.0, self.1
+        )
+    }
+}
+
+impl std::error::Error for RowTooLarge {}
+
+/// check that an encoded row fits in a page
+fn check_record_size(record: &[u8]) -> Result<(), io::Error> {
+    if record.len() > MAX_RECORD_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            RowTooLarge(record.len(), MAX_RECORD_SIZE),
+        ));
+    }
+    Ok(())
+}
+
+/// the size of the row and the largest size a row could have,
+/// when the error was caused by a row too large for a heap table
+pub fn row_too_large(err: &io::Error) -> Option<(usize, usize)> {
+    err.get_ref()
+        .and_then(|err| err.downcast_ref::<RowTooLarge>())
+        .map(|RowTooLarge(size, limit)| (*size, *limit))
+}
+
+fn fsm_entry(page: &Page) -> u8 {
+    (page.free_space() / FSM_UNIT).min(u8::MAX as usize) as u8
+}
+
+/// The persistent data table is kept in a binary heap file of fixed-size pages.
+///
+/// Pages are cached by a bounded buffer pool, so changing a single row only
+/// reads and writes the page where the row is kept.
+pub struct HeapStorage {
+    dir: TableDir,
+    pool: RefCell<BufferPool>,
+    /// free space map of the tables which have been used
+    fsm: RefCell<HashMap<String, Vec<u8>>>,
+    /// index catalog once it has been read
+    catalog: RefCell<Option<IndexCatalog>>,
+}
+
+impl HeapStorage {
+    /// create a storage without write-ahead log
+    pub fn new(path_root: &str) -> Self {
+        Self::with_pool(
+            path_root,
+            BufferPool::new(DEFAULT_POOL_SIZE, Wal::disabled()),
+        )
+    }
+
+    /// create a storage without write-ahead log which caches at most `pool_size` pages
+    pub fn with_pool_size(path_root: &str, pool_size: usize) -> Self {
+        Self::with_pool(path_root, BufferPool::new(pool_size, Wal::disabled()))
+    }
+
+    /// open the storage with a write-ahead log in `path_root`, the tables are recovered first
+    pub fn open(path_root: &str) -> Result<Self, io::Error> {
+        let pool = BufferPool::new(DEFAULT_POOL_SIZE, Wal::open(path_root)?);
+        Ok(Self::with_pool(path_root, pool))
+    }
+
+    fn with_pool(path_root: &str, pool: BufferPool) -> Self {
+        Self {
+            dir: TableDir::new(path_root, "heap"),
+            pool: RefCell::new(pool),
+            fsm: RefCell::new(HashMap::new()),
+            catalog: RefCell::new(None),
+        }
+    }
+
+    fn fsm_path(&self, name: &str) -> String {
+        format!("{}/{}.fsm", self.dir.path_root, name)
+    }
+
+    fn catalog_path(&self) -> String {
+        format!("{}/{}", self.dir.path_root, CATALOG_FILE)
+    }
+
+    fn index_path(&self, index: &str) -> String {
+        format!("{}/{}.idx", self.dir.path_root, index)
+    }
+
+    /// the index catalog, read from its file the first time
+    fn catalog(&self) -> Result<IndexCatalog, io::Error> {
+        if let Some(catalog) = self.catalog.borrow().as_ref() {
+            return Ok(catalog.clone());
+        }
+        let catalog = match fs::read(self.catalog_path()) {
+            Ok(bytes) => IndexCatalog::from_bytes(&bytes)?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => IndexCatalog::default(),
+            Err(err) => return Err(err),
+        };
+        *self.catalog.borrow_mut() = Some(catalog.clone());
+        Ok(catalog)
+    }
+
+    /// replace the catalog file, it is read again when needed
+    fn write_catalog(&self, catalog: &IndexCatalog) -> Result<(), io::Error> {
+        *self.catalog.borrow_mut() = None;
+        self.pool
+            .borrow_mut()
+            .write_file(&self.catalog_path(), Some(&catalog.to_bytes()?))
+    }
+
+    /// run `f` on the tree of every index
+    fn each_index(
+        &self,
+        indexes: &[IndexInfo],
+        columns: &ColumnInfo,
+        mut f: impl FnMut(&KeyColumns, &mut FilePages) -> Result<(), io::Error>,
+    ) -> Result<(), io::Error> {
+        for index in indexes {
+            let key_columns = KeyColumns::new(index, columns)?;
+            let path = self.index_path(&index.name);
+            let mut pool = self.pool.borrow_mut();
+            f(
+                &key_columns,
+                &mut FilePages {
+                    pool: &mut pool,
+                    path: &path,
+                },
+            )?;
+        }
+        Ok(())
+    }
+
+    /// number of pages and column infos kept in the header page
+    fn header(&self, path: &str) -> Result<(u32, ColumnInfo), io::Error> {
+        let mut pool = self.pool.borrow_mut();
+        let record = pool
+            .page(path, HEADER_PAGE)?
+            .get(HEADER_SLOT)
+            .ok_or_else(broken_heap)?;
+        if record.len() < 4 {
+            return Err(broken_heap());
+        }
+        let page_count = u32::from_le_bytes([record[0], record[1], record[2], record[3]]);
+        let columns = serde_json::from_slice(&record[4..])?;
+        Ok((page_count, columns))
+    }
+
+    fn header_record(page_count: u32, columns: &ColumnInfo) -> Result<Vec<u8>, io::Error> {
+        let mut record = page_count.to_le_bytes().to_vec();
+        record.extend(serde_json::to_vec(columns)?);
+        if record.len() > MAX_RECORD_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Too many columns for a heap table",
+            ));
+        }
+        Ok(record)
+    }
+
+    fn set_page_count(&self, path: &str, page_count: u32) -> Result<(), io::Error> {
+        let (_, columns) = self.header(path)?;
+        let record = Self::header_record(page_count, &columns)?;
+        let mut pool = self.pool.borrow_mut();
+        if !pool
+            .page_mut(path, HEADER_PAGE)?
+            .update(HEADER_SLOT, &record)
+        {
+            return Err(broken_heap());
+        }
+        Ok(())
+    }
+
+    /// make sure the free space map of the table is loaded
+    fn load_fsm(&self, name: &str, path: &str) -> Result<(), io::Error> {
+        if self.fsm.borrow().contains_key(name) {
+            return Ok(());
+        }
+        let (page_count, _) = self.header(path)?;
+        let fsm = match fs::read(self.fsm_path(name)) {
+            Ok(fsm) if fsm.len() == page_count as usize => fsm,
+            // rebuild the map from the pages
+            _ => {
+                let mut pool = self.pool.borrow_mut();
+                let mut fsm = vec![0];
+                for page_no in 1..page_count {
+                    fsm.push(fsm_entry(pool.page(path, page_no)?));
+                }
+                fsm
+            }
+        };
+        self.fsm.borrow_mut().insert(name.to_string(), fsm);
+        Ok(())
+    }
+
+    fn update_fsm(&self, name: &str, page_no: u32, page: &Page) {
+        if let Some(fsm) = self.fsm.borrow_mut().get_mut(name) {
+            let page_no = page_no as usize;
+            if fsm.len() <= page_no {
+                fsm.resize(page_no + 1, 0);
+            }
+            fsm[page_no] = fsm_entry(page);
+        }
+    }
+
+    /// write the modified pages, the free space map and the indexes of the table to disk
+    fn flush(&self, name: &str, path: &str) -> Result<(), io::Error> {
+        let indexes = self.catalog()?.of_table(name);
+        let mut pool = self.pool.borrow_mut();
+        pool.flush(path)?;
+        if let Some(fsm) = self.fsm.borrow().get(name) {
+            pool.write_file(&self.fsm_path(name), Some(fsm))?;
+        }
+        for index in indexes {
+            pool.flush(&self.index_path(&index.name))?;
+        }
+        Ok(())
+    }
+
+    /// put an encoded row in a page with enough free space, or in a new page
+    fn insert_record(&self, name: &str, path: &str, record: &[u8]) -> Result<RowId, io::Error> {
+        check_record_size(record)?;
+        self.load_fsm(name, path)?;
+        let candidate = self.fsm.borrow()[name]
+            .iter()
+            .enumerate()
+            .skip(1)
+            .find(|(_, free)| **free as usize * FSM_UNIT >= record.len())
+            .map(|(page_no, _)| page_no as u32);
+        if let Some(page_no) = candidate {
+            let mut pool = self.pool.borrow_mut();
+            let page = pool.page_mut(path, page_no)?;
+            let slot = page.insert(record);
+            self.update_fsm(name, page_no, page);
+            if let Some(slot) = slot {
+                return Ok(row_id(page_no, slot));
+            }
+        }
+        let (page_count, _) = self.header(path)?;
+        self.set_page_count(path, page_count + 1)?;
+        let mut pool = self.pool.borrow_mut();
+        let page = pool.new_page(path, page_count)?;
+        let slot = page.insert(record).ok_or_else(broken_heap)?;
+        self.update_fsm(name, page_count, page);
+        Ok(row_id(page_count, slot))
+    }
+}
+
+/// contents of a file made of the pages
+fn pages_bytes(pages: &[Page]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(pages.len() * PAGE_SIZE);
+    for page in pages {
+        bytes.extend_from_slice(page.as_bytes());
+    }
+    bytes
+}
+
+/// commit the changes started with `begin`, or roll them back on error
+fn finish(pool: &mut BufferPool, result: Result<(), io::Error>) -> Result<(), io::Error> {
+    match result {
+        Ok(()) => pool.commit(),
+        Err(err) => {
+            pool.rollback()?;
+            Err(err)
+        }
+    }
+}
+
+/// Iterator over the rows of a heap table, one page is decoded at a time
+struct HeapScan<'a> {
+    storage: &'a HeapStorage,
+    path: String,
+    next_page: u32,
+    page_count: u32,
+    rows: VecDeque<(RowId, RowValue)>,
+}
+
+impl<'a> HeapScan<'a> {
+    fn read_page(&mut self) -> Result<(), io::Error> {
+        let page_no = self.next_page;
+        self.next_page += 1;
+        let mut pool = self.storage.pool.borrow_mut();
+        for (slot, record) in pool.page(&self.path, page_no)?.records() {
+            self.rows
+                .push_back((row_id(page_no, slot), decode_row(record)?));
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Iterator for HeapScan<'a> {
+    type Item = Result<(RowId, RowValue), io::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.rows.is_empty() && self.next_page < self.page_count {
+            if let Err(err) = self.read_page() {
+                // stop after the error
+                self.next_page = self.page_count;
+                return Some(Err(err));
+            }
+        }
+        self.rows.pop_front().map(Ok)
+    }
+}
+
+impl StorageEngine for HeapStorage {
+    fn exists(&self, name: &str) -> bool {
+        self.dir.exists(name)
+    }
+
+    fn list_tables(&self) -> Result<Vec<String>, io::Error> {
+        self.dir.list_tables()
+    }
+
+    fn load(&self, name: &str) -> Result<SqlTable, io::Error> {
+        let scan = self.scan(name)?;
+        let rows = scan
+            .rows
+            .map(|row| row.map(|(_, row)| row))
+            .collect::<Result<_, _>>()?;
+        Ok(SqlTable {
+            columns: scan.columns,
+            rows,
+        })
+    }
+
+    fn save(&mut self, name: &str, table: &SqlTable) -> Result<(), io::Error> {
+        let path = self.dir.get_path(name);
+        let indexes = self.catalog()?.of_table(name);
+        for index in &indexes {
+            KeyColumns::new(index, &table.columns)?.check_unique(&table.rows)?;
+        }
+        // pack the rows into pages
+        let mut pages = vec![Page::new()];
+        let mut fsm = vec![0];
+        let mut ids = Vec::with_capacity(table.rows.len());
+        for row in &table.rows {
+            let record = encode_row(row);
+            check_record_size(&record)?;
+            let last = pages.len() - 1;
+            match (last != 0).then(|| pages[last].insert(&record)).flatten() {
+                Some(slot) => ids.push(row_id(last as u32, slot)),
+                None => {
+                    let mut page = Page::new();
+                    let slot = page.insert(&record).ok_or_else(broken_heap)?;
+                    ids.push(row_id(pages.len() as u32, slot));
+                    pages.push(page);
+                    fsm.push(0);
+                }
+            }
+        }
+        for (page_no, page) in pages.iter().enumerate().skip(1) {
+            fsm[page_no] = fsm_entry(page);
+        }
+        let header = Self::header_record(pages.len() as u32, &table.columns)?;
+        pages[0].insert(&header);
+
+        let bytes = pages_bytes(&pages);
+        let mut trees = Vec::with_capacity(indexes.len());
+        for index in &indexes {
+            let key_columns = KeyColumns::new(index, &table.columns)?;
+            let mut tree = key_columns.empty();
+            key_columns.build(&mut tree, ids.iter().copied().zip(&table.rows))?;
+            trees.push((self.index_path(&index.name), pages_bytes(&tree)));
+        }
+        let fsm_path = self.fsm_path(name);
+        let pool = self.pool.get_mut();
+        pool.begin()?;
+        let result = pool
+            .write_file(&path, Some(&bytes))
+            .and_then(|_| pool.write_file(&fsm_path, Some(&fsm)))
+            .and_then(|_| {
+                trees
+                    .iter()
+                    .try_for_each(|(path, bytes)| pool.write_file(path, Some(bytes)))
+            });
+        self.fsm.get_mut().insert(name.to_string(), fsm);
+        finish(pool, result)
+    }
+
+    fn delete(&mut self, name: &str) -> Result<(), io::Error> {
+        if !self.exists(name) {
+            return Err(table_not_found());
+        }
+        let path = self.dir.get_path(name);
+        let fsm_path = self.fsm_path(name);
+        self.fsm.get_mut().remove(name);
+        let mut catalog = self.catalog()?;
+        let indexes = catalog.remove_table(name);
+        self.pool.get_mut().begin()?;
+        let result = (|| {
+            let mut pool = self.pool.borrow_mut();
+            pool.write_file(&path, None)?;
+            pool.write_file(&fsm_path, None)?;
+            for index in &indexes {
+                pool.write_file(&self.index_path(&index.name), None)?;
+            }
+            drop(pool);
+            if !indexes.is_empty() {
+                self.write_catalog(&catalog)?;
+            }
+            Ok(())
+        })();
+        finish(self.pool.get_mut(), result)
+    }
+
+    fn scan(&self, name: &str) -> Result<TableScan<'_>, io::Error> {
+        if !self.exists(name) {
+            return Err(table_not_found());
+        }
+        let path = self.dir.get_path(name);
+        let (page_count, columns) = self.header(&path)?;
+        Ok(TableScan {
+            columns,
+            rows: Box::new(HeapScan {
+                storage: self,
+                path,
+                next_page: 1,
+                page_count,
+                rows: VecDeque::new(),
+            }),
+        })
+    }
+
+    fn append(&mut self, name: &str, row: RowValue) -> Result<RowId, io::Error> {
+        if !self.exists(name) {
+            return Err(table_not_found());
+        }
+        let path = self.dir.get_path(name);
+        let indexes = self.catalog()?.of_table(name);
+        let (_, columns) = self.header(&path)?;
+        self.each_index(&indexes, &columns, |key_columns, tree| {
+            key_columns.check_insert(tree, &row, None)
+        })?;
+        let id = self.insert_record(name, &path, &encode_row(&row))?;
+        self.each_index(&indexes, &columns, |key_columns, tree| {
+            key_columns.insert(tree, id, &row)
+        })?;
+        self.flush(name, &path)?;
+        Ok(id)
+    }
+
+    fn remove_rows(&mut self, name: &str, ids: &[RowId]) -> Result<(), io::Error> {
+        if !self.exists(name) {
+            return Err(table_not_found());
+        }
+        let path = self.dir.get_path(name);
+        self.load_fsm(name, &path)?;
+        let indexes = self.catalog()?.of_table(name);
+        if !indexes.is_empty() {
+            let (_, columns) = self.header(&path)?;
+            let rows = self.fetch_rows(name, ids)?;
+            self.each_index(&indexes, &columns, |key_columns, tree| {
+                rows.iter()
+                    .try_for_each(|(id, row)| key_columns.remove(tree, *id, row))
+            })?;
+        }
+        let mut by_page: BTreeMap<u32, Vec<usize>> = BTreeMap::new();
+        for id in ids {
+            let (page_no, slot) = split_row_id(*id);
+            by_page.entry(page_no).or_default().push(slot);
+        }
+        for (page_no, slots) in by_page {
+            let mut pool = self.pool.borrow_mut();
+            let page = pool.page_mut(&path, page_no)?;
+            for slot in slots {
+                page.delete(slot);
+            }
+            self.update_fsm(name, page_no, page);
+        }
+        self.flush(name, &path)
+    }
+
+    fn fetch_rows(&self, name: &str, ids: &[RowId]) -> Result<Vec<(RowId, RowValue)>, io::Error> {
+        if !self.exists(name) {
+            return Err(table_not_found());
+        }
+        let path = self.dir.get_path(name);
+        let (page_count, _) = self.header(&path)?;
+        let mut pool = self.pool.borrow_mut();
+        let mut rows = Vec::with_capacity(ids.len());
+        for id in ids {
+            let (page_no, slot) = split_row_id(*id);
+            if page_no == HEADER_PAGE || page_no >= page_count {
+                continue;
+            }
+            if let Some(record) = pool.page(&path, page_no)?.get(slot) {
+                rows.push((*id, decode_row(record)?));
+            }
+        }
+        Ok(rows)
+    }
+
+    fn supports_indexes(&self) -> bool {
+        true
+    }
+
+    fn create_index(&mut self, index: &IndexInfo) -> Result<(), io::Error> {
+        let mut catalog = self.catalog()?;
+        catalog.add(index.clone())?;
+        let TableScan { columns, rows } = self.scan(&index.table)?;
+        let rows = rows.collect::<Result<Vec<_>, _>>()?;
+        let key_columns = KeyColumns::new(index, &columns)?;
+        key_columns.check_unique(rows.iter().map(|(_, row)| row))?;
+        let mut tree = key_columns.empty();
+        key_columns.build(&mut tree, rows.iter().map(|(id, row)| (*id, row)))?;
+
+        let path = self.index_path(&index.name);
+        self.pool.get_mut().begin()?;
+        let result = self
+            .pool
+            .get_mut()
+            .write_file(&path, Some(&pages_bytes(&tree)))
+            .and_then(|_| self.write_catalog(&catalog));
+        finish(self.pool.get_mut(), result)
+    }
+
+    fn drop_index(&mut self, name: &str) -> Result<(), io::Error> {
+        let mut catalog = self.catalog()?;
+        catalog.remove(name)?;
+        let path = self.index_path(name);
+        self.pool.get_mut().begin()?;
+        let result = self
+            .pool
+            .get_mut()
+            .write_file(&path, None)
+            .and_then(|_| self.write_catalog(&catalog));
+        finish(self.pool.get_mut(), result)
+    }
+
+    fn indexes(&self, table: &str) -> Vec<IndexInfo> {
+        self.catalog()
+            .map(|catalog| catalog.of_table(table))
+            .unwrap_or_default()
+    }
+
+    fn index_lookup(
+        &self,
+        index: &str,
+        lower: Bound<&SqlValue>,
+        upper: Bound<&SqlValue>,
+    ) -> Result<Vec<RowId>, io::Error> {
+        let catalog = self.catalog()?;
+        let index = catalog.get(index).ok_or_else(index_not_found)?;
+        let path = self.index_path(&index.name);
+        let mut pool = self.pool.borrow_mut();
+        let mut pages = FilePages {
+            pool: &mut pool,
+            path: &path,
+        };
+        lookup(index, &mut pages, lower, upper)
+    }
+
+    /// the cached pages may have been changed by the process which held the lock before
+    fn lock(&mut self, names: &[&str], mode: LockMode) -> Result<LockGuard, io::Error> {
+        let guard = self.dir.lock(names, mode)?;
+        self.fsm.get_mut().clear();
+        *self.catalog.get_mut() = None;
+        self.pool.get_mut().invalidate();
+        Ok(guard)
+    }
+
+    fn set_busy_timeout(&mut self, timeout: Duration) {
+        self.dir.busy_timeout = timeout;
+    }
+
+    fn pool_stats(&self) -> PoolStats {
+        self.pool.borrow().stats()
+    }
+
+    fn begin(&mut self) -> Result<(), io::Error> {
+        self.pool.get_mut().begin()
+    }
+
+    fn commit(&mut self) -> Result<(), io::Error> {
+        self.pool.get_mut().commit()
+    }
+
+    fn rollback(&mut self) -> Result<(), io::Error> {
+        // the free space maps are read again from the recovered files
+        self.fsm.get_mut().clear();
+        *self.catalog.get_mut() = None;
+        self.pool.get_mut().rollback()
+    }
+
+    fn replace_rows(
+        &mut self,
+        name: &str,
+        rows: Vec<(RowId, RowValue)>,
+    ) -> Result<Vec<RowId>, io::Error> {
+        if !self.exists(name) {
+            return Err(table_not_found());
+        }
+        let path = self.dir.get_path(name);
+        self.load_fsm(name, &path)?;
+        let indexes = self.catalog()?.of_table(name);
+        let (_, columns) = self.header(&path)?;
+        let mut ids = Vec::with_capacity(rows.len());
+        for (id, row) in rows {
+            let old_rows = match indexes.is_empty() {
+                true => Vec::new(),
+                false => self.fetch_rows(name, &[id])?,
+            };
+            self.each_index(&indexes, &columns, |key_columns, tree| {
+                key_columns.check_insert(tree, &row, Some(id))
+            })?;
+            let record = encode_row(&row);
+            // the old row is kept when the new one could not be written
+            check_record_size(&record)?;
+            let (page_no, slot) = split_row_id(id);
+            let updated = {
+                let mut pool = self.pool.borrow_mut();
+                let page = pool.page_mut(&path, page_no)?;
+                let updated = page.update(slot, &record);
+                if !updated {
+                    // the row moves to another page
+                    page.delete(slot);
+                }
+                self.update_fsm(name, page_no, page);
+                updated
+            };
+            let new_id = match updated {
+                true => id,
+                false => self.insert_record(name, &path, &record)?,
+            };
+            self.each_index(&indexes, &columns, |key_columns, tree| {
+                for (old_id, old_row) in &old_rows {
+                    key_columns.remove(tree, *old_id, old_row)?;
+                }
+                key_columns.insert(tree, new_id, &row)
+            })?;
+            ids.push(new_id);
+        }
+        self.flush(name, &path)?;
+        Ok(ids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::sql_analyzer::types::*;
+    use super::*;
+
+    fn temp_root(name: &str) -> String {
+        let root = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        root.to_string_lossy().to_string()
+    }
+
+    fn columns() -> ColumnInfo {
+        vec![
+            Column {
+                name: "id".into(),
+                type_info: SqlType::Int,
+                constraints: ColumnConstraints::default(),
+            },
+            Column {
+                name: "name".into(),
+                type_info: SqlType::String,
+                constraints: ColumnConstraints::default(),
+            },
+        ]
+    }
+
+    fn row(id: i32) -> RowValue {
+        RowValue {
+            values: vec![SqlValue::Int(id), SqlValue::String(format!("name {id}"))],
+        }
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let root = temp_root("rust_db_test_heap_load");
+        let mut storage = HeapStorage::new(&root);
+        let table = SqlTable {
+            columns: columns(),
+            rows: (0..500).map(row).collect(),
+        };
+        storage.save("people", &table).unwrap();
+        assert_eq!(storage.list_tables().unwrap(), vec![String::from("people")]);
+        assert_eq!(storage.load("people").unwrap(), table);
+
+        // a new storage reads everything from disk
+        let storage = HeapStorage::with_pool_size(&root, 2);
+        assert_eq!(storage.load("people").unwrap(), table);
+    }
+
+    #[test]
+    fn test_single_row_changes_touch_one_page() {
+        let root = temp_root("rust_db_test_heap_pages");
+        let path = format!("{root}/numbers.heap");
+        let mut storage = HeapStorage::with_pool_size(&root, 4);
+        storage.save("numbers", &SqlTable::new(columns())).unwrap();
+        let mut ids = Vec::new();
+        for i in 0..2000 {
+            ids.push(storage.append("numbers", row(i)).unwrap());
+        }
+        assert!(storage.header(&path).unwrap().0 > 10);
+
+        let before = storage.pool_stats();
+        storage.remove_rows("numbers", &[ids[1000]]).unwrap();
+        let after = storage.pool_stats();
+        assert_eq!(after.writes - before.writes, 1);
+        assert!(after.reads - before.reads <= 1);
+
+        let before = storage.pool_stats();
+        let new_ids = storage
+            .replace_rows("numbers", vec![(ids[1500], row(-1))])
+            .unwrap();
+        assert_eq!(new_ids, vec![ids[1500]]);
+        assert_eq!(storage.pool_stats().writes - before.writes, 1);
+
+        let table = storage.load("numbers").unwrap();
+        assert_eq!(table.rows.len(), 1999);
+        assert!(table.rows.contains(&row(-1)));
+        assert!(!table.rows.contains(&row(1000)));
+        assert!(!table.rows.contains(&row(1500)));
+
+        // the freed space is reused by the next insert
+        let id = storage.append("numbers", row(1000)).unwrap();
+        assert_eq!(split_row_id(id).0, split_row_id(ids[1000]).0);
+    }
+
+    #[test]
+    fn test_index_lookup_touches_few_pages() {
+        let root = temp_root("rust_db_test_heap_index");
+        let mut storage = HeapStorage::new(&root);
+        let table = SqlTable {
+            columns: columns(),
+            rows: (0..2000).map(row).collect(),
+        };
+        storage.save("people", &table).unwrap();
+        let index = IndexInfo {
+            name: "people_id".into(),
+            table: "people".into(),
+            columns: vec!["id".into()],
+            unique: true,
+            kind: IndexKind::BTree,
+        };
+        storage.create_index(&index).unwrap();
+        assert_eq!(storage.indexes("people"), vec![index]);
+
+        // a cold lookup only reads the path down the tree and the page of the row
+        let storage = HeapStorage::new(&root);
+        let key = SqlValue::Int(1234);
+        let ids = storage
+            .index_lookup("people_id", Bound::Included(&key), Bound::Included(&key))
+            .unwrap();
+        let rows = storage.fetch_rows("people", &ids).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].1, row(1234));
+        assert!(storage.pool_stats().reads <= 5);
+
+        // the index follows the row changes
+        let mut storage = storage;
+        let err = storage.append("people", row(7)).unwrap_err();
+        assert!(super::super::is_duplicate_key(&err));
+        storage
+            .replace_rows("people", vec![(ids[0], row(5000))])
+            .unwrap();
+        storage.remove_rows("people", &ids).unwrap();
+        let id = storage.append("people", row(4000)).unwrap();
+        let lookup = |storage: &HeapStorage, key: i32| {
+            let key = SqlValue::Int(key);
+            storage
+                .index_lookup("people_id", Bound::Included(&key), Bound::Included(&key))
+                .unwrap()
+        };
+        assert!(lookup(&storage, 1234).is_empty());
+        assert!(lookup(&storage, 5000).is_empty());
+        assert_eq!(lookup(&storage, 4000), vec![id]);
+
+        storage.delete("people").unwrap();
+        assert!(storage.indexes("people").is_empty());
+        assert!(storage.drop_index("people_id").is_err());
+    }
+
+    #[test]
+    fn test_hash_index_lookup() {
+        let root = temp_root("rust_db_test_heap_hash_index");
+        let mut storage = HeapStorage::new(&root);
+        let table = SqlTable {
+            columns: columns(),
+            rows: (0..5000).map(row).collect(),
+        };
+        storage.save("people", &table).unwrap();
+        let index = IndexInfo {
+            name: "people_hash".into(),
+            table: "people".into(),
+            columns: vec!["id".into()],
+            unique: false,
+            kind: IndexKind::Hash,
+        };
+        storage.create_index(&index).unwrap();
+        storage.append("people", row(77)).unwrap();
+
+        // the meta page and the page of the bucket, then the header page and the page of each row
+        let storage = HeapStorage::new(&root);
+        let key = SqlValue::Int(77);
+        let ids = storage
+            .index_lookup("people_hash", Bound::Included(&key), Bound::Included(&key))
+            .unwrap();
+        let rows = storage.fetch_rows("people", &ids).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert!(rows.iter().all(|(_, found)| *found == row(77)));
+        assert!(storage.pool_stats().reads <= 5);
+        assert!(storage
+            .index_lookup("people_hash", Bound::Unbounded, Bound::Included(&key))
+            .is_err());
+    }
+
+    #[test]
+    fn test_row_size_limit() {
+        let root = temp_root("rust_db_test_heap_row_size");
+        let mut storage = HeapStorage::new(&root);
+        let table = SqlTable {
+            columns: columns(),
+            rows: vec![row(0)],
+        };
+        storage.save("people", &table).unwrap();
+        let named = |len: usize| RowValue {
+            values: vec![SqlValue::Int(1), SqlValue::String("x".repeat(len))],
+        };
+        // the largest row fills an empty page
+        let len = MAX_RECORD_SIZE - encode_row(&named(0)).len();
+        let id = storage.append("people", named(len)).unwrap();
+        assert_eq!(
+            storage.fetch_rows("people", &[id]).unwrap(),
+            vec![(id, named(len))]
+        );
+        let err = storage.append("people", named(len + 1)).unwrap_err();
+        assert_eq!(
+            row_too_large(&err),
+            Some((MAX_RECORD_SIZE + 1, MAX_RECORD_SIZE))
+        );
+
+        // a failed update or save keeps the rows as they were
+        let err = storage
+            .replace_rows("people", vec![(id, named(len + 1))])
+            .unwrap_err();
+        assert!(row_too_large(&err).is_some());
+        let large = SqlTable {
+            columns: columns(),
+            rows: vec![named(len + 1)],
+        };
+        assert!(row_too_large(&storage.save("people", &large).unwrap_err()).is_some());
+        let rows = storage.load("people").unwrap().rows;
+        assert_eq!(rows, vec![row(0), named(len)]);
+    }
+
+    #[test]
+    fn test_free_space_map_rebuild() {
+        let root = temp_root("rust_db_test_heap_fsm");
+        let mut storage = HeapStorage::new(&root);
+        let table = SqlTable {
+            columns: columns(),
+            rows: (0..300).map(row).collect(),
+        };
+        storage.save("people", &table).unwrap();
+        fs::remove_file(storage.fsm_path("people")).unwrap();
+
+        let mut storage = HeapStorage::new(&root);
+        storage.append("people", row(300)).unwrap();
+        assert_eq!(storage.load("people").unwrap().rows.len(), 301);
+        storage.delete("people").unwrap();
+        assert!(!storage.exists("people"));
+    }
+
+    #[test]
+    fn test_file_replaced_by_other_process() {
+        let root = temp_root("rust_db_test_heap_replaced");
+        let mut first = HeapStorage::open(&root).unwrap();
+        let table = SqlTable {
+            columns: columns(),
+            rows: vec![row(1)],
+        };
+        first.save("people", &table).unwrap();
+        let lock = first.lock(&["people"], LockMode::Shared).unwrap();
+        assert_eq!(first.load("people").unwrap(), table);
+        drop(lock);
+
+        // the other process writes a new file in place of the one still open in `first`
+        let mut second = HeapStorage::open(&root).unwrap();
+        let lock = second.lock(&["people"], LockMode::Exclusive).unwrap();
+        let table = SqlTable {
+            columns: columns(),
+            rows: vec![row(1), row(2)],
+        };
+        second.save("people", &table).unwrap();
+        drop(lock);
+
+        let lock = first.lock(&["people"], LockMode::Exclusive).unwrap();
+        assert_eq!(first.load("people").unwrap(), table);
+        first.append("people", row(3)).unwrap();
+        drop(lock);
+        let storage = HeapStorage::open(&root).unwrap();
+        assert_eq!(storage.load("people").unwrap().rows.len(), 3);
+    }
+
+    #[test]
+    fn test_recover_unfinished_transaction() {
+        let root = temp_root("rust_db_test_heap_recover");
+        let table = SqlTable {
+            columns: columns(),
+            rows: (0..10).map(row).collect(),
+        };
+        let ids = {
+            let mut storage = HeapStorage::open(&root).unwrap();
+            storage.save("people", &table).unwrap();
+            let ids: Vec<RowId> = storage
+                .scan("people")
+                .unwrap()
+                .rows
+                .map(|row| row.unwrap().0)
+                .collect();
+            storage.begin().unwrap();
+            storage.remove_rows("people", &ids[..5]).unwrap();
+            storage.append("people", row(10)).unwrap();
+            assert_eq!(storage.load("people").unwrap().rows.len(), 6);
+            // the storage goes away before the commit, like a crash
+            ids
+        };
+        let mut storage = HeapStorage::open(&root).unwrap();
+        assert_eq!(storage.load("people").unwrap(), table);
+
+        // a committed transaction stays
+        storage.begin().unwrap();
+        storage.remove_rows("people", &ids[..5]).unwrap();
+        storage.commit().unwrap();
+        let storage = HeapStorage::open(&root).unwrap();
+        assert_eq!(storage.load("people").unwrap().rows.len(), 5);
+    }
+}
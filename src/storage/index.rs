@@ -0,0 +1,376 @@
+use super::super::executor::types::{ColumnInfo, IndexInfo};
+use super::super::sql_analyzer::types::{IndexKind, RowValue, SqlValue};
+use super::btree::{compare_values, BTree, PageStore};
+use super::hash::HashIndex;
+use super::page::Page;
+use super::{indexes_unsupported, RowId, StorageEngine};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+use std::io;
+use std::ops::Bound;
+
+/// A row would give a unique index a key it already has
+#[derive(Debug)]
+struct DuplicateKey(String);
+
+impl fmt::Display for DuplicateKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Duplicate key in unique index {}", self.0)
+    }
+}
+
+impl std::error::Error for DuplicateKey {}
+
+fn duplicate_key(index: &IndexInfo) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        DuplicateKey(index.name.clone()),
+    )
+}
+
+/// check whether the error was caused by a duplicate key in a unique index
+pub fn is_duplicate_key(err: &io::Error) -> bool {
+    duplicate_key_index(err).is_some()
+}
+
+/// name of the unique index which would get a duplicate key
+pub fn duplicate_key_index(err: &io::Error) -> Option<String> {
+    err.get_ref()
+        .and_then(|err| err.downcast_ref::<DuplicateKey>())
+        .map(|DuplicateKey(index)| index.clone())
+}
+
+pub fn index_not_found() -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, "Index not found")
+}
+
+/// ids of the rows whose first indexed column is within the bounds,
+/// a hash index only finds the rows of a single key
+pub fn lookup(
+    index: &IndexInfo,
+    store: &mut dyn PageStore,
+    lower: Bound<&SqlValue>,
+    upper: Bound<&SqlValue>,
+) -> Result<Vec<RowId>, io::Error> {
+    match index.kind {
+        IndexKind::BTree => Ok(BTree::new(store)
+            .range(lower, upper)?
+            .into_iter()
+            .map(|(_, id)| id)
+            .collect()),
+        IndexKind::Hash => match (lower, upper) {
+            (Bound::Included(lower), Bound::Included(upper))
+                if index.columns.len() == 1 && compare_values(lower, upper).is_eq() =>
+            {
+                HashIndex::new(store).get(std::slice::from_ref(lower))
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "A hash index only finds equal keys",
+            )),
+        },
+    }
+}
+
+/// The indexes of all the tables of a storage
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct IndexCatalog {
+    indexes: Vec<IndexInfo>,
+}
+
+impl IndexCatalog {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, io::Error> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, io::Error> {
+        Ok(serde_json::to_vec(self)?)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&IndexInfo> {
+        self.indexes.iter().find(|index| index.name == name)
+    }
+
+    pub fn of_table(&self, table: &str) -> Vec<IndexInfo> {
+        self.indexes
+            .iter()
+            .filter(|index| index.table == table)
+            .cloned()
+            .collect()
+    }
+
+    pub fn add(&mut self, index: IndexInfo) -> Result<(), io::Error> {
+        if self.get(&index.name).is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "Index already exists",
+            ));
+        }
+        self.indexes.push(index);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<IndexInfo, io::Error> {
+        let position = self
+            .indexes
+            .iter()
+            .position(|index| index.name == name)
+            .ok_or_else(index_not_found)?;
+        Ok(self.indexes.remove(position))
+    }
+
+    /// remove the indexes of a dropped table
+    pub fn remove_table(&mut self, table: &str) -> Vec<IndexInfo> {
+        let (removed, kept) = self
+            .indexes
+            .drain(..)
+            .partition(|index| index.table == table);
+        self.indexes = kept;
+        removed
+    }
+}
+
+/// A change of the indexes made by a transaction
+#[derive(Clone, Debug)]
+enum IndexChange {
+    Create(IndexInfo),
+    /// an index of the storage
+    Drop(IndexInfo),
+}
+
+/// Indexes created and dropped by a transaction, which are not in the storage yet.
+/// The rows of the transaction are not in the storage either,
+/// so the indexes are changed when the transaction commits
+#[derive(Clone, Debug, Default)]
+pub struct PendingIndexes {
+    changes: Vec<IndexChange>,
+}
+
+impl PendingIndexes {
+    /// position of the index called `name` created by the transaction
+    fn created(&self, name: &str) -> Option<usize> {
+        self.changes
+            .iter()
+            .position(|change| matches!(change, IndexChange::Create(index) if index.name == name))
+    }
+
+    fn dropped(&self, name: &str) -> bool {
+        self.changes
+            .iter()
+            .any(|change| matches!(change, IndexChange::Drop(index) if index.name == name))
+    }
+
+    /// the index of the storage called `name`, unless the transaction dropped it
+    fn stored(&self, storage: &dyn StorageEngine, name: &str) -> Option<IndexInfo> {
+        if self.dropped(name) {
+            return None;
+        }
+        storage
+            .list_tables()
+            .unwrap_or_default()
+            .iter()
+            .flat_map(|table| storage.indexes(table))
+            .find(|index| index.name == name)
+    }
+
+    /// the indexes of the table as the transaction sees them
+    pub fn of_table(&self, storage: &dyn StorageEngine, table: &str) -> Vec<IndexInfo> {
+        let mut indexes: Vec<IndexInfo> = storage
+            .indexes(table)
+            .into_iter()
+            .filter(|index| !self.dropped(&index.name))
+            .collect();
+        indexes.extend(
+            self.created_indexes()
+                .filter(|index| index.table == table)
+                .cloned(),
+        );
+        indexes
+    }
+
+    /// create the index once the transaction commits, the table has to exist
+    pub fn create_index(
+        &mut self,
+        storage: &dyn StorageEngine,
+        index: &IndexInfo,
+    ) -> Result<(), io::Error> {
+        if !storage.supports_indexes() {
+            return Err(indexes_unsupported());
+        }
+        if self.created(&index.name).is_some() || self.stored(storage, &index.name).is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "Index already exists",
+            ));
+        }
+        self.changes.push(IndexChange::Create(index.clone()));
+        Ok(())
+    }
+
+    /// drop the index once the transaction commits
+    pub fn drop_index(&mut self, storage: &dyn StorageEngine, name: &str) -> Result<(), io::Error> {
+        if let Some(position) = self.created(name) {
+            self.changes.remove(position);
+            return Ok(());
+        }
+        let index = self.stored(storage, name).ok_or_else(index_not_found)?;
+        self.changes.push(IndexChange::Drop(index));
+        Ok(())
+    }
+
+    /// the indexes of a dropped table are dropped with it
+    pub fn drop_table(&mut self, storage: &dyn StorageEngine, table: &str) {
+        self.changes
+            .retain(|change| !matches!(change, IndexChange::Create(index) if index.table == table));
+        for index in storage.indexes(table) {
+            if !self.dropped(&index.name) {
+                self.changes.push(IndexChange::Drop(index));
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// the tables whose indexes change
+    pub fn tables(&self) -> impl Iterator<Item = &str> {
+        self.changes.iter().map(|change| match change {
+            IndexChange::Create(index) | IndexChange::Drop(index) => index.table.as_str(),
+        })
+    }
+
+    /// the indexes of the storage to drop, before the tables are written
+    pub fn dropped_indexes(&self) -> impl Iterator<Item = &IndexInfo> {
+        self.changes.iter().filter_map(|change| match change {
+            IndexChange::Drop(index) => Some(index),
+            IndexChange::Create(_) => None,
+        })
+    }
+
+    /// the indexes to create, after the tables are written
+    pub fn created_indexes(&self) -> impl Iterator<Item = &IndexInfo> {
+        self.changes.iter().filter_map(|change| match change {
+            IndexChange::Create(index) => Some(index),
+            IndexChange::Drop(_) => None,
+        })
+    }
+}
+
+/// Positions of the indexed columns in the rows of a table
+pub struct KeyColumns<'a> {
+    pub index: &'a IndexInfo,
+    positions: Vec<usize>,
+}
+
+impl<'a> KeyColumns<'a> {
+    pub fn new(index: &'a IndexInfo, columns: &ColumnInfo) -> Result<Self, io::Error> {
+        let positions = index
+            .columns
+            .iter()
+            .map(|name| {
+                columns
+                    .iter()
+                    .position(|column| &column.name == name)
+                    .ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("Column {name} does not exist"),
+                        )
+                    })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { index, positions })
+    }
+
+    pub fn key(&self, row: &RowValue) -> Vec<SqlValue> {
+        self.positions
+            .iter()
+            .map(|position| row.values.get(*position).cloned().unwrap_or(SqlValue::Null))
+            .collect()
+    }
+
+    /// fail if the rows give the unique index the same key twice
+    pub fn check_unique<'r>(
+        &self,
+        rows: impl IntoIterator<Item = &'r RowValue>,
+    ) -> Result<(), io::Error> {
+        if !self.index.unique {
+            return Ok(());
+        }
+        let mut keys = HashSet::new();
+        for row in rows {
+            if !keys.insert(self.key(row)) {
+                return Err(duplicate_key(self.index));
+            }
+        }
+        Ok(())
+    }
+
+    /// pages of the index without any row
+    pub fn empty(&self) -> Vec<Page> {
+        match self.index.kind {
+            IndexKind::BTree => BTree::empty(),
+            IndexKind::Hash => HashIndex::empty(),
+        }
+    }
+
+    /// fill an empty index with the rows
+    pub fn build<'r>(
+        &self,
+        store: &mut dyn PageStore,
+        rows: impl IntoIterator<Item = (RowId, &'r RowValue)>,
+    ) -> Result<(), io::Error> {
+        for (id, row) in rows {
+            self.insert(store, id, row)?;
+        }
+        Ok(())
+    }
+
+    /// fail if the row would give the unique index a key it already has for another row
+    pub fn check_insert(
+        &self,
+        store: &mut dyn PageStore,
+        row: &RowValue,
+        replaced: Option<RowId>,
+    ) -> Result<(), io::Error> {
+        if !self.index.unique {
+            return Ok(());
+        }
+        let key = self.key(row);
+        let ids = match self.index.kind {
+            IndexKind::BTree => BTree::new(store).get(&key)?,
+            IndexKind::Hash => HashIndex::new(store).get(&key)?,
+        };
+        if ids.iter().any(|id| Some(*id) != replaced) {
+            return Err(duplicate_key(self.index));
+        }
+        Ok(())
+    }
+
+    pub fn insert(
+        &self,
+        store: &mut dyn PageStore,
+        id: RowId,
+        row: &RowValue,
+    ) -> Result<(), io::Error> {
+        match self.index.kind {
+            IndexKind::BTree => BTree::new(store).insert(self.key(row), id),
+            IndexKind::Hash => HashIndex::new(store).insert(self.key(row), id),
+        }
+    }
+
+    pub fn remove(
+        &self,
+        store: &mut dyn PageStore,
+        id: RowId,
+        row: &RowValue,
+    ) -> Result<(), io::Error> {
+        match self.index.kind {
+            IndexKind::BTree => BTree::new(store).remove(&self.key(row), id)?,
+            IndexKind::Hash => HashIndex::new(store).remove(&self.key(row), id)?,
+        };
+        Ok(())
+    }
+}
@@ -0,0 +1,76 @@
+use super::super::executor::types::SqlTable;
+use super::{LockGuard, LockMode, StorageEngine, TableDir, Wal};
+use std::io::{self, BufReader};
+use std::time::Duration;
+
+/// The persistent data table is in json format.
+///
+/// The whole table (column infos and rows) is serialized with serde, so the
+/// type of every value, including `Null`, survives a round trip
+pub struct JsonStorage {
+    dir: TableDir,
+    wal: Wal,
+}
+
+impl JsonStorage {
+    /// create a storage without write-ahead log
+    pub fn new(path_root: &str) -> Self {
+        Self {
+            dir: TableDir::new(path_root, "json"),
+            wal: Wal::disabled(),
+        }
+    }
+
+    /// open the storage with a write-ahead log in `path_root`, the tables are recovered first
+    pub fn open(path_root: &str) -> Result<Self, io::Error> {
+        Ok(Self {
+            wal: Wal::open(path_root)?,
+            ..Self::new(path_root)
+        })
+    }
+}
+
+impl StorageEngine for JsonStorage {
+    fn exists(&self, name: &str) -> bool {
+        self.dir.exists(name)
+    }
+
+    fn list_tables(&self) -> Result<Vec<String>, io::Error> {
+        self.dir.list_tables()
+    }
+
+    fn load(&self, name: &str) -> Result<SqlTable, io::Error> {
+        let reader = BufReader::new(self.dir.open(name)?);
+        let table = serde_json::from_reader(reader)?;
+        Ok(table)
+    }
+
+    fn save(&mut self, name: &str, table: &SqlTable) -> Result<(), io::Error> {
+        let contents = serde_json::to_vec(table)?;
+        self.dir.write(&mut self.wal, name, &contents)
+    }
+
+    fn delete(&mut self, name: &str) -> Result<(), io::Error> {
+        self.dir.delete(&mut self.wal, name)
+    }
+
+    fn lock(&mut self, names: &[&str], mode: LockMode) -> Result<LockGuard, io::Error> {
+        self.dir.lock(names, mode)
+    }
+
+    fn set_busy_timeout(&mut self, timeout: Duration) {
+        self.dir.busy_timeout = timeout;
+    }
+
+    fn begin(&mut self) -> Result<(), io::Error> {
+        self.wal.begin()
+    }
+
+    fn commit(&mut self) -> Result<(), io::Error> {
+        self.wal.commit()
+    }
+
+    fn rollback(&mut self) -> Result<(), io::Error> {
+        self.wal.rollback()
+    }
+}
@@ -0,0 +1,90 @@
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Name of the lock file of the whole data directory
+pub const DATABASE_LOCK_FILE: &str = "db.lock";
+/// How long to wait for a lock held by another process by default
+pub const DEFAULT_BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+/// pause between two attempts to get a busy lock
+const RETRY_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How a lock is shared with other processes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LockMode {
+    /// several readers could hold the lock together
+    Shared,
+    /// a single writer holds the lock
+    Exclusive,
+}
+
+/// Advisory locks held until the guard is dropped
+#[derive(Debug, Default)]
+pub struct LockGuard {
+    files: Vec<File>,
+}
+
+impl LockGuard {
+    /// keep the locks of `other` too
+    pub fn extend(&mut self, other: LockGuard) {
+        self.files.extend(other.files)
+    }
+}
+
+fn locked() -> io::Error {
+    io::Error::new(io::ErrorKind::TimedOut, "Database is locked")
+}
+
+/// check whether the error was caused by a lock which stayed busy until the timeout
+pub fn is_locked(err: &io::Error) -> bool {
+    err.kind() == io::ErrorKind::TimedOut
+}
+
+/// lock the file at `path`, created if needed, waiting at most `timeout` for other processes
+pub fn lock_file(path: &str, mode: LockMode, timeout: Duration) -> Result<LockGuard, io::Error> {
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)?;
+    let start = Instant::now();
+    loop {
+        let result = match mode {
+            LockMode::Shared => file.try_lock_shared(),
+            LockMode::Exclusive => file.try_lock(),
+        };
+        match result {
+            Ok(()) => return Ok(LockGuard { files: vec![file] }),
+            Err(TryLockError::WouldBlock) if start.elapsed() < timeout => {
+                thread::sleep(RETRY_INTERVAL)
+            }
+            Err(TryLockError::WouldBlock) => return Err(locked()),
+            Err(TryLockError::Error(err)) => return Err(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_modes() {
+        let root = std::env::temp_dir().join("rust_db_test_lock");
+        std::fs::create_dir_all(&root).unwrap();
+        let path = root.join("t.lock").to_string_lossy().to_string();
+        let timeout = Duration::from_millis(50);
+
+        let first = lock_file(&path, LockMode::Shared, timeout).unwrap();
+        let second = lock_file(&path, LockMode::Shared, timeout).unwrap();
+        let err = lock_file(&path, LockMode::Exclusive, timeout).unwrap_err();
+        assert!(is_locked(&err));
+        drop(first);
+        drop(second);
+
+        let _writer = lock_file(&path, LockMode::Exclusive, timeout).unwrap();
+        assert!(lock_file(&path, LockMode::Shared, timeout).is_err());
+    }
+}
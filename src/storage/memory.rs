@@ -0,0 +1,177 @@
+use super::super::executor::types::{IndexInfo, SqlTable};
+use super::super::sql_analyzer::types::{RowValue, SqlValue};
+use super::index::{index_not_found, lookup, IndexCatalog, KeyColumns};
+use super::page::Page;
+use super::{table_not_found, RowId, StorageEngine};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io;
+use std::ops::Bound;
+
+/// Tables are kept in the process without any file I/O,
+/// everything is lost once the storage is dropped
+#[derive(Clone, Debug, Default)]
+pub struct MemoryStorage {
+    tables: HashMap<String, SqlTable>,
+    indexes: IndexCatalog,
+    /// pages of the index trees by index name
+    trees: RefCell<HashMap<String, Vec<Page>>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// fill the trees of the indexes of the table again, row ids are positions in the table
+    fn rebuild_indexes(&mut self, name: &str) -> Result<(), io::Error> {
+        let table = self.tables.get(name).ok_or_else(table_not_found)?;
+        let trees = self.trees.get_mut();
+        for index in self.indexes.of_table(name) {
+            let key_columns = KeyColumns::new(&index, &table.columns)?;
+            let mut pages = key_columns.empty();
+            key_columns.build(
+                &mut pages,
+                table
+                    .rows
+                    .iter()
+                    .enumerate()
+                    .map(|(id, row)| (id as RowId, row)),
+            )?;
+            trees.insert(index.name.clone(), pages);
+        }
+        Ok(())
+    }
+}
+
+impl StorageEngine for MemoryStorage {
+    fn exists(&self, name: &str) -> bool {
+        self.tables.contains_key(name)
+    }
+
+    fn list_tables(&self) -> Result<Vec<String>, io::Error> {
+        let mut names: Vec<String> = self.tables.keys().cloned().collect();
+        names.sort();
+        Ok(names)
+    }
+
+    fn load(&self, name: &str) -> Result<SqlTable, io::Error> {
+        self.tables.get(name).cloned().ok_or_else(table_not_found)
+    }
+
+    fn save(&mut self, name: &str, table: &SqlTable) -> Result<(), io::Error> {
+        for index in self.indexes.of_table(name) {
+            KeyColumns::new(&index, &table.columns)?.check_unique(&table.rows)?;
+        }
+        self.tables.insert(name.to_string(), table.clone());
+        self.rebuild_indexes(name)
+    }
+
+    fn delete(&mut self, name: &str) -> Result<(), io::Error> {
+        self.tables.remove(name).ok_or_else(table_not_found)?;
+        for index in self.indexes.remove_table(name) {
+            self.trees.get_mut().remove(&index.name);
+        }
+        Ok(())
+    }
+
+    fn append(&mut self, name: &str, row: RowValue) -> Result<RowId, io::Error> {
+        let table = self.tables.get_mut(name).ok_or_else(table_not_found)?;
+        let id = table.rows.len() as RowId;
+        let trees = self.trees.get_mut();
+        let indexes = self.indexes.of_table(name);
+        for index in &indexes {
+            let pages = trees.get_mut(&index.name).ok_or_else(index_not_found)?;
+            KeyColumns::new(index, &table.columns)?.check_insert(pages, &row, None)?;
+        }
+        for index in &indexes {
+            let pages = trees.get_mut(&index.name).ok_or_else(index_not_found)?;
+            KeyColumns::new(index, &table.columns)?.insert(pages, id, &row)?;
+        }
+        table.rows.push(row);
+        Ok(id)
+    }
+
+    fn remove_rows(&mut self, name: &str, ids: &[RowId]) -> Result<(), io::Error> {
+        let table = self.tables.get_mut(name).ok_or_else(table_not_found)?;
+        let mut index = 0;
+        table.rows.retain(|_| {
+            index += 1;
+            !ids.contains(&(index as RowId - 1))
+        });
+        // the following rows moved, so their ids changed
+        self.rebuild_indexes(name)
+    }
+
+    fn fetch_rows(&self, name: &str, ids: &[RowId]) -> Result<Vec<(RowId, RowValue)>, io::Error> {
+        let table = self.tables.get(name).ok_or_else(table_not_found)?;
+        Ok(ids
+            .iter()
+            .filter_map(|id| Some((*id, table.rows.get(*id as usize)?.clone())))
+            .collect())
+    }
+
+    fn supports_indexes(&self) -> bool {
+        true
+    }
+
+    fn create_index(&mut self, index: &IndexInfo) -> Result<(), io::Error> {
+        let table = self.tables.get(&index.table).ok_or_else(table_not_found)?;
+        let key_columns = KeyColumns::new(index, &table.columns)?;
+        key_columns.check_unique(&table.rows)?;
+        self.indexes.add(index.clone())?;
+        self.rebuild_indexes(&index.table)
+    }
+
+    fn drop_index(&mut self, name: &str) -> Result<(), io::Error> {
+        self.indexes.remove(name)?;
+        self.trees.get_mut().remove(name);
+        Ok(())
+    }
+
+    fn indexes(&self, table: &str) -> Vec<IndexInfo> {
+        self.indexes.of_table(table)
+    }
+
+    fn index_lookup(
+        &self,
+        index: &str,
+        lower: Bound<&SqlValue>,
+        upper: Bound<&SqlValue>,
+    ) -> Result<Vec<RowId>, io::Error> {
+        let index = self.indexes.get(index).ok_or_else(index_not_found)?;
+        let mut trees = self.trees.borrow_mut();
+        let pages = trees.get_mut(&index.name).ok_or_else(index_not_found)?;
+        lookup(index, pages, lower, upper)
+    }
+
+    fn replace_rows(
+        &mut self,
+        name: &str,
+        rows: Vec<(RowId, RowValue)>,
+    ) -> Result<Vec<RowId>, io::Error> {
+        let table = self.tables.get_mut(name).ok_or_else(table_not_found)?;
+        let indexes = self.indexes.of_table(name);
+        let trees = self.trees.get_mut();
+        let mut ids = Vec::with_capacity(rows.len());
+        for (id, row) in rows {
+            let old_row = match table.rows.get_mut(id as usize) {
+                Some(old_row) => old_row,
+                None => return Err(io::Error::new(io::ErrorKind::NotFound, "Row not found")),
+            };
+            for index in &indexes {
+                let pages = trees.get_mut(&index.name).ok_or_else(index_not_found)?;
+                KeyColumns::new(index, &table.columns)?.check_insert(pages, &row, Some(id))?;
+            }
+            for index in &indexes {
+                let pages = trees.get_mut(&index.name).ok_or_else(index_not_found)?;
+                let key_columns = KeyColumns::new(index, &table.columns)?;
+                key_columns.remove(pages, id, old_row)?;
+                key_columns.insert(pages, id, &row)?;
+            }
+            *old_row = row;
+            ids.push(id);
+        }
+        Ok(ids)
+    }
+}
@@ -1,147 +1,305 @@
+mod btree;
+mod buffer_pool;
+mod csv;
+mod hash;
+mod heap;
+mod index;
+mod json;
+mod lock;
+mod memory;
+mod mvcc;
+mod page;
+mod record;
+mod wal;
+
+pub use self::btree::compare_values;
+pub use self::buffer_pool::PoolStats;
+pub use self::csv::CsvStorage;
+pub use self::heap::{row_too_large, HeapStorage};
+pub use self::index::{duplicate_key_index, is_duplicate_key, PendingIndexes};
+pub use self::json::JsonStorage;
+pub use self::lock::{is_locked, LockGuard, LockMode, DEFAULT_BUSY_TIMEOUT};
+pub use self::memory::MemoryStorage;
+pub use self::mvcc::{is_write_conflict, MvccStorage, TxId};
+pub use self::record::{decode_row, encode_row};
+pub use self::wal::Wal;
+
 use super::executor::types::*;
-use super::sql_analyzer::types::Column;
-use super::sql_analyzer::types::RowValue;
-use super::sql_analyzer::types::SqlType;
-use super::sql_analyzer::types::SqlValue;
-use csv::Writer;
-use csv::{ReaderBuilder, StringRecord};
+use super::sql_analyzer::types::{RowValue, SqlValue};
+use std::collections::HashSet;
 use std::fs;
 use std::fs::File;
 use std::io;
+use std::ops::Bound;
+use std::time::Duration;
 
-pub enum StoreUtil {
-    /// The persistent data table is in csv format
-    Csv(String),
-    /// The persistent data table is in json format
-    Json(String),
+/// Position of a row in its table, given by the storage engine.
+/// It stays valid until the table is saved again or the row is removed
+pub type RowId = u64;
+
+/// Rows of a table produced one by one, without materialising the table
+pub struct TableScan<'a> {
+    /// Column info of the scanned table
+    pub columns: ColumnInfo,
+    pub rows: Box<dyn Iterator<Item = Result<(RowId, RowValue), io::Error>> + 'a>,
 }
 
-//Consider constructing a table with the first row as the column name, the second row recording the data format(string, int),
-//and the third row starting to record the data content
+/// A backend that keeps tables somewhere.
+///
+/// Only `exists`, `list_tables`, `load`, `save` and `delete` are required,
+/// the row level operations fall back to loading and saving the whole table,
+/// in which case the row id is the index of the row in the table.
+pub trait StorageEngine {
+    /// check whether the table exists
+    fn exists(&self, name: &str) -> bool;
+
+    /// names of all the tables in the storage
+    fn list_tables(&self) -> Result<Vec<String>, io::Error>;
 
-impl StoreUtil {
-    // return file's path
-    pub fn get_path(&self, name: &String) -> String {
-        let path_root;
-        match self {
-            StoreUtil::Csv(path) => {
-                path_root = path;
-            }
-            StoreUtil::Json(path) => {
-                path_root = path;
+    /// load table with table name
+    fn load(&self, name: &str) -> Result<SqlTable, io::Error>;
+
+    /// save table persistently, the old content of the table is replaced
+    fn save(&mut self, name: &str, table: &SqlTable) -> Result<(), io::Error>;
+
+    /// delete the table
+    fn delete(&mut self, name: &str) -> Result<(), io::Error>;
+
+    /// iterate over the rows of the table
+    fn scan(&self, name: &str) -> Result<TableScan<'_>, io::Error> {
+        let table = self.load(name)?;
+        Ok(TableScan {
+            columns: table.columns,
+            rows: Box::new(
+                table
+                    .rows
+                    .into_iter()
+                    .enumerate()
+                    .map(|(index, row)| Ok((index as RowId, row))),
+            ),
+        })
+    }
+
+    /// append a row to the table
+    fn append(&mut self, name: &str, row: RowValue) -> Result<RowId, io::Error> {
+        let mut table = self.load(name)?;
+        table.rows.push(row);
+        self.save(name, &table)?;
+        Ok(table.rows.len() as RowId - 1)
+    }
+
+    /// remove the rows with the given ids
+    fn remove_rows(&mut self, name: &str, ids: &[RowId]) -> Result<(), io::Error> {
+        let mut table = self.load(name)?;
+        let ids: HashSet<&RowId> = ids.iter().collect();
+        let mut index: RowId = 0;
+        table.rows.retain(|_| {
+            index += 1;
+            !ids.contains(&(index - 1))
+        });
+        self.save(name, &table)
+    }
+
+    /// the rows with the given ids
+    fn fetch_rows(&self, name: &str, ids: &[RowId]) -> Result<Vec<(RowId, RowValue)>, io::Error> {
+        let ids: HashSet<&RowId> = ids.iter().collect();
+        self.scan(name)?
+            .rows
+            .filter(|row| row.as_ref().map_or(true, |(id, _)| ids.contains(id)))
+            .collect()
+    }
+
+    /// whether the storage keeps indexes, otherwise `create_index` is unsupported
+    fn supports_indexes(&self) -> bool {
+        false
+    }
+
+    /// create an index on columns of a table, it is filled with the rows already in the table
+    fn create_index(&mut self, _index: &IndexInfo) -> Result<(), io::Error> {
+        Err(indexes_unsupported())
+    }
+
+    fn drop_index(&mut self, _name: &str) -> Result<(), io::Error> {
+        Err(indexes_unsupported())
+    }
+
+    /// the indexes kept on the table
+    fn indexes(&self, _table: &str) -> Vec<IndexInfo> {
+        Vec::new()
+    }
+
+    /// ids of the rows whose first indexed column is within the bounds
+    fn index_lookup(
+        &self,
+        _index: &str,
+        _lower: Bound<&SqlValue>,
+        _upper: Bound<&SqlValue>,
+    ) -> Result<Vec<RowId>, io::Error> {
+        Err(indexes_unsupported())
+    }
+
+    /// lock the tables against other processes until the guard is dropped,
+    /// shared to read them or exclusive to change them
+    fn lock(&mut self, _names: &[&str], _mode: LockMode) -> Result<LockGuard, io::Error> {
+        Ok(LockGuard::default())
+    }
+
+    /// how long `lock` waits for the locks held by other processes
+    fn set_busy_timeout(&mut self, _timeout: Duration) {}
+
+    /// page I/O done so far, nothing is counted by the storages without pages
+    fn pool_stats(&self) -> PoolStats {
+        PoolStats::default()
+    }
+
+    /// start a group of changes which are applied atomically,
+    /// nested calls join the group already started
+    fn begin(&mut self) -> Result<(), io::Error> {
+        Ok(())
+    }
+
+    /// make the changes since `begin` durable
+    fn commit(&mut self) -> Result<(), io::Error> {
+        Ok(())
+    }
+
+    /// undo the changes since `begin`
+    fn rollback(&mut self) -> Result<(), io::Error> {
+        Ok(())
+    }
+
+    /// replace the rows with the given ids, return the ids of the new rows
+    fn replace_rows(
+        &mut self,
+        name: &str,
+        rows: Vec<(RowId, RowValue)>,
+    ) -> Result<Vec<RowId>, io::Error> {
+        let mut table = self.load(name)?;
+        let mut ids = Vec::with_capacity(rows.len());
+        for (id, row) in rows {
+            match table.rows.get_mut(id as usize) {
+                Some(old_row) => *old_row = row,
+                None => return Err(io::Error::new(io::ErrorKind::NotFound, "Row not found")),
             }
-        };
-        let path = path_root.to_owned() + "/" + &name;
-        path
+            ids.push(id);
+        }
+        self.save(name, &table)?;
+        Ok(ids)
     }
-    /// check whether the table exists
-    pub fn exists(&self, name: &String) -> bool {
-        let path = self.get_path(name);
-        fs::metadata(path).is_ok()
-    }
-
-    pub fn delete(&self, name: &String) -> Result<(), io::Error> {
-        if self.exists(&name) {
-            let path = self.get_path(&name);
-            fs::remove_file(path.clone())?;
-            Ok(())
-        } else {
-            return Err(io::Error::new(io::ErrorKind::NotFound, "Table not found"));
+}
+
+/// open the storage engine named `kind` ("csv", "json", "heap" or ":memory:") with tables in `path_root`,
+/// the tables are recovered from the write-ahead log first
+pub fn open_storage(kind: &str, path_root: &str) -> Result<Box<dyn StorageEngine>, io::Error> {
+    match kind.to_lowercase().as_str() {
+        "csv" => Ok(Box::new(CsvStorage::open(path_root)?)),
+        "json" => Ok(Box::new(JsonStorage::open(path_root)?)),
+        "heap" => Ok(Box::new(HeapStorage::open(path_root)?)),
+        ":memory:" | "memory" => Ok(Box::new(MemoryStorage::new())),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unknown backend {kind}, expected csv, json, heap or :memory:"),
+        )),
+    }
+}
+
+pub(crate) fn indexes_unsupported() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Indexes are only supported by the heap and memory backends",
+    )
+}
+
+pub(crate) fn table_not_found() -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, "Table not found")
+}
+
+/// Directory where every table is kept in its own file `<name>.<extension>`
+#[derive(Clone, Debug)]
+struct TableDir {
+    path_root: String,
+    extension: &'static str,
+    busy_timeout: Duration,
+}
+
+impl TableDir {
+    fn new(path_root: &str, extension: &'static str) -> Self {
+        Self {
+            path_root: path_root.to_string(),
+            extension,
+            busy_timeout: DEFAULT_BUSY_TIMEOUT,
         }
     }
 
-    /// load table with table name
-    pub fn load(&self, name: String) -> Result<SqlTable, io::Error> {
-        if !self.exists(&name) {
-            return Err(io::Error::new(io::ErrorKind::NotFound, "Table not found"));
-        } else {
-            let path = self.get_path(&name);
-            let file = File::open(path)?;
-            let mut csv_reader = ReaderBuilder::new().has_headers(true).from_reader(file);
-            let head: &StringRecord = csv::Reader::headers(&mut csv_reader)?;
-            let columns_name: Vec<String> = head.iter().map(|col| col.to_string()).collect();
-            let records: Vec<StringRecord> =
-                csv::Reader::records(&mut csv_reader).collect::<Result<_, _>>()?;
-            let columns_type: Vec<String> = records[0].iter().map(|col| col.to_string()).collect();
-            let columns: Vec<Column> = columns_name
-                .iter()
-                .zip(columns_type.iter())
-                .map(|(name, type_name)| Column {
-                    name: name.to_string(),
-                    type_info: match type_name.as_str() {
-                        "String" => SqlType::String,
-                        "Int" => SqlType::Int,
-                        _ => SqlType::Unknown,
-                    },
-                })
-                .collect();
-            let rows: Vec<RowValue> = records[1..]
-                .iter()
-                .map(|record| {
-                    let values: Vec<SqlValue> = record
-                        .iter()
-                        .zip(columns.iter())
-                        .map(|(field, column)| match column.type_info {
-                            SqlType::String => SqlValue::String(field.to_string()),
-                            SqlType::Int => field
-                                .to_string()
-                                .parse::<i32>()
-                                .map_or(SqlValue::Unknown, SqlValue::Int),
-                            _ => SqlValue::Unknown,
-                        })
-                        .collect();
-                    RowValue { values }
-                })
-                .collect();
-
-            Ok(SqlTable { columns, rows })
+    // return file's path
+    fn get_path(&self, name: &str) -> String {
+        format!("{}/{}.{}", self.path_root, name, self.extension)
+    }
+
+    fn exists(&self, name: &str) -> bool {
+        fs::metadata(self.get_path(name)).is_ok()
+    }
+
+    fn list_tables(&self) -> Result<Vec<String>, io::Error> {
+        let mut names = Vec::new();
+        for entry in fs::read_dir(&self.path_root)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == self.extension) {
+                if let Some(stem) = path.file_stem() {
+                    names.push(stem.to_string_lossy().to_string());
+                }
+            }
         }
+        names.sort();
+        Ok(names)
     }
-    /// save table persistently
-    pub fn save(&self, name: String, table: &SqlTable) -> Result<(), io::Error> {
-        let path = self.get_path(&name);
-        if self.exists(&name) {
-            fs::remove_file(path.clone())?
+
+    fn open(&self, name: &str) -> Result<File, io::Error> {
+        if !self.exists(name) {
+            return Err(table_not_found());
         }
-        //get infos from table
-        let columns = &table.columns;
-        let columns_name: Vec<String> = columns.iter().map(|column| column.name.clone()).collect();
-        let columns_type: Vec<String> = columns
-            .iter()
-            .map(|column| match column.type_info {
-                SqlType::String => String::from("String"),
-                SqlType::Int => String::from("Int"),
-                _ => String::from("Unknown"),
-            })
-            .collect();
-
-        //write
-        //Temporarily not considering resource consumption for multiple complete storage
-        let mut writer_csv = Writer::from_path(path)?;
-        writer_csv.write_record(columns_name)?;
-        writer_csv.write_record(columns_type)?;
-        let original_matrix = &table.rows;
-        for row in original_matrix {
-            let row_values: Vec<String> = row
-                .clone()
-                .values
-                .iter()
-                .map(|sql_value| match sql_value {
-                    SqlValue::String(s) => s.clone(),
-                    SqlValue::Int(i) => i.to_string(),
-                    SqlValue::Unknown => String::from("Unknown"),
-                })
-                .collect();
-            writer_csv.write_record(row_values)?;
+        File::open(self.get_path(name))
+    }
+
+    /// Lock the data directory and then the tables, in the order of their names.
+    ///
+    /// The tables are locked in `<name>.lock` since the table files are replaced when saved.
+    /// All the writers share the write-ahead log of the directory, so an exclusive lock
+    /// keeps the other processes out of the whole directory.
+    fn lock(&self, names: &[&str], mode: LockMode) -> Result<LockGuard, io::Error> {
+        fs::create_dir_all(&self.path_root)?;
+        let database = format!("{}/{}", self.path_root, lock::DATABASE_LOCK_FILE);
+        let mut guard = lock::lock_file(&database, mode, self.busy_timeout)?;
+        let mut names = names.to_vec();
+        names.sort();
+        names.dedup();
+        for name in names {
+            let path = format!("{}/{}.lock", self.path_root, name);
+            guard.extend(lock::lock_file(&path, mode, self.busy_timeout)?);
         }
-        writer_csv.flush()?;
-        Ok(())
+        Ok(guard)
+    }
+
+    /// replace the file of the table with `contents` through the log
+    fn write(&self, wal: &mut Wal, name: &str, contents: &[u8]) -> Result<(), io::Error> {
+        wal.write_file(&self.get_path(name), Some(contents))
+    }
+
+    /// remove the file of the table through the log
+    fn delete(&self, wal: &mut Wal, name: &str) -> Result<(), io::Error> {
+        if !self.exists(name) {
+            return Err(table_not_found());
+        }
+        wal.write_file(&self.get_path(name), None)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::super::sql_analyzer::types::*;
     use super::*;
+    use chrono::{NaiveDate, NaiveTime};
+    use rust_decimal::Decimal;
     #[test]
     fn test_save_cvs() {
         // Create a test SqlTable
@@ -150,10 +308,30 @@ mod tests {
                 Column {
                     name: String::from("Name"),
                     type_info: SqlType::String,
+                    constraints: ColumnConstraints {
+                        primary_key: true,
+                        not_null: true,
+                        check: Some(WhereConstraint::Constrait(
+                            String::from("Name"),
+                            CmpOpt::Ne,
+                            SqlValue::String(String::from("")),
+                        )),
+                        ..Default::default()
+                    },
                 },
                 Column {
                     name: String::from("Age"),
                     type_info: SqlType::Int,
+                    constraints: ColumnConstraints {
+                        unique: true,
+                        default: Some(Expr::Value(SqlValue::Int(18))),
+                        references: Some(ForeignKey {
+                            table: String::from("ages"),
+                            column: String::from("age"),
+                            on_delete: OnDelete::SetNull,
+                        }),
+                        ..Default::default()
+                    },
                 },
             ],
             rows: vec![
@@ -163,16 +341,182 @@ mod tests {
                 RowValue {
                     values: vec![SqlValue::String(String::from("Alice")), SqlValue::Int(30)],
                 },
+                // NULL is told apart from the strings looking like it
+                RowValue {
+                    values: vec![SqlValue::String(String::from("\\N")), SqlValue::Null],
+                },
+                RowValue {
+                    values: vec![SqlValue::String(String::from("NULL")), SqlValue::Null],
+                },
             ],
         };
 
         // Create a test Storage object
-        let storage = StoreUtil::Csv(String::from(r"E:\git_commits\rust_db"));
+        let root = std::env::temp_dir().join("rust_db_test_csv");
+        fs::create_dir_all(&root).unwrap();
+        let mut storage = CsvStorage::new(&root.to_string_lossy());
 
         // Call the save function with the test data
-        let result = storage.save("test_table.csv".into(), &test_table);
+        let result = storage.save("test_table", &test_table);
 
         // Assert that the save function returned Ok
         assert!(result.is_ok());
+        assert_eq!(storage.load("test_table").unwrap(), test_table);
+    }
+
+    #[test]
+    fn test_migrate_csv_file_names() {
+        let root = std::env::temp_dir().join("rust_db_test_csv_migrate");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        // a table written by an older version, and a file which is not a table
+        fs::write(root.join("people"), "name,age\nString,Int\nJohn,25\n").unwrap();
+        fs::write(root.join("notes"), "not a table").unwrap();
+
+        let storage = CsvStorage::open(&root.to_string_lossy()).unwrap();
+        assert_eq!(storage.list_tables().unwrap(), vec![String::from("people")]);
+        let table = storage.load("people").unwrap();
+        assert_eq!(
+            table.rows[0].values,
+            vec![SqlValue::String("John".into()), SqlValue::Int(25)]
+        );
+        assert!(!root.join("people").exists());
+        assert!(root.join("notes").exists());
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let test_table = SqlTable {
+            columns: vec![
+                Column {
+                    name: String::from("Name"),
+                    type_info: SqlType::String,
+                    constraints: ColumnConstraints::default(),
+                },
+                Column {
+                    name: String::from("Extra"),
+                    type_info: SqlType::Unknown,
+                    constraints: ColumnConstraints::default(),
+                },
+            ],
+            rows: vec![RowValue {
+                values: vec![SqlValue::String(String::from("John")), SqlValue::Null],
+            }],
+        };
+        let root = std::env::temp_dir().join("rust_db_test_json");
+        fs::create_dir_all(&root).unwrap();
+        let mut storage = JsonStorage::new(&root.to_string_lossy());
+
+        storage.save("people", &test_table).unwrap();
+        assert!(storage
+            .list_tables()
+            .unwrap()
+            .contains(&"people".to_string()));
+        assert_eq!(storage.load("people").unwrap(), test_table);
+        storage.delete("people").unwrap();
+        assert!(!storage.exists("people"));
+    }
+
+    #[test]
+    fn test_value_types_round_trip() {
+        let column = |name: &str, type_info| Column {
+            name: String::from(name),
+            type_info,
+            constraints: ColumnConstraints::default(),
+        };
+        let test_table = SqlTable {
+            columns: vec![
+                column("big", SqlType::BigInt),
+                column("ratio", SqlType::Double),
+                column("flag", SqlType::Boolean),
+                column("day", SqlType::Date),
+                column("at", SqlType::Time),
+                column("created", SqlType::Timestamp),
+                column("price", SqlType::Decimal(20, 4)),
+                column("code", SqlType::Char(3)),
+                column("name", SqlType::Varchar(10)),
+                column("data", SqlType::Blob),
+            ],
+            rows: vec![
+                RowValue {
+                    values: vec![
+                        SqlValue::BigInt(i64::MAX),
+                        SqlValue::Double(0.1),
+                        SqlValue::Boolean(true),
+                        SqlValue::Date(NaiveDate::from_ymd_opt(2024, 2, 29).unwrap()),
+                        SqlValue::Time(NaiveTime::from_hms_milli_opt(23, 59, 59, 250).unwrap()),
+                        SqlValue::Timestamp(
+                            NaiveDate::from_ymd_opt(1969, 12, 31)
+                                .unwrap()
+                                .and_hms_opt(8, 0, 0)
+                                .unwrap(),
+                        ),
+                        SqlValue::Decimal(Decimal::from_i128_with_scale(-12345678901234567890, 4)),
+                        SqlValue::String(String::from("ab ")),
+                        SqlValue::String(String::from("\\N")),
+                        // not valid UTF-8
+                        SqlValue::Blob(vec![0xff, 0x00, b'\\', 0xc3]),
+                    ],
+                },
+                RowValue {
+                    values: vec![
+                        SqlValue::BigInt(-3),
+                        SqlValue::Double(-2.5e-300),
+                        SqlValue::Null,
+                        SqlValue::Date(NaiveDate::from_ymd_opt(-44, 3, 15).unwrap()),
+                        SqlValue::Null,
+                        SqlValue::Null,
+                        SqlValue::Decimal(Decimal::new(1000, 4)),
+                        SqlValue::Null,
+                        SqlValue::String(String::new()),
+                        SqlValue::Blob(Vec::new()),
+                    ],
+                },
+            ],
+        };
+        let root = std::env::temp_dir().join("rust_db_test_types");
+        let _ = fs::remove_dir_all(&root);
+        let dir = |name: &str| {
+            let dir = root.join(name);
+            fs::create_dir_all(&dir).unwrap();
+            dir.to_string_lossy().to_string()
+        };
+        let storages: Vec<Box<dyn StorageEngine>> = vec![
+            Box::new(CsvStorage::new(&dir("csv"))),
+            Box::new(JsonStorage::new(&dir("json"))),
+            Box::new(HeapStorage::new(&dir("heap"))),
+        ];
+        for mut storage in storages {
+            storage.save("metrics", &test_table).unwrap();
+            assert_eq!(storage.load("metrics").unwrap(), test_table);
+        }
+    }
+
+    #[test]
+    fn test_memory_storage() {
+        let mut storage = MemoryStorage::new();
+        let table = SqlTable::new(vec![Column {
+            name: String::from("id"),
+            type_info: SqlType::Int,
+            constraints: ColumnConstraints::default(),
+        }]);
+        storage.save("numbers", &table).unwrap();
+        storage
+            .append(
+                "numbers",
+                RowValue {
+                    values: vec![SqlValue::Int(7)],
+                },
+            )
+            .unwrap();
+
+        assert_eq!(
+            storage.list_tables().unwrap(),
+            vec![String::from("numbers")]
+        );
+        assert_eq!(storage.load("numbers").unwrap().rows.len(), 1);
+        storage.delete("numbers").unwrap();
+        assert!(!storage.exists("numbers"));
+        assert!(storage.load("numbers").is_err());
     }
 }
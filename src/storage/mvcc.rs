@@ -0,0 +1,897 @@
+use super::super::executor::types::{ColumnInfo, IndexInfo, SqlTable};
+use super::super::sql_analyzer::types::{RowValue, SqlValue};
+use super::btree::compare_values;
+use super::index::{index_not_found, PendingIndexes};
+use super::{
+    indexes_unsupported, table_not_found, LockGuard, LockMode, RowId, StorageEngine, TableScan,
+};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::io;
+use std::ops::Bound;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+/// Id of a transaction, ids grow with the start of the transactions
+pub type TxId = u64;
+
+/// creator of the versions loaded from the underlying storage, seen by every transaction
+const BOOTSTRAP_TXID: TxId = 0;
+/// creator of the versions written by a rolled back transaction, seen by no transaction
+const ABORTED_TXID: TxId = TxId::MAX;
+
+/// A row was changed by another transaction since the snapshot of the writer was taken
+#[derive(Debug)]
+struct WriteConflict;
+
+impl fmt::Display for WriteConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Row was changed by a concurrent transaction")
+    }
+}
+
+impl std::error::Error for WriteConflict {}
+
+fn write_conflict() -> io::Error {
+    io::Error::other(WriteConflict)
+}
+
+/// check whether the error was caused by a write-write conflict between transactions
+pub fn is_write_conflict(err: &io::Error) -> bool {
+    err.get_ref().is_some_and(|err| err.is::<WriteConflict>())
+}
+
+/// A version of a row, created by `xmin` and deleted by `xmax`
+#[derive(Clone, Debug)]
+struct RowVersion {
+    xmin: TxId,
+    xmax: Option<TxId>,
+    row: RowValue,
+}
+
+impl RowVersion {
+    /// whether no running or future transaction could see the version
+    fn is_dead(&self) -> bool {
+        self.xmin == ABORTED_TXID || self.xmax.is_some()
+    }
+}
+
+/// A version of a table, a new one is created when the table is created again
+#[derive(Clone, Debug)]
+struct TableVersion {
+    xmin: TxId,
+    xmax: Option<TxId>,
+    columns: ColumnInfo,
+    rows: Vec<RowVersion>,
+}
+
+/// The transactions whose changes could be seen:
+/// the ones committed before the snapshot was taken and the owner of the snapshot
+#[derive(Clone, Debug)]
+struct Snapshot {
+    txid: TxId,
+    /// the first transaction started after the snapshot
+    next: TxId,
+    /// transactions running when the snapshot was taken
+    active: BTreeSet<TxId>,
+}
+
+impl Snapshot {
+    fn sees(&self, txid: TxId) -> bool {
+        txid == self.txid || (txid < self.next && !self.active.contains(&txid))
+    }
+
+    fn visible(&self, xmin: TxId, xmax: Option<TxId>) -> bool {
+        self.sees(xmin) && !xmax.is_some_and(|xmax| self.sees(xmax))
+    }
+
+    /// the last version of the table, if it is visible and nobody else changed it since the snapshot
+    fn table_to_write(&self, versions: &[TableVersion]) -> Result<Option<usize>, io::Error> {
+        let last = match versions.last() {
+            None => return Ok(None),
+            Some(last) => last,
+        };
+        let changed = !self.sees(last.xmin) || last.xmax.is_some_and(|xmax| !self.sees(xmax));
+        if changed {
+            return Err(write_conflict());
+        }
+        if self.visible(last.xmin, last.xmax) {
+            Ok(Some(versions.len() - 1))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// mark a visible version as deleted, it fails if another transaction deleted it first
+    fn delete(&self, xmax: &mut Option<TxId>) -> Result<(), io::Error> {
+        match *xmax {
+            Some(txid) if txid != self.txid => Err(write_conflict()),
+            _ => {
+                *xmax = Some(self.txid);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// State shared by all the sessions of a storage
+struct Shared {
+    inner: Box<dyn StorageEngine + Send>,
+    next_txid: TxId,
+    active: BTreeSet<TxId>,
+    /// versions of the tables used so far, the other tables are only kept by `inner`
+    tables: HashMap<String, Vec<TableVersion>>,
+}
+
+impl Shared {
+    /// versions of the table, loaded from the underlying storage when first used
+    fn versions(&mut self, name: &str) -> Result<&mut Vec<TableVersion>, io::Error> {
+        if !self.tables.contains_key(name) {
+            let mut versions = Vec::new();
+            if self.inner.exists(name) {
+                let table = self.inner.load(name)?;
+                versions.push(TableVersion {
+                    xmin: BOOTSTRAP_TXID,
+                    xmax: None,
+                    columns: table.columns,
+                    rows: table
+                        .rows
+                        .into_iter()
+                        .map(|row| RowVersion {
+                            xmin: BOOTSTRAP_TXID,
+                            xmax: None,
+                            row,
+                        })
+                        .collect(),
+                });
+            }
+            self.tables.insert(name.to_string(), versions);
+        }
+        Ok(self.tables.get_mut(name).unwrap())
+    }
+
+    /// the table as seen by the snapshot
+    fn visible_table(&mut self, name: &str, snapshot: &Snapshot) -> Option<SqlTable> {
+        let versions = self.versions(name).ok()?;
+        let table = versions
+            .iter()
+            .rev()
+            .find(|table| snapshot.visible(table.xmin, table.xmax))?;
+        Some(SqlTable {
+            columns: table.columns.clone(),
+            rows: table
+                .rows
+                .iter()
+                .filter(|row| snapshot.visible(row.xmin, row.xmax))
+                .map(|row| row.row.clone())
+                .collect(),
+        })
+    }
+
+    /// drop the versions no running or future transaction could see.
+    /// The id of a row is its position in the table, so a dead row version is replaced
+    /// by an empty one instead of being removed, which would move the following rows
+    fn vacuum(&mut self, name: &str) {
+        if !self.active.is_empty() {
+            return;
+        }
+        if let Some(versions) = self.tables.get_mut(name) {
+            versions.retain(|table| table.xmin != ABORTED_TXID && table.xmax.is_none());
+            for table in versions.iter_mut() {
+                for row in table.rows.iter_mut().filter(|row| row.is_dead()) {
+                    *row = RowVersion {
+                        xmin: ABORTED_TXID,
+                        xmax: None,
+                        row: RowValue::default(),
+                    };
+                }
+            }
+        }
+    }
+}
+
+/// The transaction of a session
+struct ActiveTx {
+    snapshot: Snapshot,
+    /// nested `begin` calls
+    depth: usize,
+    /// tables written by the transaction
+    written: BTreeSet<String>,
+    /// indexes changed by the transaction, they are changed in the underlying storage on commit
+    indexes: PendingIndexes,
+}
+
+/// Multi-version concurrency control on top of another storage engine.
+///
+/// Every row keeps the id of the transaction which created it and the one which deleted it,
+/// so a transaction reads the snapshot taken when it began while other sessions keep writing.
+/// Deleting or replacing a row which another transaction changed since the snapshot
+/// fails with a write conflict, the first writer wins.
+/// The tables are written to the underlying storage when a transaction commits.
+/// The underlying storage keeps the indexes, but its row ids are not the ids of the versions,
+/// so an index lookup filters the visible rows of the table.
+///
+/// The versions of every table used are kept in memory and a commit saves whole tables,
+/// so it is meant for sessions sharing small tables in one process.
+/// The REPL opens the storage engines directly, its transactions are kept by the session.
+pub struct MvccStorage {
+    shared: Arc<Mutex<Shared>>,
+    tx: Option<ActiveTx>,
+}
+
+impl MvccStorage {
+    pub fn new(inner: Box<dyn StorageEngine + Send>) -> Self {
+        Self {
+            shared: Arc::new(Mutex::new(Shared {
+                inner,
+                next_txid: BOOTSTRAP_TXID + 1,
+                active: BTreeSet::new(),
+                tables: HashMap::new(),
+            })),
+            tx: None,
+        }
+    }
+
+    /// another session on the same tables, with transactions of its own
+    pub fn session(&self) -> Self {
+        Self {
+            shared: Arc::clone(&self.shared),
+            tx: None,
+        }
+    }
+
+    fn shared(&self) -> MutexGuard<'_, Shared> {
+        // the versions are consistent after any panic, the lock is still usable
+        self.shared.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// snapshot of the running transaction, or of the committed data outside a transaction
+    fn snapshot(&self, shared: &Shared) -> Snapshot {
+        match &self.tx {
+            Some(tx) => tx.snapshot.clone(),
+            None => Snapshot {
+                txid: BOOTSTRAP_TXID,
+                next: shared.next_txid,
+                active: shared.active.clone(),
+            },
+        }
+    }
+
+    /// run a change of the table in the running transaction, or in a transaction of its own
+    fn write<T>(
+        &mut self,
+        name: &str,
+        change: impl FnOnce(&mut Vec<TableVersion>, &Snapshot) -> Result<T, io::Error>,
+    ) -> Result<T, io::Error> {
+        self.begin()?;
+        let result = {
+            let tx = self.tx.as_mut().unwrap();
+            tx.written.insert(name.to_string());
+            let mut shared = self.shared.lock().unwrap_or_else(PoisonError::into_inner);
+            shared
+                .versions(name)
+                .and_then(|versions| change(versions, &tx.snapshot))
+        };
+        match result {
+            Ok(value) => {
+                self.commit()?;
+                Ok(value)
+            }
+            Err(err) => {
+                self.rollback()?;
+                Err(err)
+            }
+        }
+    }
+
+    /// change the indexes in the running transaction, or in a transaction of its own
+    fn change_indexes(
+        &mut self,
+        change: impl FnOnce(&mut PendingIndexes, &dyn StorageEngine) -> Result<(), io::Error>,
+    ) -> Result<(), io::Error> {
+        self.begin()?;
+        let result = {
+            let tx = self.tx.as_mut().unwrap();
+            let shared = self.shared.lock().unwrap_or_else(PoisonError::into_inner);
+            change(&mut tx.indexes, shared.inner.as_ref())
+        };
+        match result {
+            Ok(()) => self.commit(),
+            Err(err) => {
+                self.rollback()?;
+                Err(err)
+            }
+        }
+    }
+
+    /// the visible row versions of the table to write
+    fn write_rows<'a>(
+        versions: &'a mut [TableVersion],
+        snapshot: &Snapshot,
+    ) -> Result<&'a mut Vec<RowVersion>, io::Error> {
+        match snapshot.table_to_write(versions)? {
+            Some(index) => Ok(&mut versions[index].rows),
+            None => Err(table_not_found()),
+        }
+    }
+
+    fn delete_row(
+        rows: &mut [RowVersion],
+        snapshot: &Snapshot,
+        id: RowId,
+    ) -> Result<(), io::Error> {
+        match rows.get_mut(id as usize) {
+            Some(row) if snapshot.visible(row.xmin, row.xmax) => snapshot.delete(&mut row.xmax),
+            _ => Err(io::Error::new(io::ErrorKind::NotFound, "Row not found")),
+        }
+    }
+}
+
+impl StorageEngine for MvccStorage {
+    fn exists(&self, name: &str) -> bool {
+        let mut shared = self.shared();
+        let snapshot = self.snapshot(&shared);
+        shared.visible_table(name, &snapshot).is_some()
+    }
+
+    fn list_tables(&self) -> Result<Vec<String>, io::Error> {
+        let mut shared = self.shared();
+        let snapshot = self.snapshot(&shared);
+        let mut names: BTreeSet<String> = shared.inner.list_tables()?.into_iter().collect();
+        names.extend(shared.tables.keys().cloned());
+        Ok(names
+            .into_iter()
+            .filter(|name| shared.visible_table(name, &snapshot).is_some())
+            .collect())
+    }
+
+    fn load(&self, name: &str) -> Result<SqlTable, io::Error> {
+        let mut shared = self.shared();
+        let snapshot = self.snapshot(&shared);
+        shared.versions(name)?;
+        shared
+            .visible_table(name, &snapshot)
+            .ok_or_else(table_not_found)
+    }
+
+    /// keep the versions of the rows which are still in the table,
+    /// so only the rows that really changed could conflict with other transactions
+    fn save(&mut self, name: &str, table: &SqlTable) -> Result<(), io::Error> {
+        self.write(name, |versions, snapshot| {
+            let index = snapshot.table_to_write(versions)?;
+            let index = match index {
+                Some(index) if versions[index].columns == table.columns => index,
+                _ => {
+                    if let Some(index) = index {
+                        snapshot.delete(&mut versions[index].xmax)?;
+                    }
+                    versions.push(TableVersion {
+                        xmin: snapshot.txid,
+                        xmax: None,
+                        columns: table.columns.clone(),
+                        rows: Vec::new(),
+                    });
+                    versions.len() - 1
+                }
+            };
+            let rows = &mut versions[index].rows;
+            let mut new_rows: HashMap<&RowValue, usize> = HashMap::new();
+            for row in &table.rows {
+                *new_rows.entry(row).or_default() += 1;
+            }
+            for version in rows.iter_mut() {
+                if !snapshot.visible(version.xmin, version.xmax) {
+                    continue;
+                }
+                match new_rows.get_mut(&version.row) {
+                    Some(count) if *count > 0 => *count -= 1,
+                    _ => snapshot.delete(&mut version.xmax)?,
+                }
+            }
+            for row in &table.rows {
+                if let Some(count) = new_rows.get_mut(row).filter(|count| **count > 0) {
+                    *count -= 1;
+                    rows.push(RowVersion {
+                        xmin: snapshot.txid,
+                        xmax: None,
+                        row: row.clone(),
+                    });
+                }
+            }
+            Ok(())
+        })
+    }
+
+    fn delete(&mut self, name: &str) -> Result<(), io::Error> {
+        self.begin()?;
+        self.write(name, |versions, snapshot| {
+            match snapshot.table_to_write(versions)? {
+                Some(index) => snapshot.delete(&mut versions[index].xmax),
+                None => Err(table_not_found()),
+            }
+        })?;
+        self.change_indexes(|indexes, inner| {
+            indexes.drop_table(inner, name);
+            Ok(())
+        })?;
+        self.commit()
+    }
+
+    fn scan(&self, name: &str) -> Result<TableScan<'_>, io::Error> {
+        let mut shared = self.shared();
+        let snapshot = self.snapshot(&shared);
+        let versions = shared.versions(name)?;
+        let table = versions
+            .iter()
+            .rev()
+            .find(|table| snapshot.visible(table.xmin, table.xmax))
+            .ok_or_else(table_not_found)?;
+        let rows: Vec<(RowId, RowValue)> = table
+            .rows
+            .iter()
+            .enumerate()
+            .filter(|(_, row)| snapshot.visible(row.xmin, row.xmax))
+            .map(|(index, row)| (index as RowId, row.row.clone()))
+            .collect();
+        Ok(TableScan {
+            columns: table.columns.clone(),
+            rows: Box::new(rows.into_iter().map(Ok)),
+        })
+    }
+
+    fn append(&mut self, name: &str, row: RowValue) -> Result<RowId, io::Error> {
+        self.write(name, |versions, snapshot| {
+            let rows = Self::write_rows(versions, snapshot)?;
+            rows.push(RowVersion {
+                xmin: snapshot.txid,
+                xmax: None,
+                row,
+            });
+            Ok(rows.len() as RowId - 1)
+        })
+    }
+
+    fn remove_rows(&mut self, name: &str, ids: &[RowId]) -> Result<(), io::Error> {
+        self.write(name, |versions, snapshot| {
+            let rows = Self::write_rows(versions, snapshot)?;
+            for id in ids {
+                Self::delete_row(rows, snapshot, *id)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn replace_rows(
+        &mut self,
+        name: &str,
+        rows: Vec<(RowId, RowValue)>,
+    ) -> Result<Vec<RowId>, io::Error> {
+        self.write(name, |versions, snapshot| {
+            let versions = Self::write_rows(versions, snapshot)?;
+            let mut ids = Vec::with_capacity(rows.len());
+            for (id, row) in rows {
+                Self::delete_row(versions, snapshot, id)?;
+                versions.push(RowVersion {
+                    xmin: snapshot.txid,
+                    xmax: None,
+                    row,
+                });
+                ids.push(versions.len() as RowId - 1);
+            }
+            Ok(ids)
+        })
+    }
+
+    fn supports_indexes(&self) -> bool {
+        self.shared().inner.supports_indexes()
+    }
+
+    // checked before the transaction is touched, a failed change rolls it back
+    fn create_index(&mut self, index: &IndexInfo) -> Result<(), io::Error> {
+        if !self.supports_indexes() {
+            return Err(indexes_unsupported());
+        }
+        if !self.exists(&index.table) {
+            return Err(table_not_found());
+        }
+        self.change_indexes(|indexes, inner| indexes.create_index(inner, index))
+    }
+
+    fn drop_index(&mut self, name: &str) -> Result<(), io::Error> {
+        if !self.supports_indexes() {
+            return Err(indexes_unsupported());
+        }
+        self.change_indexes(|indexes, inner| indexes.drop_index(inner, name))
+    }
+
+    fn indexes(&self, table: &str) -> Vec<IndexInfo> {
+        let shared = self.shared();
+        match &self.tx {
+            Some(tx) => tx.indexes.of_table(shared.inner.as_ref(), table),
+            None => shared.inner.indexes(table),
+        }
+    }
+
+    /// the visible rows whose first indexed column is within the bounds
+    fn index_lookup(
+        &self,
+        index: &str,
+        lower: Bound<&SqlValue>,
+        upper: Bound<&SqlValue>,
+    ) -> Result<Vec<RowId>, io::Error> {
+        let index = self
+            .list_tables()?
+            .iter()
+            .flat_map(|table| self.indexes(table))
+            .find(|other| other.name == index)
+            .ok_or_else(index_not_found)?;
+        let scan = self.scan(&index.table)?;
+        let position = scan
+            .columns
+            .iter()
+            .position(|column| Some(&column.name) == index.columns.first())
+            .ok_or_else(index_not_found)?;
+        let within = |value: &SqlValue| {
+            let above = match lower {
+                Bound::Included(lower) => compare_values(value, lower).is_ge(),
+                Bound::Excluded(lower) => compare_values(value, lower).is_gt(),
+                Bound::Unbounded => true,
+            };
+            let below = match upper {
+                Bound::Included(upper) => compare_values(value, upper).is_le(),
+                Bound::Excluded(upper) => compare_values(value, upper).is_lt(),
+                Bound::Unbounded => true,
+            };
+            above && below
+        };
+        let mut ids = Vec::new();
+        for row in scan.rows {
+            let (id, row) = row?;
+            if within(row.values.get(position).unwrap_or(&SqlValue::Null)) {
+                ids.push(id);
+            }
+        }
+        Ok(ids)
+    }
+
+    /// lock the tables in the underlying storage. Other processes may have changed them
+    /// until now, so the versions are read again unless a running transaction could see them
+    fn lock(&mut self, names: &[&str], mode: LockMode) -> Result<LockGuard, io::Error> {
+        let mut shared = self.shared();
+        let guard = shared.inner.lock(names, mode)?;
+        if shared.active.is_empty() {
+            shared.tables.clear();
+        }
+        Ok(guard)
+    }
+
+    fn set_busy_timeout(&mut self, timeout: Duration) {
+        self.shared().inner.set_busy_timeout(timeout)
+    }
+
+    /// start a transaction, its snapshot is taken now
+    fn begin(&mut self) -> Result<(), io::Error> {
+        if let Some(tx) = &mut self.tx {
+            tx.depth += 1;
+            return Ok(());
+        }
+        let mut shared = self.shared();
+        let txid = shared.next_txid;
+        shared.next_txid += 1;
+        let snapshot = Snapshot {
+            txid,
+            next: txid,
+            active: shared.active.clone(),
+        };
+        shared.active.insert(txid);
+        drop(shared);
+        self.tx = Some(ActiveTx {
+            snapshot,
+            depth: 1,
+            written: BTreeSet::new(),
+            indexes: PendingIndexes::default(),
+        });
+        Ok(())
+    }
+
+    /// make the changes visible to the transactions started afterwards
+    /// and write the changed tables to the underlying storage
+    fn commit(&mut self) -> Result<(), io::Error> {
+        match &mut self.tx {
+            None => return Ok(()),
+            Some(tx) if tx.depth > 1 => {
+                tx.depth -= 1;
+                return Ok(());
+            }
+            Some(_) => {}
+        }
+        let tx = self.tx.take().unwrap();
+        let txid = tx.snapshot.txid;
+        let mut shared = self.shared();
+        // the transaction sees everything committed so far and its own changes
+        let mut active = shared.active.clone();
+        active.remove(&txid);
+        let latest = Snapshot {
+            txid,
+            next: shared.next_txid,
+            active,
+        };
+        let tables: Vec<(&String, Option<SqlTable>)> = tx
+            .written
+            .iter()
+            .map(|name| (name, shared.visible_table(name, &latest)))
+            .collect();
+        // the indexes are dropped before the tables are written and created after
+        let inner = &mut shared.inner;
+        let result = inner.begin().and_then(|_| {
+            for index in tx.indexes.dropped_indexes() {
+                inner.drop_index(&index.name)?;
+            }
+            for (name, table) in tables {
+                match table {
+                    Some(table) => inner.save(name, &table)?,
+                    None if inner.exists(name) => inner.delete(name)?,
+                    None => {}
+                }
+            }
+            for index in tx.indexes.created_indexes() {
+                inner.create_index(index)?;
+            }
+            inner.commit()
+        });
+        if let Err(err) = result {
+            let _ = shared.inner.rollback();
+            drop(shared);
+            self.tx = Some(tx);
+            self.rollback()?;
+            return Err(err);
+        }
+        shared.active.remove(&txid);
+        for name in &tx.written {
+            shared.vacuum(name);
+        }
+        Ok(())
+    }
+
+    /// forget the changes of the transaction, even if `begin` was called several times
+    fn rollback(&mut self) -> Result<(), io::Error> {
+        let tx = match self.tx.take() {
+            None => return Ok(()),
+            Some(tx) => tx,
+        };
+        let txid = tx.snapshot.txid;
+        let mut shared = self.shared();
+        for name in &tx.written {
+            if let Some(versions) = shared.tables.get_mut(name) {
+                for table in versions.iter_mut() {
+                    abort_version(&mut table.xmin, &mut table.xmax, txid);
+                    for row in table.rows.iter_mut() {
+                        abort_version(&mut row.xmin, &mut row.xmax, txid);
+                    }
+                }
+            }
+        }
+        shared.active.remove(&txid);
+        for name in &tx.written {
+            shared.vacuum(name);
+        }
+        Ok(())
+    }
+}
+
+/// undo the changes of `txid` to a version,
+/// the versions are not removed as other sessions may refer to them by row id
+fn abort_version(xmin: &mut TxId, xmax: &mut Option<TxId>, txid: TxId) {
+    if *xmin == txid {
+        *xmin = ABORTED_TXID;
+    }
+    if *xmax == Some(txid) {
+        *xmax = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::sql_analyzer::types::*;
+    use super::super::{CsvStorage, MemoryStorage};
+    use super::*;
+    use std::thread;
+
+    fn columns() -> ColumnInfo {
+        vec![Column {
+            name: "id".into(),
+            type_info: SqlType::Int,
+            constraints: ColumnConstraints::default(),
+        }]
+    }
+
+    fn row(id: i32) -> RowValue {
+        RowValue {
+            values: vec![SqlValue::Int(id)],
+        }
+    }
+
+    fn ids(storage: &MvccStorage) -> Vec<RowId> {
+        storage
+            .scan("t")
+            .unwrap()
+            .rows
+            .map(|row| row.unwrap().0)
+            .collect()
+    }
+
+    fn storage() -> MvccStorage {
+        let mut storage = MvccStorage::new(Box::new(MemoryStorage::new()));
+        let table = SqlTable {
+            columns: columns(),
+            rows: (0..3).map(row).collect(),
+        };
+        storage.save("t", &table).unwrap();
+        storage
+    }
+
+    #[test]
+    fn test_snapshot_isolation() {
+        let mut writer = storage();
+        let mut reader = writer.session();
+        reader.begin().unwrap();
+        assert_eq!(reader.load("t").unwrap().rows.len(), 3);
+
+        writer.append("t", row(3)).unwrap();
+        let first = ids(&writer)[0];
+        writer.remove_rows("t", &[first]).unwrap();
+        writer.save("u", &SqlTable::new(columns())).unwrap();
+        // the reader keeps its snapshot
+        assert_eq!(reader.load("t").unwrap().rows, vec![row(0), row(1), row(2)]);
+        assert!(!reader.exists("u"));
+        reader.commit().unwrap();
+        assert_eq!(reader.load("t").unwrap().rows, vec![row(1), row(2), row(3)]);
+        assert!(reader.exists("u"));
+    }
+
+    #[test]
+    fn test_write_conflict() {
+        let mut first = storage();
+        let mut second = first.session();
+        let id = ids(&first)[1];
+        first.begin().unwrap();
+        second.begin().unwrap();
+        first.replace_rows("t", vec![(id, row(10))]).unwrap();
+        // the second writer loses, whether the first one committed or not
+        let err = second.remove_rows("t", &[id]).unwrap_err();
+        assert!(is_write_conflict(&err));
+        assert!(second.tx.is_none());
+        first.commit().unwrap();
+
+        second.begin().unwrap();
+        let ids = ids(&second);
+        first.remove_rows("t", &ids[..1]).unwrap();
+        let err = second.remove_rows("t", &ids[..1]).unwrap_err();
+        assert!(is_write_conflict(&err));
+
+        // inserts never conflict, a whole table save only changes the rows which differ
+        second.begin().unwrap();
+        let mut table = second.load("t").unwrap();
+        first.append("t", row(20)).unwrap();
+        table.rows.push(row(30));
+        second.save("t", &table).unwrap();
+        second.commit().unwrap();
+        let mut rows = first.load("t").unwrap().rows;
+        rows.sort_by_key(|row| format!("{row:?}"));
+        assert_eq!(rows, vec![row(10), row(2), row(20), row(30)]);
+    }
+
+    #[test]
+    fn test_rollback_and_persist() {
+        let mut storage = storage();
+        storage.begin().unwrap();
+        storage.append("t", row(3)).unwrap();
+        storage.delete("t").unwrap();
+        assert!(!storage.exists("t"));
+        storage.rollback().unwrap();
+        assert_eq!(storage.load("t").unwrap().rows.len(), 3);
+
+        storage.append("t", row(3)).unwrap();
+        let shared = storage.shared();
+        assert_eq!(shared.inner.load("t").unwrap().rows.len(), 4);
+        // nothing is running, only the live versions are kept,
+        // the rolled back row is left empty so the ids of the other rows stay the same
+        assert_eq!(shared.tables["t"].len(), 1);
+        let rows = &shared.tables["t"][0].rows;
+        assert_eq!(rows.len(), 5);
+        assert_eq!(rows.iter().filter(|row| !row.is_dead()).count(), 4);
+        assert_eq!(rows[3].row, RowValue::default());
+    }
+
+    #[test]
+    fn test_vacuum_keeps_row_ids() {
+        let mut storage = storage();
+        let before = ids(&storage);
+        // every removal commits and vacuums the table
+        storage.remove_rows("t", &before[..1]).unwrap();
+        storage.remove_rows("t", &before[2..]).unwrap();
+        assert_eq!(storage.load("t").unwrap().rows, vec![row(1)]);
+        assert_eq!(ids(&storage), vec![before[1]]);
+        storage
+            .replace_rows("t", vec![(before[1], row(10))])
+            .unwrap();
+        assert_eq!(storage.load("t").unwrap().rows, vec![row(10)]);
+    }
+
+    #[test]
+    fn test_indexes() {
+        let mut storage = storage();
+        let index = |name: &str| IndexInfo {
+            name: name.into(),
+            table: "t".into(),
+            columns: vec!["id".into()],
+            unique: true,
+            kind: IndexKind::BTree,
+        };
+        storage.begin().unwrap();
+        storage.save("u", &SqlTable::new(columns())).unwrap();
+        storage.create_index(&index("t_id")).unwrap();
+        // the index is created in the underlying storage on commit
+        assert_eq!(storage.indexes("t").len(), 1);
+        assert!(storage.shared().inner.indexes("t").is_empty());
+        storage.commit().unwrap();
+        assert_eq!(storage.shared().inner.indexes("t").len(), 1);
+        assert!(storage.create_index(&index("t_id")).is_err());
+
+        // a lookup gives the ids of the visible rows
+        storage.append("t", row(5)).unwrap();
+        let ids = ids(&storage);
+        let found = storage
+            .index_lookup("t_id", Bound::Included(&SqlValue::Int(1)), Bound::Unbounded)
+            .unwrap();
+        assert_eq!(found, ids[1..].to_vec());
+
+        // the indexes of a table created again in a transaction are dropped with the old table
+        storage.begin().unwrap();
+        storage.delete("t").unwrap();
+        storage.save("t", &SqlTable::new(columns())).unwrap();
+        assert!(storage.indexes("t").is_empty());
+        storage.commit().unwrap();
+        assert!(storage.shared().inner.indexes("t").is_empty());
+        assert!(storage.drop_index("t_id").is_err());
+    }
+
+    #[test]
+    fn test_lock_reads_other_processes() {
+        let root = std::env::temp_dir().join("rust_db_test_mvcc_lock");
+        let _ = std::fs::remove_dir_all(&root);
+        let root = root.to_string_lossy().to_string();
+        let mut storage = MvccStorage::new(Box::new(CsvStorage::open(&root).unwrap()));
+        storage.set_busy_timeout(Duration::from_millis(50));
+        storage.save("t", &SqlTable::new(columns())).unwrap();
+        assert!(storage.load("t").unwrap().rows.is_empty());
+
+        // another process writes the table
+        let mut other = CsvStorage::new(&root);
+        let lock = other.lock(&["t"], LockMode::Exclusive).unwrap();
+        assert!(storage.lock(&["t"], LockMode::Shared).is_err());
+        other.append("t", row(1)).unwrap();
+        drop(lock);
+        let _lock = storage.lock(&["t"], LockMode::Shared).unwrap();
+        assert_eq!(storage.load("t").unwrap().rows, vec![row(1)]);
+    }
+
+    #[test]
+    fn test_concurrent_sessions() {
+        let storage = storage();
+        let handles: Vec<_> = (0..4)
+            .map(|n| {
+                let mut session = storage.session();
+                thread::spawn(move || {
+                    for i in 0..25 {
+                        session.append("t", row(100 * n + i)).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(storage.load("t").unwrap().rows.len(), 103);
+    }
+}
@@ -0,0 +1,281 @@
+use std::io;
+
+/// Size of every page in a heap file
+pub const PAGE_SIZE: usize = 4096;
+/// slot count (u16) + start of the record area (u16)
+const HEADER_SIZE: usize = 4;
+/// offset (u16) + length (u16) of a record
+const SLOT_SIZE: usize = 4;
+/// Largest record which could be kept in an empty page
+pub const MAX_RECORD_SIZE: usize = PAGE_SIZE - HEADER_SIZE - SLOT_SIZE;
+
+/// A slotted page.
+///
+/// The slot directory grows from the start of the page, right after the header,
+/// and the records grow from the end of the page towards the slots.
+/// A deleted record leaves an empty slot (offset 0) which could be reused,
+/// so the slot number of the other records never changes.
+#[derive(Clone, Debug)]
+pub struct Page {
+    data: Box<[u8; PAGE_SIZE]>,
+}
+
+impl Default for Page {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Page {
+    /// an empty page
+    pub fn new() -> Self {
+        let mut page = Self {
+            data: Box::new([0; PAGE_SIZE]),
+        };
+        page.set_records_start(PAGE_SIZE);
+        page
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, io::Error> {
+        if bytes.len() != PAGE_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Page has a wrong size",
+            ));
+        }
+        let mut data = Box::new([0; PAGE_SIZE]);
+        data.copy_from_slice(bytes);
+        let page = Self { data };
+        if page.records_start() < page.slots_end() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Page is broken"));
+        }
+        Ok(page)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data[..]
+    }
+
+    fn read_u16(&self, offset: usize) -> usize {
+        u16::from_le_bytes([self.data[offset], self.data[offset + 1]]) as usize
+    }
+
+    fn write_u16(&mut self, offset: usize, value: usize) {
+        // PAGE_SIZE is 4096, every offset in a page fits in u16
+        self.data[offset..offset + 2].copy_from_slice(&(value as u16).to_le_bytes());
+    }
+
+    /// number of slots, including the empty ones
+    pub fn slot_count(&self) -> usize {
+        self.read_u16(0)
+    }
+
+    fn set_slot_count(&mut self, count: usize) {
+        self.write_u16(0, count)
+    }
+
+    fn records_start(&self) -> usize {
+        match self.read_u16(2) {
+            // an offset of PAGE_SIZE does not fit in u16
+            0 => PAGE_SIZE,
+            start => start,
+        }
+    }
+
+    fn set_records_start(&mut self, start: usize) {
+        self.write_u16(2, if start == PAGE_SIZE { 0 } else { start })
+    }
+
+    fn slots_end(&self) -> usize {
+        HEADER_SIZE + self.slot_count() * SLOT_SIZE
+    }
+
+    /// (offset, length) of the record in `slot`
+    fn slot(&self, slot: usize) -> (usize, usize) {
+        let at = HEADER_SIZE + slot * SLOT_SIZE;
+        (self.read_u16(at), self.read_u16(at + 2))
+    }
+
+    fn set_slot(&mut self, slot: usize, offset: usize, len: usize) {
+        let at = HEADER_SIZE + slot * SLOT_SIZE;
+        self.write_u16(at, offset);
+        self.write_u16(at + 2, len);
+    }
+
+    /// bytes between the slot directory and the records
+    fn contiguous_free(&self) -> usize {
+        self.records_start() - self.slots_end()
+    }
+
+    /// bytes taken by the records
+    fn used_space(&self) -> usize {
+        (0..self.slot_count()).map(|slot| self.slot(slot).1).sum()
+    }
+
+    /// bytes available for a new record once the page is compacted,
+    /// the slot needed by the record is already taken into account
+    pub fn free_space(&self) -> usize {
+        let free = PAGE_SIZE - self.slots_end() - self.used_space();
+        if self.empty_slot().is_some() {
+            free
+        } else {
+            free.saturating_sub(SLOT_SIZE)
+        }
+    }
+
+    fn empty_slot(&self) -> Option<usize> {
+        (0..self.slot_count()).find(|slot| self.slot(*slot).0 == 0)
+    }
+
+    /// record kept in `slot`, None if the slot is empty or does not exist
+    pub fn get(&self, slot: usize) -> Option<&[u8]> {
+        if slot >= self.slot_count() {
+            return None;
+        }
+        match self.slot(slot) {
+            (0, _) => None,
+            (offset, len) => Some(&self.data[offset..offset + len]),
+        }
+    }
+
+    /// all records of the page with their slot number
+    pub fn records(&self) -> impl Iterator<Item = (usize, &[u8])> {
+        (0..self.slot_count()).filter_map(|slot| self.get(slot).map(|record| (slot, record)))
+    }
+
+    /// move all records to the end of the page, so the free space is contiguous
+    fn compact(&mut self) {
+        let mut records: Vec<(usize, Vec<u8>)> = self
+            .records()
+            .map(|(slot, record)| (slot, record.to_vec()))
+            .collect();
+        // keep the records in their original order
+        records.sort_by_key(|(slot, _)| std::cmp::Reverse(self.slot(*slot).0));
+        let mut start = PAGE_SIZE;
+        for (slot, record) in records {
+            start -= record.len();
+            self.data[start..start + record.len()].copy_from_slice(&record);
+            self.set_slot(slot, start, record.len());
+        }
+        self.set_records_start(start);
+    }
+
+    /// put `record` in the page, return its slot or None if there is no space left
+    pub fn insert(&mut self, record: &[u8]) -> Option<usize> {
+        if record.is_empty() || record.len() > self.free_space() {
+            return None;
+        }
+        let slot = match self.empty_slot() {
+            Some(slot) => slot,
+            None => {
+                let slot = self.slot_count();
+                self.set_slot_count(slot + 1);
+                // the new slot takes space, make sure it is not given to a record
+                self.set_slot(slot, 0, 0);
+                slot
+            }
+        };
+        if self.contiguous_free() < record.len() {
+            self.compact();
+        }
+        let start = self.records_start() - record.len();
+        self.data[start..start + record.len()].copy_from_slice(record);
+        self.set_records_start(start);
+        self.set_slot(slot, start, record.len());
+        Some(slot)
+    }
+
+    /// remove the record in `slot`, return false if there is no such record
+    pub fn delete(&mut self, slot: usize) -> bool {
+        if self.get(slot).is_none() {
+            return false;
+        }
+        self.set_slot(slot, 0, 0);
+        // trailing empty slots could be given back
+        while self.slot_count() > 0 && self.slot(self.slot_count() - 1).0 == 0 {
+            self.set_slot_count(self.slot_count() - 1);
+        }
+        true
+    }
+
+    /// replace the record in `slot` and keep its slot number,
+    /// return false if the new record does not fit in the page
+    pub fn update(&mut self, slot: usize, record: &[u8]) -> bool {
+        let (offset, len) = match self.get(slot) {
+            Some(old) => (self.slot(slot).0, old.len()),
+            None => return false,
+        };
+        if record.len() <= len {
+            // shrink in place
+            self.data[offset..offset + record.len()].copy_from_slice(record);
+            self.set_slot(slot, offset, record.len());
+            return true;
+        }
+        let available = PAGE_SIZE - self.slots_end() - (self.used_space() - len);
+        if record.is_empty() || record.len() > available {
+            return false;
+        }
+        // the old record is freed but the slot is kept for the new one
+        self.set_slot(slot, 0, 0);
+        if self.contiguous_free() < record.len() {
+            self.compact();
+        }
+        let start = self.records_start() - record.len();
+        self.data[start..start + record.len()].copy_from_slice(record);
+        self.set_records_start(start);
+        self.set_slot(slot, start, record.len());
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_get_delete() {
+        let mut page = Page::new();
+        let a = page.insert(b"hello").unwrap();
+        let b = page.insert(b"world!").unwrap();
+        assert_eq!(page.get(a), Some(&b"hello"[..]));
+        assert_eq!(page.get(b), Some(&b"world!"[..]));
+
+        assert!(page.delete(a));
+        assert_eq!(page.get(a), None);
+        assert_eq!(page.get(b), Some(&b"world!"[..]));
+        // the empty slot is reused
+        assert_eq!(page.insert(b"again"), Some(a));
+
+        let page = Page::from_bytes(page.as_bytes()).unwrap();
+        assert_eq!(page.records().count(), 2);
+    }
+
+    #[test]
+    fn test_fill_and_compact() {
+        let mut page = Page::new();
+        let record = [7u8; 100];
+        let mut slots = Vec::new();
+        while let Some(slot) = page.insert(&record) {
+            slots.push(slot);
+        }
+        assert_eq!(slots.len(), (PAGE_SIZE - HEADER_SIZE) / (100 + SLOT_SIZE));
+        // free every second record, the space is fragmented
+        for slot in slots.iter().step_by(2) {
+            page.delete(*slot);
+        }
+        let big = [9u8; 300];
+        let slot = page.insert(&big).unwrap();
+        assert_eq!(page.get(slot), Some(&big[..]));
+        assert!(page.update(slots[1], &[1u8; 150]));
+        assert_eq!(page.get(slots[1]), Some(&[1u8; 150][..]));
+        assert_eq!(page.get(slots[3]), Some(&record[..]));
+    }
+
+    #[test]
+    fn test_record_too_large() {
+        let mut page = Page::new();
+        assert!(page.insert(&vec![0u8; MAX_RECORD_SIZE + 1]).is_none());
+        assert!(page.insert(&vec![0u8; MAX_RECORD_SIZE]).is_some());
+        assert_eq!(page.free_space(), 0);
+    }
+}
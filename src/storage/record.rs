@@ -0,0 +1,150 @@
+use super::super::sql_analyzer::types::{RowValue, SqlValue};
+use chrono::{Datelike, NaiveDate, NaiveDateTime, NaiveTime, Timelike};
+use rust_decimal::Decimal;
+use std::io;
+
+// Binary layout of a row: the number of values (u16) followed by every value,
+// a value starts with a tag byte and then its payload in little endian
+const TAG_NULL: u8 = 0;
+const TAG_INT: u8 = 1;
+const TAG_STRING: u8 = 2;
+const TAG_BIGINT: u8 = 3;
+const TAG_DOUBLE: u8 = 4;
+const TAG_BOOLEAN: u8 = 5;
+/// days since 0001-01-01 (i32)
+const TAG_DATE: u8 = 6;
+/// seconds since midnight and nanoseconds (u32, u32)
+const TAG_TIME: u8 = 7;
+/// seconds since 1970-01-01 00:00:00 (i64) and nanoseconds (u32)
+const TAG_TIMESTAMP: u8 = 8;
+/// the 16 bytes of `Decimal::serialize`
+const TAG_DECIMAL: u8 = 9;
+/// the length (u32) and the bytes
+const TAG_BLOB: u8 = 10;
+
+fn broken_record() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "Record is broken")
+}
+
+/// encode a row into bytes
+pub fn encode_row(row: &RowValue) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(&(row.values.len() as u16).to_le_bytes());
+    for value in &row.values {
+        match value {
+            SqlValue::Null => bytes.push(TAG_NULL),
+            SqlValue::Int(i) => {
+                bytes.push(TAG_INT);
+                bytes.extend_from_slice(&i.to_le_bytes());
+            }
+            SqlValue::BigInt(i) => {
+                bytes.push(TAG_BIGINT);
+                bytes.extend_from_slice(&i.to_le_bytes());
+            }
+            SqlValue::Double(d) => {
+                bytes.push(TAG_DOUBLE);
+                bytes.extend_from_slice(&d.to_le_bytes());
+            }
+            SqlValue::Blob(b) => {
+                bytes.push(TAG_BLOB);
+                bytes.extend_from_slice(&(b.len() as u32).to_le_bytes());
+                bytes.extend_from_slice(b);
+            }
+            SqlValue::Decimal(d) => {
+                bytes.push(TAG_DECIMAL);
+                bytes.extend_from_slice(&d.serialize());
+            }
+            SqlValue::Boolean(b) => bytes.extend_from_slice(&[TAG_BOOLEAN, *b as u8]),
+            SqlValue::Date(d) => {
+                bytes.push(TAG_DATE);
+                bytes.extend_from_slice(&d.num_days_from_ce().to_le_bytes());
+            }
+            SqlValue::Time(t) => {
+                bytes.push(TAG_TIME);
+                bytes.extend_from_slice(&t.num_seconds_from_midnight().to_le_bytes());
+                bytes.extend_from_slice(&t.nanosecond().to_le_bytes());
+            }
+            SqlValue::Timestamp(t) => {
+                bytes.push(TAG_TIMESTAMP);
+                bytes.extend_from_slice(&t.timestamp().to_le_bytes());
+                bytes.extend_from_slice(&t.timestamp_subsec_nanos().to_le_bytes());
+            }
+            SqlValue::String(s) => {
+                bytes.push(TAG_STRING);
+                bytes.extend_from_slice(&(s.len() as u32).to_le_bytes());
+                bytes.extend_from_slice(s.as_bytes());
+            }
+        }
+    }
+    bytes
+}
+
+/// Cursor over the bytes of a record
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], io::Error> {
+        if self.bytes.len() < len {
+            return Err(broken_record());
+        }
+        let (head, tail) = self.bytes.split_at(len);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn take_array<const N: usize>(&mut self) -> Result<[u8; N], io::Error> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+}
+
+/// decode a row encoded by `encode_row`
+pub fn decode_row(bytes: &[u8]) -> Result<RowValue, io::Error> {
+    let mut reader = Reader { bytes };
+    let count = u16::from_le_bytes(reader.take_array()?);
+    let mut values = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let value = match reader.take_array::<1>()?[0] {
+            TAG_NULL => SqlValue::Null,
+            TAG_INT => SqlValue::Int(i32::from_le_bytes(reader.take_array()?)),
+            TAG_BIGINT => SqlValue::BigInt(i64::from_le_bytes(reader.take_array()?)),
+            TAG_DOUBLE => SqlValue::Double(f64::from_le_bytes(reader.take_array()?)),
+            TAG_DECIMAL => SqlValue::Decimal(Decimal::deserialize(reader.take_array()?)),
+            TAG_BOOLEAN => SqlValue::Boolean(reader.take_array::<1>()?[0] != 0),
+            TAG_DATE => {
+                NaiveDate::from_num_days_from_ce_opt(i32::from_le_bytes(reader.take_array()?))
+                    .map(SqlValue::Date)
+                    .ok_or_else(broken_record)?
+            }
+            TAG_TIME => {
+                let secs = u32::from_le_bytes(reader.take_array()?);
+                let nanos = u32::from_le_bytes(reader.take_array()?);
+                NaiveTime::from_num_seconds_from_midnight_opt(secs, nanos)
+                    .map(SqlValue::Time)
+                    .ok_or_else(broken_record)?
+            }
+            TAG_TIMESTAMP => {
+                let secs = i64::from_le_bytes(reader.take_array()?);
+                let nanos = u32::from_le_bytes(reader.take_array()?);
+                NaiveDateTime::from_timestamp_opt(secs, nanos)
+                    .map(SqlValue::Timestamp)
+                    .ok_or_else(broken_record)?
+            }
+            TAG_STRING => {
+                let len = u32::from_le_bytes(reader.take_array()?) as usize;
+                let s = std::str::from_utf8(reader.take(len)?).map_err(|_| broken_record())?;
+                SqlValue::String(s.to_string())
+            }
+            TAG_BLOB => {
+                let len = u32::from_le_bytes(reader.take_array()?) as usize;
+                SqlValue::Blob(reader.take(len)?.to_vec())
+            }
+            _ => return Err(broken_record()),
+        };
+        values.push(value);
+    }
+    Ok(RowValue { values })
+}
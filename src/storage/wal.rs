@@ -0,0 +1,821 @@
+use super::lock::{lock_file, LockMode, DATABASE_LOCK_FILE, DEFAULT_BUSY_TIMEOUT};
+use super::page::PAGE_SIZE;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Name of the log file in the data directory
+pub const WAL_FILE: &str = "wal.log";
+
+// Every record of the log is framed as: payload length (u32), checksum of the payload (u32), payload.
+// A record cut by a crash fails the checksum, the log is read up to that record.
+const KIND_BEGIN: u8 = 1;
+const KIND_WRITE: u8 = 2;
+const KIND_COMMIT: u8 = 3;
+const KIND_END: u8 = 4;
+
+/// Which part of a file is written
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Target {
+    /// the whole file is replaced
+    File,
+    /// a single page of the file
+    Page(u32),
+}
+
+/// Image of a file or a page, `None` when the file or page does not exist
+type Image = Option<Vec<u8>>;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum LogRecord {
+    Begin(u64),
+    /// before and after image of a write, used for undo and redo
+    Write {
+        txid: u64,
+        path: String,
+        target: Target,
+        before: Image,
+        after: Image,
+    },
+    /// the changes of the transaction are durable
+    Commit(u64),
+    /// the changes of the transaction are all on disk
+    End(u64),
+}
+
+fn checksum(bytes: &[u8]) -> u32 {
+    // FNV-1a
+    bytes.iter().fold(0x811c9dc5, |hash: u32, byte| {
+        (hash ^ *byte as u32).wrapping_mul(0x01000193)
+    })
+}
+
+fn broken_record() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "Log record is broken")
+}
+
+impl LogRecord {
+    fn txid(&self) -> u64 {
+        match self {
+            LogRecord::Begin(txid)
+            | LogRecord::Commit(txid)
+            | LogRecord::End(txid)
+            | LogRecord::Write { txid, .. } => *txid,
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        fn push_image(bytes: &mut Vec<u8>, image: &Image) {
+            match image {
+                None => bytes.push(0),
+                Some(image) => {
+                    bytes.push(1);
+                    bytes.extend_from_slice(&(image.len() as u32).to_le_bytes());
+                    bytes.extend_from_slice(image);
+                }
+            }
+        }
+        let mut payload = Vec::new();
+        match self {
+            LogRecord::Begin(txid) => {
+                payload.push(KIND_BEGIN);
+                payload.extend_from_slice(&txid.to_le_bytes());
+            }
+            LogRecord::Write {
+                txid,
+                path,
+                target,
+                before,
+                after,
+            } => {
+                payload.push(KIND_WRITE);
+                payload.extend_from_slice(&txid.to_le_bytes());
+                payload.extend_from_slice(&(path.len() as u32).to_le_bytes());
+                payload.extend_from_slice(path.as_bytes());
+                match target {
+                    Target::File => payload.push(0),
+                    Target::Page(page_no) => {
+                        payload.push(1);
+                        payload.extend_from_slice(&page_no.to_le_bytes());
+                    }
+                }
+                push_image(&mut payload, before);
+                push_image(&mut payload, after);
+            }
+            LogRecord::Commit(txid) => {
+                payload.push(KIND_COMMIT);
+                payload.extend_from_slice(&txid.to_le_bytes());
+            }
+            LogRecord::End(txid) => {
+                payload.push(KIND_END);
+                payload.extend_from_slice(&txid.to_le_bytes());
+            }
+        }
+        let mut bytes = Vec::with_capacity(payload.len() + 8);
+        bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&checksum(&payload).to_le_bytes());
+        bytes.extend(payload);
+        bytes
+    }
+
+    fn decode(mut payload: &[u8]) -> Result<Self, io::Error> {
+        fn take<'a>(bytes: &mut &'a [u8], len: usize) -> Result<&'a [u8], io::Error> {
+            if bytes.len() < len {
+                return Err(broken_record());
+            }
+            let (head, tail) = bytes.split_at(len);
+            *bytes = tail;
+            Ok(head)
+        }
+        fn take_u32(bytes: &mut &[u8]) -> Result<u32, io::Error> {
+            Ok(u32::from_le_bytes(take(bytes, 4)?.try_into().unwrap()))
+        }
+        fn take_u64(bytes: &mut &[u8]) -> Result<u64, io::Error> {
+            Ok(u64::from_le_bytes(take(bytes, 8)?.try_into().unwrap()))
+        }
+        fn take_image(bytes: &mut &[u8]) -> Result<Image, io::Error> {
+            match take(bytes, 1)?[0] {
+                0 => Ok(None),
+                _ => {
+                    let len = take_u32(bytes)? as usize;
+                    Ok(Some(take(bytes, len)?.to_vec()))
+                }
+            }
+        }
+        let payload = &mut payload;
+        let kind = take(payload, 1)?[0];
+        let txid = take_u64(payload)?;
+        match kind {
+            KIND_BEGIN => Ok(LogRecord::Begin(txid)),
+            KIND_COMMIT => Ok(LogRecord::Commit(txid)),
+            KIND_END => Ok(LogRecord::End(txid)),
+            KIND_WRITE => {
+                let len = take_u32(payload)? as usize;
+                let path =
+                    String::from_utf8(take(payload, len)?.to_vec()).map_err(|_| broken_record())?;
+                let target = match take(payload, 1)?[0] {
+                    0 => Target::File,
+                    _ => Target::Page(take_u32(payload)?),
+                };
+                Ok(LogRecord::Write {
+                    txid,
+                    path,
+                    target,
+                    before: take_image(payload)?,
+                    after: take_image(payload)?,
+                })
+            }
+            _ => Err(broken_record()),
+        }
+    }
+}
+
+/// read the records of the log, a broken record and everything after it are ignored
+fn read_log(file: &mut File) -> Result<Vec<LogRecord>, io::Error> {
+    let mut bytes = Vec::new();
+    file.seek(SeekFrom::Start(0))?;
+    file.read_to_end(&mut bytes)?;
+    let mut records = Vec::new();
+    let mut rest = &bytes[..];
+    while rest.len() >= 8 {
+        let len = u32::from_le_bytes(rest[0..4].try_into().unwrap()) as usize;
+        let sum = u32::from_le_bytes(rest[4..8].try_into().unwrap());
+        if rest.len() < 8 + len || checksum(&rest[8..8 + len]) != sum {
+            break;
+        }
+        match LogRecord::decode(&rest[8..8 + len]) {
+            Ok(record) => records.push(record),
+            Err(_) => break,
+        }
+        rest = &rest[8 + len..];
+    }
+    Ok(records)
+}
+
+/// current content of the target, None if it does not exist
+fn read_image(path: &str, target: Target) -> Result<Image, io::Error> {
+    match target {
+        Target::File => match fs::read(path) {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        },
+        Target::Page(page_no) => match File::open(path) {
+            Ok(mut file) => read_page(&mut file, page_no),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        },
+    }
+}
+
+fn read_page(file: &mut File, page_no: u32) -> Result<Image, io::Error> {
+    let offset = page_no as u64 * PAGE_SIZE as u64;
+    if file.metadata()?.len() < offset + PAGE_SIZE as u64 {
+        return Ok(None);
+    }
+    let mut bytes = vec![0; PAGE_SIZE];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut bytes)?;
+    Ok(Some(bytes))
+}
+
+fn write_page(file: &mut File, page_no: u32, image: &Image) -> Result<(), io::Error> {
+    let offset = page_no as u64 * PAGE_SIZE as u64;
+    match image {
+        Some(bytes) => {
+            file.seek(SeekFrom::Start(offset))?;
+            file.write_all(bytes)
+        }
+        // the page did not exist
+        None => {
+            if file.metadata()?.len() > offset {
+                file.set_len(offset)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// make the entries of the directory of `path` durable, e.g. after a rename
+fn sync_parent(path: &str) -> Result<(), io::Error> {
+    // a directory could not be opened as a file on windows
+    if cfg!(unix) {
+        let dir = match Path::new(path).parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+/// Replace the file at `path` with the content written by `write`.
+///
+/// The content goes to `<path>.tmp` first, which is synced and then renamed over the old file,
+/// so the file always has either its old or its new content, even if the process dies.
+fn replace_file(
+    path: &str,
+    write: impl FnOnce(&mut File) -> Result<(), io::Error>,
+) -> Result<(), io::Error> {
+    let temp_path = format!("{path}.tmp");
+    let result = File::create(&temp_path).and_then(|mut file| {
+        write(&mut file)?;
+        file.sync_all()
+    });
+    if let Err(err) = result {
+        let _ = fs::remove_file(&temp_path);
+        return Err(err);
+    }
+    fs::rename(&temp_path, path)?;
+    sync_parent(path)
+}
+
+/// put `image` in place of the target, used by recovery and rollback
+fn write_image(path: &str, target: Target, image: &Image) -> Result<(), io::Error> {
+    match (target, image) {
+        (Target::File, Some(bytes)) => replace_file(path, |file| file.write_all(bytes)),
+        (Target::File, None) => match fs::remove_file(path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            Err(_) => Ok(()),
+            Ok(()) => sync_parent(path),
+        },
+        (Target::Page(page_no), _) => {
+            let mut file = match OpenOptions::new()
+                .write(true)
+                .create(image.is_some())
+                .truncate(false)
+                .open(path)
+            {
+                Ok(file) => file,
+                Err(err) if err.kind() == io::ErrorKind::NotFound && image.is_none() => {
+                    return Ok(())
+                }
+                Err(err) => return Err(err),
+            };
+            write_page(&mut file, page_no, image)?;
+            file.sync_all()
+        }
+    }
+}
+
+/// Changes done by the current transaction
+#[derive(Default)]
+struct ActiveTx {
+    /// given by the first write, None while the transaction has written nothing
+    txid: Option<u64>,
+    /// nested `begin` calls
+    depth: usize,
+    /// before images in the order of the writes
+    undo: Vec<(String, Target, Image)>,
+}
+
+/// Write-ahead log of the table files in a directory.
+///
+/// Every write of a file or page is first recorded in the log with the old and new content,
+/// then applied to the file. When the log is opened, the writes of committed transactions
+/// that may not have reached the files are redone and the writes of the unfinished ones are undone.
+/// A disabled log writes the files directly.
+///
+/// The log is shared by the processes using the directory. Files are only written while
+/// the database lock is held exclusively (see `TableDir::lock`), so a transaction gets its id
+/// and its Begin record with its first write, when no other process could be writing the log.
+pub struct Wal {
+    file: Option<File>,
+    next_txid: u64,
+    active: Option<ActiveTx>,
+}
+
+impl Wal {
+    /// a log which does nothing, files are written directly
+    pub fn disabled() -> Self {
+        Self {
+            file: None,
+            next_txid: 1,
+            active: None,
+        }
+    }
+
+    /// open the log in `dir`, the tables in `dir` are recovered first
+    pub fn open(dir: &str) -> Result<Self, io::Error> {
+        fs::create_dir_all(dir)?;
+        // another process may be writing the log
+        let _lock = lock_file(
+            &format!("{dir}/{DATABASE_LOCK_FILE}"),
+            LockMode::Exclusive,
+            DEFAULT_BUSY_TIMEOUT,
+        )?;
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(format!("{dir}/{WAL_FILE}"))?;
+        let next_txid = recover(&mut file)?;
+        Ok(Self {
+            file: Some(file),
+            next_txid,
+            active: None,
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.file.is_some()
+    }
+
+    fn append(&mut self, record: &LogRecord) -> Result<(), io::Error> {
+        if let Some(file) = &mut self.file {
+            file.seek(SeekFrom::End(0))?;
+            file.write_all(&record.encode())?;
+            file.sync_data()?;
+        }
+        Ok(())
+    }
+
+    /// start a transaction, nested calls join the transaction already started.
+    /// Nothing is written to the log until the first write of the transaction
+    pub fn begin(&mut self) -> Result<(), io::Error> {
+        match &mut self.active {
+            Some(active) => active.depth += 1,
+            None => {
+                self.active = Some(ActiveTx {
+                    txid: None,
+                    depth: 1,
+                    undo: Vec::new(),
+                })
+            }
+        }
+        Ok(())
+    }
+
+    /// the id of the current transaction, given on its first write.
+    /// The records left in the log by a process which died are recovered first,
+    /// so the log is empty and the id is not used by any record in it
+    fn txid(&mut self) -> Result<u64, io::Error> {
+        if let Some(txid) = self.active.as_ref().and_then(|active| active.txid) {
+            return Ok(txid);
+        }
+        if let Some(file) = &mut self.file {
+            if file.metadata()?.len() > 0 {
+                self.next_txid = self.next_txid.max(recover(file)?);
+            }
+        }
+        let txid = self.next_txid;
+        self.next_txid += 1;
+        self.append(&LogRecord::Begin(txid))?;
+        if let Some(active) = &mut self.active {
+            active.txid = Some(txid);
+        }
+        Ok(txid)
+    }
+
+    /// empty the log unless a transaction in it has not ended yet
+    fn truncate(&mut self) -> Result<(), io::Error> {
+        if let Some(file) = &mut self.file {
+            let records = read_log(file)?;
+            let live = records.iter().any(|record| match record {
+                LogRecord::Begin(txid) => !records.contains(&LogRecord::End(*txid)),
+                _ => false,
+            });
+            if !live {
+                file.set_len(0)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// finish the transaction, its writes are durable once the outermost `commit` returns
+    pub fn commit(&mut self) -> Result<(), io::Error> {
+        let txid = match &mut self.active {
+            None => return Ok(()),
+            Some(active) if active.depth > 1 => {
+                active.depth -= 1;
+                return Ok(());
+            }
+            Some(active) => active.txid,
+        };
+        let Some(txid) = txid else {
+            // nothing was written
+            self.active = None;
+            return Ok(());
+        };
+        self.append(&LogRecord::Commit(txid))?;
+        let active = self.active.take().unwrap_or_default();
+        let mut paths: Vec<&String> = active.undo.iter().map(|(path, _, _)| path).collect();
+        paths.sort();
+        paths.dedup();
+        for path in paths {
+            if let Ok(file) = File::open(path) {
+                file.sync_all()?;
+            }
+        }
+        self.append(&LogRecord::End(txid))?;
+        self.truncate()
+    }
+
+    /// undo every write of the transaction
+    pub fn rollback(&mut self) -> Result<(), io::Error> {
+        let active = match self.active.take() {
+            None => return Ok(()),
+            Some(active) => active,
+        };
+        let Some(txid) = active.txid else {
+            return Ok(());
+        };
+        for (path, target, before) in active.undo.iter().rev() {
+            write_image(path, *target, before)?;
+        }
+        self.append(&LogRecord::End(txid))?;
+        self.truncate()
+    }
+
+    /// record a write before it is applied, the write joins the current transaction
+    /// or gets one of its own, in which case true is returned
+    fn log_write(
+        &mut self,
+        path: &str,
+        target: Target,
+        before: Image,
+        after: &Image,
+    ) -> Result<bool, io::Error> {
+        let single = self.active.is_none();
+        if single {
+            self.begin()?;
+        }
+        let txid = match self.txid() {
+            Ok(txid) => txid,
+            Err(err) => {
+                if single {
+                    self.rollback()?;
+                }
+                return Err(err);
+            }
+        };
+        let record = LogRecord::Write {
+            txid,
+            path: path.to_string(),
+            target,
+            before: before.clone(),
+            after: after.clone(),
+        };
+        if let Err(err) = self.append(&record) {
+            if single {
+                self.rollback()?;
+            }
+            return Err(err);
+        }
+        if let Some(active) = &mut self.active {
+            active.undo.push((path.to_string(), target, before));
+        }
+        Ok(single)
+    }
+
+    /// commit or roll back the transaction started by `log_write` for a single write
+    fn finish_write(&mut self, single: bool, result: Result<(), io::Error>) -> io::Result<()> {
+        match result {
+            Ok(()) if single => self.commit(),
+            Ok(()) => Ok(()),
+            Err(err) => {
+                if single {
+                    self.rollback()?;
+                }
+                Err(err)
+            }
+        }
+    }
+
+    /// replace the whole file with `contents`, or remove it if `contents` is None
+    pub fn write_file(&mut self, path: &str, contents: Option<&[u8]>) -> Result<(), io::Error> {
+        let after: Image = contents.map(|bytes| bytes.to_vec());
+        if !self.is_enabled() {
+            return write_image(path, Target::File, &after);
+        }
+        let before = read_image(path, Target::File)?;
+        let single = self.log_write(path, Target::File, before, &after)?;
+        let result = write_image(path, Target::File, &after);
+        self.finish_write(single, result)
+    }
+
+    /// write page `page_no` of the opened file at `path`
+    pub fn write_page(
+        &mut self,
+        path: &str,
+        file: &mut File,
+        page_no: u32,
+        bytes: &[u8],
+    ) -> Result<(), io::Error> {
+        let after: Image = Some(bytes.to_vec());
+        if !self.is_enabled() {
+            return write_page(file, page_no, &after);
+        }
+        let before = read_page(file, page_no)?;
+        let single = self.log_write(path, Target::Page(page_no), before, &after)?;
+        let result = write_page(file, page_no, &after);
+        self.finish_write(single, result)
+    }
+}
+
+/// redo the committed transactions and undo the unfinished ones,
+/// return the next transaction id
+fn recover(file: &mut File) -> Result<u64, io::Error> {
+    let records = read_log(file)?;
+    let mut committed: HashMap<u64, bool> = HashMap::new();
+    let mut ended: Vec<u64> = Vec::new();
+    let mut max_txid = 0;
+    for record in &records {
+        match record {
+            LogRecord::Begin(txid) => {
+                committed.insert(*txid, false);
+            }
+            LogRecord::Commit(txid) => {
+                committed.insert(*txid, true);
+            }
+            LogRecord::End(txid) => ended.push(*txid),
+            LogRecord::Write { .. } => {}
+        }
+        max_txid = max_txid.max(record.txid());
+    }
+    let pending = |txid: &u64, is_committed: bool| {
+        !ended.contains(txid) && committed.get(txid) == Some(&is_committed)
+    };
+    // redo in the order of the log
+    for record in &records {
+        if let LogRecord::Write {
+            txid,
+            path,
+            target,
+            after,
+            ..
+        } = record
+        {
+            if pending(txid, true) {
+                write_image(path, *target, after)?;
+            }
+        }
+    }
+    // undo in the reverse order
+    for record in records.iter().rev() {
+        if let LogRecord::Write {
+            txid,
+            path,
+            target,
+            before,
+            ..
+        } = record
+        {
+            if pending(txid, false) {
+                write_image(path, *target, before)?;
+            }
+        }
+    }
+    file.set_len(0)?;
+    file.sync_all()?;
+    Ok(max_txid + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_root(name: &str) -> String {
+        let root = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        root.to_string_lossy().to_string()
+    }
+
+    fn log(root: &str, records: &[LogRecord]) {
+        let mut file = File::create(format!("{root}/{WAL_FILE}")).unwrap();
+        for record in records {
+            file.write_all(&record.encode()).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_record_round_trip() {
+        let record = LogRecord::Write {
+            txid: 3,
+            path: String::from("a/b.heap"),
+            target: Target::Page(7),
+            before: None,
+            after: Some(vec![1, 2, 3]),
+        };
+        let bytes = record.encode();
+        assert_eq!(LogRecord::decode(&bytes[8..]).unwrap(), record);
+    }
+
+    #[test]
+    fn test_interrupted_replace() {
+        let root = temp_root("rust_db_test_wal_replace");
+        let path = format!("{root}/t.csv");
+        fs::write(&path, "old contents").unwrap();
+        // the write dies halfway through the new contents
+        let result = replace_file(&path, |file| {
+            file.write_all(b"new con")?;
+            Err(io::Error::other("killed"))
+        });
+        assert!(result.is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old contents");
+        assert!(!Path::new(&format!("{path}.tmp")).exists());
+
+        Wal::disabled()
+            .write_file(&path, Some(b"new contents"))
+            .unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new contents");
+        assert_eq!(fs::read_dir(&root).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_redo_committed() {
+        let root = temp_root("rust_db_test_wal_redo");
+        let path = format!("{root}/t.csv");
+        fs::write(&path, "old").unwrap();
+        // the process was killed after the commit record but before the file was written
+        log(
+            &root,
+            &[
+                LogRecord::Begin(1),
+                LogRecord::Write {
+                    txid: 1,
+                    path: path.clone(),
+                    target: Target::File,
+                    before: Some(b"old".to_vec()),
+                    after: Some(b"new".to_vec()),
+                },
+                LogRecord::Commit(1),
+            ],
+        );
+        Wal::open(&root).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+    }
+
+    #[test]
+    fn test_undo_unfinished() {
+        let root = temp_root("rust_db_test_wal_undo");
+        let path = format!("{root}/t.csv");
+        let created = format!("{root}/u.csv");
+        fs::write(&path, "half written").unwrap();
+        fs::write(&created, "new table").unwrap();
+        // the process was killed in the middle of the transaction
+        log(
+            &root,
+            &[
+                LogRecord::Begin(1),
+                LogRecord::Write {
+                    txid: 1,
+                    path: path.clone(),
+                    target: Target::File,
+                    before: Some(b"old".to_vec()),
+                    after: Some(b"new".to_vec()),
+                },
+                LogRecord::Write {
+                    txid: 1,
+                    path: created.clone(),
+                    target: Target::File,
+                    before: None,
+                    after: Some(b"new table".to_vec()),
+                },
+            ],
+        );
+        // a torn record at the end of the log
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(format!("{root}/{WAL_FILE}"))
+            .unwrap();
+        file.write_all(&LogRecord::Commit(1).encode()[..5]).unwrap();
+
+        let wal = Wal::open(&root).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old");
+        assert!(fs::metadata(&created).is_err());
+        assert_eq!(wal.next_txid, 2);
+        assert_eq!(fs::metadata(format!("{root}/{WAL_FILE}")).unwrap().len(), 0);
+    }
+
+    #[test]
+    fn test_rollback() {
+        let root = temp_root("rust_db_test_wal_rollback");
+        let path = format!("{root}/t.heap");
+        let mut wal = Wal::open(&root).unwrap();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .unwrap();
+        wal.write_page(&path, &mut file, 0, &[1; PAGE_SIZE])
+            .unwrap();
+
+        wal.begin().unwrap();
+        wal.write_page(&path, &mut file, 0, &[2; PAGE_SIZE])
+            .unwrap();
+        wal.write_page(&path, &mut file, 1, &[3; PAGE_SIZE])
+            .unwrap();
+        wal.rollback().unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), vec![1; PAGE_SIZE]);
+    }
+
+    #[test]
+    fn test_shared_log() {
+        let root = temp_root("rust_db_test_wal_shared");
+        let path = format!("{root}/t.csv");
+        let other_path = format!("{root}/u.csv");
+        fs::write(&path, "old").unwrap();
+        // two processes use the directory, the first one starts a transaction
+        // while the other one writes and commits
+        let mut first = Wal::open(&root).unwrap();
+        let mut other = Wal::open(&root).unwrap();
+        first.begin().unwrap();
+        assert_eq!(fs::metadata(format!("{root}/{WAL_FILE}")).unwrap().len(), 0);
+        other.write_file(&other_path, Some(b"other")).unwrap();
+        first.write_file(&path, Some(b"new")).unwrap();
+
+        // the first process dies before its commit, its write is still undone
+        let mut file = File::open(format!("{root}/{WAL_FILE}")).unwrap();
+        let records = read_log(&mut file).unwrap();
+        assert!(matches!(records[0], LogRecord::Begin(_)));
+        drop(first);
+        Wal::open(&root).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old");
+        assert_eq!(fs::read_to_string(&other_path).unwrap(), "other");
+    }
+
+    #[test]
+    fn test_recover_before_write() {
+        let root = temp_root("rust_db_test_wal_dead_process");
+        let path = format!("{root}/t.csv");
+        let other_path = format!("{root}/u.csv");
+        let mut wal = Wal::open(&root).unwrap();
+        // another process died in the middle of a transaction after the log was opened
+        fs::write(&path, "half written").unwrap();
+        log(
+            &root,
+            &[
+                LogRecord::Begin(5),
+                LogRecord::Write {
+                    txid: 5,
+                    path: path.clone(),
+                    target: Target::File,
+                    before: Some(b"old".to_vec()),
+                    after: Some(b"new".to_vec()),
+                },
+            ],
+        );
+        wal.write_file(&other_path, Some(b"other")).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old");
+        assert_eq!(wal.next_txid, 7);
+        assert_eq!(fs::metadata(format!("{root}/{WAL_FILE}")).unwrap().len(), 0);
+
+        // the log is kept while a transaction in it has not ended
+        wal.begin().unwrap();
+        wal.write_file(&other_path, Some(b"again")).unwrap();
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(format!("{root}/{WAL_FILE}"))
+            .unwrap();
+        file.write_all(&LogRecord::Begin(9).encode()).unwrap();
+        wal.commit().unwrap();
+        assert!(fs::metadata(format!("{root}/{WAL_FILE}")).unwrap().len() > 0);
+    }
+}